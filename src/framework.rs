@@ -1,7 +1,7 @@
 use crate::{
     Args, assets::{shader_asset::{ShaderAsset, ShaderAssetPath}, sound_asset::SoundAsset, texture_asset::TextureAsset}, game::game_main, managers::{
-        self, assets::{AssetManager, ModelAssetId, ShaderAssetId, SoundAssetId, TextureAssetId, get_full_asset_path}, debugger, input::{self, InputManager}, navigation::NavigationManager, networking, physics::{self, BodyColliderType, CollisionGroups, PhysicsManager}, render::{RenderLayer, RenderManager}, saves::SavesManager, sound::set_listener_transform, systems::{self, SystemValue}, ui::{UiManager, set_default_visuals}
-    }, objects::{Transform, character_controller::CharacterController, empty_object::EmptyObject, instanced_model_object::InstancedModelObject, instanced_model_transform_holder::InstancedModelTransformHolder, master_instanced_model_object::MasterInstancedModelObject, model_object::ModelObject, nav_object::{NavObject, NavObjectData}, nav_obstacle::NavObstacle, navmesh::NavigationGround, particle_system::ParticleSystem, ray::Ray, sound_emitter::SoundEmitter, trigger::Trigger}
+        self, assets::{AssetManager, ModelAssetId, ShaderAssetId, SoundAssetId, TextureAssetId, get_full_asset_path}, debugger, events, input::{self, InputManager}, navigation::NavigationManager, networking::{self, auth, NetworkEvent}, physics::{self, BodyColliderType, CollisionGroups, PhysicsManager}, render::{RenderLayer, RenderManager}, saves::{SaveError, SaveInfo, SaveMetadata, SaveMigration, SavesManager, OBJECT_TREES_VALUE_PREFIX}, sound::set_listener_transform, systems::{self, SystemValue}, ui::{UiManager, set_default_visuals}
+    }, objects::{Transform, serialization::{self, ObjectConstructor}, character_controller::CharacterController, empty_object::EmptyObject, instanced_model_object::InstancedModelObject, instanced_model_transform_holder::InstancedModelTransformHolder, master_instanced_model_object::MasterInstancedModelObject, model_object::ModelObject, nav_object::{NavObject, NavObjectData}, nav_obstacle::NavObstacle, navmesh::NavigationGround, particle_system::ParticleSystem, ray::Ray, sound_emitter::SoundEmitter, trigger::Trigger}
 };
 use egui_glium::egui_winit::egui::{self, Color32, CornerRadius, FontData, FontDefinitions, FontFamily, Id, Shadow, Stroke, Window};
 use ez_al::{EzAl, SoundSourceType};
@@ -12,7 +12,7 @@ use glium::{
 use once_cell::sync::Lazy;
 use winit::{event::ElementState, keyboard::PhysicalKey, window::Fullscreen};
 use std::{
-    collections::{BTreeMap, HashMap}, fs, time::{Duration, Instant}
+    collections::{BTreeMap, HashMap}, fs, sync::atomic::{AtomicBool, Ordering}, time::{Duration, Instant}
 };
 use glium::winit::{
     event::{Event, MouseButton, WindowEvent},
@@ -22,6 +22,10 @@ use glium::winit::{
 };
 
 pub static mut FRAMEWORK_POINTER: usize = 0;
+// set by the SIGINT/SIGTERM handler, the server saves and stops after the current tick
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+// how long the stopping server waits for the clients to get the disconnect reason
+const SHUTDOWN_DISCONNECT_TIMEOUT: Duration = Duration::from_secs(3);
 static FONT: Lazy<Vec<u8>> =
     Lazy::new(|| fs::read(get_full_asset_path("fonts/Oswald-VariableFont_wght.ttf")).unwrap());
 static BOLD_FONT: Lazy<Vec<u8>> =
@@ -218,7 +222,9 @@ pub fn start_game_with_render(args: Args, debug_mode: DebugMode) {
         .unwrap();
 }
 
-pub fn start_game_without_render(args: Args) {
+/// Runs the headless server. If autosave_interval is set, the loaded save is saved that often.
+/// SIGINT and SIGTERM disconnect the clients, save the game and return.
+pub fn start_game_without_render(args: Args, tick_rate: u32, autosave_interval: Option<Duration>) {
     println!("starting game without render");
 
     let mut framework = Framework {
@@ -243,14 +249,42 @@ pub fn start_game_without_render(args: Args) {
         FRAMEWORK_POINTER = ptr as usize;
     };
 
+    if let Err(err) = ctrlc::set_handler(|| SHUTDOWN_REQUESTED.store(true, Ordering::SeqCst)) {
+        debugger::error(&format!("Framework error!\nfailed to set the shutdown signal handler\nerr: {}", err));
+    }
+
     game_main::start(args, &mut framework);
 
-    game_loop::game_loop((), 60, 0.1, |game_loop| {
+    let mut last_autosave_time = Instant::now();
+    let mut shutdown_started_time: Option<Instant> = None;
+    game_loop::game_loop((), tick_rate, 0.1, |game_loop| {
         unsafe {
             let ptr = &mut framework as *mut Framework;
             FRAMEWORK_POINTER = ptr as usize;
         };
         update_game(&mut framework, Duration::from_secs_f64(game_loop.accumulated_time()));
+
+        // the game keeps updating while the clients are disconnected, so the systems can handle their disconnects
+        if let Some(shutdown_started_time) = shutdown_started_time {
+            if !networking::is_disconnecting_clients() || shutdown_started_time.elapsed() > SHUTDOWN_DISCONNECT_TIMEOUT {
+                framework.save_if_loaded();
+                game_loop.exit();
+            }
+            return;
+        }
+
+        if SHUTDOWN_REQUESTED.load(Ordering::SeqCst) {
+            networking::disconnect_all_clients("the server is shutting down");
+            shutdown_started_time = Some(Instant::now());
+            return;
+        }
+
+        if let Some(autosave_interval) = autosave_interval {
+            if last_autosave_time.elapsed() >= autosave_interval {
+                last_autosave_time = Instant::now();
+                framework.save_if_loaded();
+            }
+        }
     }, |_| { });
 }
 
@@ -266,13 +300,28 @@ fn update_game(framework: &mut Framework, delta_time: Duration) {
     
     let networking_update_time = Instant::now();
     networking::update(delta_time);
+    load_connected_players(framework);
     let networking_update_time = networking_update_time.elapsed();
 
     game_main::update(framework);
 
     let systems_update_time = Instant::now();
+    serialization::update(framework);
+    systems::dispatch_events(framework);
+    for (group, key) in framework.saves.update_regions() {
+        systems::region_loaded(framework, &group, &key);
+    }
     systems::update(framework);
     let systems_update_time = systems_update_time.elapsed();
+    // after the systems, so they can still set the values of the disconnected players
+    unload_disconnected_players(framework);
+
+    let replication_update_time = Instant::now();
+    networking::spawn::update(framework);
+    networking::prediction::update(framework);
+    networking::rpc::update(framework);
+    networking::replication::update(framework);
+    let networking_update_time = networking_update_time + replication_update_time.elapsed();
 
     let total_update_time = total_update_time.elapsed();
     framework.last_frame_systems_update_time
@@ -285,6 +334,42 @@ fn update_game(framework: &mut Framework, delta_time: Duration) {
         .insert(String::from("Total Game Update Time"), total_update_time);
 }
 
+fn load_connected_players(framework: &mut Framework) {
+    for event in networking::get_network_events() {
+        if let NetworkEvent::ClientConnected(client_id) = event {
+            let player_id = match client_id.parse().ok().and_then(auth::get_player_id) {
+                Some(player_id) => player_id,
+                None => continue,
+            };
+            // another connection of the same player already has it's data, reloading it would drop the unsaved values
+            if framework.saves.is_player_loaded(&player_id) {
+                continue;
+            }
+            if let Err(err) = framework.saves.load_player(&player_id) {
+                debugger::error(&format!("Framework error!\nfailed to load the data of player '{}'\nerr: {}", player_id, err));
+            }
+        }
+    }
+}
+
+fn unload_disconnected_players(framework: &mut Framework) {
+    for event in networking::get_network_events() {
+        if let NetworkEvent::ClientDisconnected(client_id, _) = event {
+            let player_id = match client_id.parse().ok().and_then(auth::get_player_id) {
+                Some(player_id) => player_id,
+                None => continue,
+            };
+            // the data is unloaded only with the last connection of the player
+            if auth::is_player_connected(&player_id) {
+                continue;
+            }
+            if let Err(err) = framework.saves.unload_player(&player_id) {
+                debugger::error(&format!("Framework error!\nfailed to save the data of player '{}'\nerr: {}", player_id, err));
+            }
+        }
+    }
+}
+
 fn get_fps(now: &Instant, frames: &usize) -> Option<usize> {
     let one_second = std::time::Duration::new(1, 0);
 
@@ -448,9 +533,15 @@ impl Framework {
     pub fn load_save(&mut self, save_name: &str) -> Result<(), ()> {
         match self.saves.load_save(save_name) {
             Ok(save_values) => {
+                let mut object_trees = HashMap::new();
                 for (key, value) in save_values {
-                    self.set_global_system_value(&key, value);
+                    if key.starts_with(OBJECT_TREES_VALUE_PREFIX) {
+                        object_trees.insert(key, value);
+                    } else {
+                        self.set_global_system_value(&key, value);
+                    }
                 }
+                serialization::restore_persistent_objects(self, object_trees);
             }
             Err(_) => {
                 debugger::error("Framework error!\nFailed to load save.");
@@ -462,7 +553,29 @@ impl Framework {
     }
 
     pub fn register_save_value(&mut self, system_value_name: &str) {
-        self.saves.register_save_value(system_value_name)
+        self.saves.register_save_value(system_value_name);
+        self.set_migrated_save_value(system_value_name);
+    }
+
+    pub fn register_save_value_with_version(&mut self, system_value_name: &str, version: u32) {
+        self.saves.register_save_value_with_version(system_value_name, version);
+        self.set_migrated_save_value(system_value_name);
+    }
+
+    pub fn register_save_migration(&mut self, system_value_name: &str, migration: SaveMigration) {
+        self.saves.register_save_migration(system_value_name, migration);
+        self.set_migrated_save_value(system_value_name);
+    }
+
+    pub fn unregister_save_migration(&mut self, system_value_name: &str) {
+        self.saves.unregister_save_migration(system_value_name)
+    }
+
+    // the save is usually loaded before the systems register their values, so they're migrated here
+    fn set_migrated_save_value(&mut self, system_value_name: &str) {
+        if let Some(value) = self.saves.take_migrated_value(system_value_name) {
+            self.set_global_system_value(system_value_name, value);
+        }
     }
 
     pub fn unregister_save_value(&mut self, system_value_name: &str) {
@@ -470,11 +583,99 @@ impl Framework {
     }
 
     pub fn new_save(&mut self, save_name: &str) -> Result<(), std::io::Error> {
-        self.saves.new_save(save_name, &self.system_globals)
+        let mut save_values = self.system_globals.clone();
+        save_values.extend(serialization::serialize_persistent_objects());
+        self.saves.new_save(save_name, &save_values)
+    }
+
+    pub fn save_game(&mut self) -> Result<(), SaveError> {
+        systems::before_save(self);
+        let mut save_values = self.system_globals.clone();
+        save_values.extend(serialization::serialize_persistent_objects());
+        self.saves.save_game(&save_values)?;
+
+        if let Some(save_name) = self.saves.current_save_name() {
+            events::emit(None, events::SAVE_COMPLETED, vec![SystemValue::String(save_name.into())]);
+        }
+        Ok(())
+    }
+
+    // the server's autosave and shutdown don't fail when nothing was loaded
+    fn save_if_loaded(&mut self) {
+        if self.saves.current_save_name().is_none() {
+            return;
+        }
+
+        if let Err(err) = self.save_game() {
+            debugger::error(&format!("Framework error!\nfailed to save the game\nerr: {}", err));
+        }
+    }
+
+    /// Server: the stable identity of the connected client that keys it's saved data.
+    pub fn get_player_id(&self, client_id: u64) -> Option<String> {
+        auth::get_player_id(client_id)
+    }
+
+    /// Server: sets the value in the saved data of the connected player.
+    /// It's written when the player disconnects and on save_game.
+    pub fn set_player_value(&mut self, client_id: u64, value_name: &str, value: Vec<SystemValue>) -> Result<(), ()> {
+        let player_id = match auth::get_player_id(client_id) {
+            Some(player_id) => player_id,
+            None => {
+                debugger::error(&format!("Framework error!\nset_player_value failed, client {} is not connected", client_id));
+                return Err(());
+            },
+        };
+
+        match self.saves.set_player_value(&player_id, value_name, value) {
+            true => Ok(()),
+            false => {
+                debugger::error(&format!("Framework error!\nset_player_value failed, player '{}' is not loaded", player_id));
+                Err(())
+            },
+        }
+    }
+
+    pub fn get_player_value(&self, client_id: u64, value_name: &str) -> Option<Vec<SystemValue>> {
+        let player_id = auth::get_player_id(client_id)?;
+        self.saves.get_player_value(&player_id, value_name)
+    }
+
+    /// Persistent objects are saved with their children by save_game and recreated by load_save.
+    /// Fails if an object of the tree doesn't support saving (see `Object::save_state`).
+    pub fn set_object_persistent(&mut self, system_id: &str, object_name: &str, is_persistent: bool) -> Result<(), ()> {
+        let object = match systems::get_system_with_id(system_id) {
+            Some(system) => system.find_object(object_name),
+            None => None,
+        };
+
+        match object {
+            Some(object) => {
+                // the whole tree is recreated from the save, so it can't have objects that aren't saved
+                if is_persistent {
+                    if let Some(unsaveable) = serialization::find_unsaveable_object(object) {
+                        debugger::error(&format!(
+                            "Framework error!\nset_object_persistent failed, object '{}' of type '{}' in the tree of '{}' doesn't support saving",
+                            unsaveable.name(), unsaveable.object_type(), object_name
+                        ));
+                        return Err(());
+                    }
+                }
+                serialization::set_object_persistent(*object.object_id(), is_persistent);
+                Ok(())
+            },
+            None => {
+                debugger::error(&format!(
+                    "Framework error!\nset_object_persistent failed, object '{}' not found in system '{}'",
+                    object_name, system_id
+                ));
+                Err(())
+            },
+        }
     }
 
-    pub fn save_game(&mut self) {
-        self.saves.save_game(&self.system_globals)
+    pub fn register_object_type(&mut self, object_type: &str, constructor: ObjectConstructor) {
+        serialization::register_object_type(object_type, constructor)
     }
 
     pub fn save_lazy_value(&mut self, value_name: &str, value: Vec<SystemValue>) {
@@ -485,6 +686,72 @@ impl Framework {
         self.saves.load_lazy_value(value_name)
     }
 
+    /// Starts loading the region in the background, the systems get region_loaded when it's ready.
+    pub fn request_region(&mut self, group: &str, key: &str) {
+        self.saves.request_region(group, key)
+    }
+
+    pub fn is_region_ready(&self, group: &str, key: &str) -> bool {
+        self.saves.is_region_ready(group, key)
+    }
+
+    pub fn get_region(&self, group: &str, key: &str) -> Option<Vec<SystemValue>> {
+        self.saves.get_region(group, key)
+    }
+
+    /// Some if the saved region failed to load, set_region can't replace it.
+    pub fn get_region_error(&self, group: &str, key: &str) -> Option<String> {
+        self.saves.get_region_error(group, key)
+    }
+
+    pub fn set_region(&mut self, group: &str, key: &str, value: Vec<SystemValue>) {
+        self.saves.set_region(group, key, value)
+    }
+
+    pub fn unload_region(&mut self, group: &str, key: &str) {
+        self.saves.unload_region(group, key)
+    }
+
+    pub fn requested_regions(&self, group: &str) -> Vec<String> {
+        self.saves.requested_regions(group)
+    }
+
+    pub fn list_saves(&self) -> Vec<SaveInfo> {
+        self.saves.list_saves()
+    }
+
+    pub fn save_exists(&self, save_name: &str) -> bool {
+        self.saves.save_exists(save_name)
+    }
+
+    pub fn rename_save(&mut self, save_name: &str, new_save_name: &str) -> Result<(), SaveError> {
+        self.saves.rename_save(save_name, new_save_name)
+    }
+
+    pub fn copy_save(&mut self, save_name: &str, new_save_name: &str) -> Result<(), SaveError> {
+        self.saves.copy_save(save_name, new_save_name)
+    }
+
+    pub fn delete_save(&mut self, save_name: &str) -> Result<(), SaveError> {
+        self.saves.delete_save(save_name)
+    }
+
+    pub fn get_current_save_metadata(&self) -> Option<SaveMetadata> {
+        self.saves.get_current_save_metadata()
+    }
+
+    pub fn set_save_thumbnail(&mut self, thumbnail: Option<Vec<u8>>) {
+        self.saves.set_save_thumbnail(thumbnail)
+    }
+
+    pub fn export_save_archive(&self, save_name: &str, archive_path: Option<&str>) -> Result<(), SaveError> {
+        self.saves.export_save_archive(save_name, archive_path)
+    }
+
+    pub fn import_save_archive(&mut self, archive_path: &str, save_name: &str) -> Result<(), SaveError> {
+        self.saves.import_save_archive(archive_path, save_name)
+    }
+
     // InputManager
     pub fn new_bind_keyboard(&mut self, bind_name: &str, keys: Vec<&str>) {
         let mut input_event_types = Vec::new();
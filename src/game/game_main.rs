@@ -2,12 +2,21 @@ use egui_glium::egui_winit::egui::Visuals;
 use glium::winit::keyboard::KeyCode;
 use crate::{
     framework::{DebugMode, Framework},
-    managers::{input::InputEventType, networking, scripting::lua::LuaSystem, systems::add_system},
+    managers::{input::InputEventType, networking, scripting::lua::{self, sandbox::LuaLimits, LuaSystem}, systems::add_system},
     systems::main_system::MainSystem, Args
 };
 
 pub fn start(args: Args, framework: &mut Framework) {
     println!("{}", serde_json::to_string_pretty(&Visuals::dark()).unwrap());
+    lua::set_hot_reload_enabled(args.hot_reload);
+    let default_lua_limits = lua::sandbox::default_limits();
+    lua::sandbox::set_default_limits(LuaLimits {
+        instruction_budget: args.lua_instruction_budget.unwrap_or(default_lua_limits.instruction_budget),
+        memory_limit: match args.lua_memory_limit {
+            Some(megabytes) => megabytes * 1024 * 1024,
+            None => default_lua_limits.memory_limit,
+        },
+    });
 
     if let Some(save_name) = &args.new_save_name {
         match framework.new_save(&save_name) {
@@ -1,9 +1,12 @@
-use std::{collections::HashMap, net::Ipv4Addr};
+use std::{collections::HashMap, fs, net::{IpAddr, Ipv4Addr, SocketAddr}};
 
 use clap::Parser;
 use framework::DebugMode;
 
-use crate::managers::saves::SavesManager;
+use crate::managers::{
+    networking::{auth::{self, ClientCredentials, NetworkAuthentication}, config::{self, ServerSettings}},
+    saves::{self, SavesManager},
+};
 mod assets;
 mod framework;
 mod game;
@@ -16,6 +19,59 @@ fn main() {
     let args = Args::parse();
     println!("run args:\n{:#?}\n---\n\n", &args);
 
+    if let Some(save_name) = &args.dump_save {
+        if !dump_save(save_name, &args.dump_format, args.output.as_deref()) {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(save_name) = &args.import_save {
+        let imported = match &args.from_json {
+            Some(json_path) => import_save(save_name, json_path),
+            None => {
+                eprintln!("--import-save needs the edited dump, use --from-json <path>");
+                false
+            },
+        };
+        if !imported {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(save_name) = &args.validate_save {
+        if !validate_save(save_name) {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let settings = match server_settings(&args) {
+        Ok(settings) => settings,
+        Err(err) => {
+            println!("Failed to load the server config from {}!\nErr: {}", args.server_config, err);
+            return;
+        }
+    };
+
+    let ip;
+    if let Some(args_ip) = args.ip {
+        ip = args_ip;
+    } else {
+        ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    }
+
+    if let Some(client_id) = args.issue_token {
+        let token_path = args.token.clone().unwrap_or("connect_token.bin".into());
+        let server_address = match args.ip {
+            Some(ip) => SocketAddr::new(ip, settings.port),
+            None => SocketAddr::new(settings.public_address(), settings.port),
+        };
+        issue_token(&args.server_key, settings.protocol_id, client_id, args.player_id.as_deref(), server_address, &token_path);
+        return;
+    }
+
     if let Some(save_name) = &args.new_save_name {
         println!("New save name is {}", save_name);
 
@@ -23,27 +79,56 @@ fn main() {
             Ok(_) => println!("Successfully created a new save file!"),
             Err(err) => println!("Failed to create a new save file!\nErr: {}", err),
         }
-        framework::start_game_without_render(args.clone());
+        framework::start_game_without_render(args.clone(), settings.tick_rate, None);
         return;
     }
 
     if let Some(_) = &args.load_save {
-        println!("Runnning game as server on port 7777");
+        println!(
+            "Runnning game as server on {}:{} (public address {}), max players: {}, tick rate: {}",
+            settings.bind_address, settings.port, settings.public_address(), settings.max_players, settings.tick_rate
+        );
 
-        managers::networking::new_server(7777, 10).unwrap();
-        framework::start_game_without_render(args.clone());
-    }
+        let authentication = match args.insecure {
+            true => NetworkAuthentication::Insecure,
+            false => match auth::load_or_create_private_key(&args.server_key) {
+                Ok(private_key) => NetworkAuthentication::Secure { private_key },
+                Err(err) => {
+                    println!("Failed to load the server's private key from {}!\nErr: {}", args.server_key, err);
+                    return;
+                }
+            },
+        };
 
-    let ip;
-    if let Some(args_ip) = args.ip {
-        ip = args_ip;
-    } else {
-        ip = Ipv4Addr::new(127, 0, 0, 1);
+        if let Err(err) = managers::networking::new_server(&settings, authentication) {
+            println!("Failed to start the server!\nErr: {:?}", err);
+            return;
+        }
+        framework::start_game_without_render(args.clone(), settings.tick_rate, settings.autosave_interval());
+        return;
     }
 
-    let client_id = args.client_id;
-    println!("Connecting to {}:7777, client ID is {}", ip, client_id);
-    managers::networking::new_client(std::net::IpAddr::V4(ip), 7777, client_id).unwrap();
+    let credentials = match (&args.token, args.insecure) {
+        (Some(token_path), _) => match auth::read_token_from_file(token_path) {
+            Ok(token) => {
+                println!("Connecting using the token {}, client ID is {}", token_path, token.client_id);
+                ClientCredentials::Token(token)
+            }
+            Err(err) => {
+                println!("Failed to read the connect token from {}!\nErr: {}", token_path, err);
+                return;
+            }
+        },
+        (None, true) => {
+            println!("Connecting to {}:{} in insecure mode, client ID is {}", ip, settings.port, args.client_id);
+            ClientCredentials::Insecure { client_id: args.client_id, player_id: args.player_id.clone() }
+        }
+        (None, false) => {
+            println!("No connect token! Use --token <path> to connect to a secure server or --insecure for local development");
+            return;
+        }
+    };
+    managers::networking::new_client(ip, settings.port, settings.protocol_id, credentials).unwrap();
 
     let debug = args.debug.clone();
 
@@ -61,11 +146,221 @@ struct Args {
     pub load_save: Option<String>,
     #[arg(long)]
     pub debug: bool,
+    /// Reloads the Lua scripts of the systems when their files are changed.
+    #[arg(long)]
+    pub hot_reload: bool,
+    /// How many instructions a Lua callback can run before it's stopped, 0 disables the limit.
+    #[arg(long)]
+    pub lua_instruction_budget: Option<u64>,
+    /// Megabytes a Lua system's VM can use, 0 disables the limit.
+    #[arg(long)]
+    pub lua_memory_limit: Option<usize>,
     #[arg(long)]
     pub new_save_name: Option<String>,
     #[arg(long = "connect")]
-    pub ip: Option<Ipv4Addr>,
+    pub ip: Option<IpAddr>,
     #[arg(long = "clientid")]
     #[clap(default_value_t)]
     pub client_id: u64,
+    /// Disables connect tokens. Anyone can join with any client id, use only for local development!
+    #[arg(long)]
+    pub insecure: bool,
+    /// Path to the toml file with the server's private key. A new key is generated if the file doesn't exist.
+    #[arg(long, default_value = "server_key.toml")]
+    pub server_key: String,
+    /// Client: path to the connect token. With --issue-token: where the new token is written.
+    #[arg(long)]
+    pub token: Option<String>,
+    /// Issues a connect token for this client id (for the server at --connect) and exits.
+    #[arg(long)]
+    pub issue_token: Option<u64>,
+    /// Stable identity of the player (e.g. an account id) that is written into the issued token or sent
+    /// to an insecure server. The server keys the player's saved data with it.
+    #[arg(long)]
+    pub player_id: Option<String>,
+    /// Path to the toml server config. Values passed through the CLI override the ones from the config.
+    #[arg(long, default_value = "server_config.toml")]
+    pub server_config: String,
+    /// Address the server is bound to.
+    #[arg(long = "bind")]
+    pub bind_address: Option<IpAddr>,
+    /// Address that is written into the connect tokens if the server is bound to 0.0.0.0.
+    #[arg(long)]
+    pub public_address: Option<IpAddr>,
+    /// Server: port to bind to. Client: port to connect to.
+    #[arg(long)]
+    pub port: Option<u16>,
+    #[arg(long)]
+    pub max_players: Option<usize>,
+    #[arg(long)]
+    pub tick_rate: Option<u32>,
+    #[arg(long)]
+    pub protocol_id: Option<u64>,
+    /// Server: seconds between the automatic saves, 0 disables them.
+    #[arg(long)]
+    pub autosave_interval: Option<u64>,
+    /// Prints the save in a human-readable format (see --dump-format and --output) and exits.
+    #[arg(long)]
+    pub dump_save: Option<String>,
+    /// Format of --dump-save: json or toml.
+    #[arg(long, default_value = "json")]
+    pub dump_format: String,
+    /// File for --dump-save, the dump is printed if it's not set.
+    #[arg(long)]
+    pub output: Option<String>,
+    /// Writes the json dump passed with --from-json into this save as its newest generation and exits.
+    #[arg(long)]
+    pub import_save: Option<String>,
+    #[arg(long)]
+    pub from_json: Option<String>,
+    /// Checks that every value of the save can be read and exits, the exit code is 1 if something is broken.
+    #[arg(long)]
+    pub validate_save: Option<String>,
+}
+
+fn server_settings(args: &Args) -> Result<ServerSettings, config::ServerConfigError> {
+    let mut settings = config::load_server_settings(&args.server_config)?;
+
+    if let Some(bind_address) = args.bind_address {
+        settings.bind_address = bind_address;
+    }
+    if let Some(public_address) = args.public_address {
+        settings.public_address = Some(public_address);
+    }
+    if let Some(port) = args.port {
+        settings.port = port;
+    }
+    if let Some(max_players) = args.max_players {
+        settings.max_players = max_players;
+    }
+    if let Some(tick_rate) = args.tick_rate {
+        settings.tick_rate = tick_rate.max(1);
+    }
+    if let Some(protocol_id) = args.protocol_id {
+        settings.protocol_id = protocol_id;
+    }
+    if let Some(autosave_interval) = args.autosave_interval {
+        settings.autosave_interval_seconds = autosave_interval;
+    }
+
+    Ok(settings)
+}
+
+fn issue_token(
+    server_key_path: &str,
+    protocol_id: u64,
+    client_id: u64,
+    player_id: Option<&str>,
+    server_address: SocketAddr,
+    token_path: &str,
+) {
+    // the token has to be signed with the key of the running server, so a new key is never generated here
+    let private_key = match auth::load_private_key(server_key_path) {
+        Ok(private_key) => private_key,
+        Err(err) => {
+            println!("Failed to load the server's private key from {}! Start the server once to generate it or pass the path to it's key file with --server-key.\nErr: {}", server_key_path, err);
+            return;
+        }
+    };
+
+    let token = match auth::issue_token(&private_key, protocol_id, client_id, player_id, vec![server_address], auth::DEFAULT_TOKEN_EXPIRE_SECONDS) {
+        Ok(token) => token,
+        Err(err) => {
+            println!("Failed to issue a connect token!\nErr: {}", err);
+            return;
+        }
+    };
+
+    match auth::write_token_to_file(&token, token_path) {
+        Ok(_) => println!("Issued a connect token for client {} ({}), written to {}", client_id, server_address, token_path),
+        Err(err) => println!("Failed to write the connect token to {}!\nErr: {}", token_path, err),
+    }
+}
+
+// returns false if the save wasn't dumped
+fn dump_save(save_name: &str, dump_format: &str, output_path: Option<&str>) -> bool {
+    let dump = match saves::dump_save(save_name) {
+        Ok(dump) => dump,
+        Err(err) => {
+            eprintln!("Failed to read the save {}!\nErr: {}", save_name, err);
+            return false;
+        }
+    };
+
+    let text = match dump_format {
+        "json" => serde_json::to_string_pretty(&dump).map_err(|err| err.to_string()),
+        "toml" => toml::to_string_pretty(&dump).map_err(|err| err.to_string()),
+        _ => Err(format!("unknown dump format '{}', use json or toml", dump_format)),
+    };
+    let text = match text {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("Failed to serialize the save {}!\nErr: {}", save_name, err);
+            return false;
+        }
+    };
+
+    match output_path {
+        Some(output_path) => match fs::write(output_path, text) {
+            Ok(_) => println!("Save {} (generation {}) is written to {}", save_name, dump.generation, output_path),
+            Err(err) => {
+                eprintln!("Failed to write the dump to {}!\nErr: {}", output_path, err);
+                return false;
+            },
+        },
+        None => println!("{}", text),
+    }
+    true
+}
+
+// returns false if the save wasn't imported
+fn import_save(save_name: &str, json_path: &str) -> bool {
+    let text = match fs::read_to_string(json_path) {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("Failed to read {}!\nErr: {}", json_path, err);
+            return false;
+        }
+    };
+
+    let dump = match serde_json::from_str(&text) {
+        Ok(dump) => dump,
+        Err(err) => {
+            eprintln!("Failed to parse {}!\nErr: {}", json_path, err);
+            return false;
+        }
+    };
+
+    match saves::import_save_dump(save_name, dump) {
+        Ok(_) => {
+            println!("Imported {} into the save {}", json_path, save_name);
+            true
+        },
+        Err(err) => {
+            eprintln!("Failed to import {} into the save {}!\nErr: {}", json_path, save_name, err);
+            false
+        },
+    }
+}
+
+// returns false if the save has problems
+fn validate_save(save_name: &str) -> bool {
+    let report = match saves::validate_save(save_name) {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!("Failed to validate the save {}!\nErr: {}", save_name, err);
+            return false;
+        }
+    };
+
+    if report.problems.is_empty() {
+        println!("Save {} is valid, checked {} values", save_name, report.checked_values);
+        return true;
+    }
+
+    eprintln!("Save {} has {} problems ({} values checked):", save_name, report.problems.len(), report.checked_values);
+    for problem in &report.problems {
+        eprintln!("  {}", problem);
+    }
+    false
 }
@@ -0,0 +1,94 @@
+use std::collections::{HashMap, HashSet};
+
+use once_cell::sync::Lazy;
+
+use super::systems::SystemValue;
+
+/// Engine events, they have no sender.
+/// Payload: trigger's system id, trigger's name, entered object's system id, entered object's name.
+pub const TRIGGER_ENTER: &str = "trigger_enter";
+/// Payload: trigger's system id, trigger's name, exited object's system id, exited object's name.
+pub const TRIGGER_EXIT: &str = "trigger_exit";
+/// Server. Payload: client id.
+pub const CLIENT_CONNECTED: &str = "client_connected";
+/// Server. Payload: client id, reason.
+pub const CLIENT_DISCONNECTED: &str = "client_disconnected";
+/// Client. No payload.
+pub const CONNECTED: &str = "connected";
+/// Client. Payload: reason, empty if it's unknown.
+pub const DISCONNECTED: &str = "disconnected";
+/// Payload: save name.
+pub const SAVE_COMPLETED: &str = "save_completed";
+
+static mut SUBSCRIPTIONS: Lazy<HashMap<String, HashSet<String>>> = Lazy::new(|| HashMap::new()); // String is system's id and HashSet is the names of the events
+static mut QUEUED_EVENTS: Vec<Event> = vec![];
+
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub name: String,
+    /// Id of the system that emitted the event, None for the engine events.
+    pub sender: Option<String>,
+    pub payload: Vec<SystemValue>,
+}
+
+/// The system's handle_event gets the events with this name.
+pub fn subscribe(system_id: &str, event_name: &str) {
+    unsafe {
+        SUBSCRIPTIONS
+            .entry(system_id.into())
+            .or_default()
+            .insert(event_name.into());
+    }
+}
+
+pub fn unsubscribe(system_id: &str, event_name: &str) {
+    unsafe {
+        if let Some(subscriptions) = SUBSCRIPTIONS.get_mut(system_id) {
+            subscriptions.remove(event_name);
+        }
+    }
+}
+
+pub fn unsubscribe_all(system_id: &str) {
+    unsafe { SUBSCRIPTIONS.remove(system_id) };
+}
+
+/// Takes the subscriptions of the system out, so they can be put back with restore_subscriptions.
+pub fn take_subscriptions(system_id: &str) -> Option<HashSet<String>> {
+    unsafe { SUBSCRIPTIONS.remove(system_id) }
+}
+
+pub fn restore_subscriptions(system_id: &str, subscriptions: Option<HashSet<String>>) {
+    unsafe {
+        match subscriptions {
+            Some(subscriptions) => SUBSCRIPTIONS.insert(system_id.into(), subscriptions),
+            None => SUBSCRIPTIONS.remove(system_id),
+        };
+    }
+}
+
+pub fn is_subscribed(system_id: &str, event_name: &str) -> bool {
+    unsafe {
+        match SUBSCRIPTIONS.get(system_id) {
+            Some(subscriptions) => subscriptions.contains(event_name),
+            None => false,
+        }
+    }
+}
+
+/// The event is delivered during the next dispatch (at the start of the systems' update),
+/// in the order the events were emitted.
+pub fn emit(sender: Option<&str>, event_name: &str, payload: Vec<SystemValue>) {
+    unsafe {
+        QUEUED_EVENTS.push(Event {
+            name: event_name.into(),
+            sender: sender.map(|sender| sender.into()),
+            payload,
+        });
+    }
+}
+
+/// Events that were emitted since the last call, the ones emitted while they're handled wait for the next one.
+pub fn take_events() -> Vec<Event> {
+    unsafe { std::mem::take(&mut QUEUED_EVENTS) }
+}
@@ -1,5 +1,6 @@
 pub mod assets;
 pub mod debugger;
+pub mod events;
 pub mod input;
 pub mod navigation;
 pub mod networking;
@@ -0,0 +1,284 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Write},
+    net::SocketAddr,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use once_cell::sync::Lazy;
+use rand::RngCore;
+use renet::transport::{ConnectToken, NETCODE_KEY_BYTES, NETCODE_USER_DATA_BYTES};
+use serde::{Deserialize, Serialize};
+
+use crate::managers::debugger;
+
+// How long an issued token can be used to start connecting.
+pub const DEFAULT_TOKEN_EXPIRE_SECONDS: u64 = 300;
+// How long the connection stays alive without any packets from the other side.
+const TOKEN_TIMEOUT_SECONDS: i32 = 15;
+// The player id is stored in the user data of the token as it's length and utf-8 bytes.
+pub const MAX_PLAYER_ID_BYTES: usize = NETCODE_USER_DATA_BYTES - 1;
+
+// server: stable ids of the connected players, kept for a frame after the disconnect so their data can be saved
+static mut PLAYER_IDS: Lazy<HashMap<u64, String>> = Lazy::new(|| HashMap::new());
+static mut DISCONNECTED_CLIENTS: Vec<u64> = vec![];
+
+#[derive(Debug, Clone)]
+pub enum NetworkAuthentication {
+    /// Clients connect only with tokens signed by the private key.
+    Secure { private_key: [u8; NETCODE_KEY_BYTES] },
+    /// Anyone can connect with any client id. Use only for local development!
+    Insecure,
+}
+
+#[derive(Debug)]
+pub enum ClientCredentials {
+    /// Token issued by the matchmaker. The client id and server addresses are taken from it.
+    Token(ConnectToken),
+    /// Connecting to an insecure server with any client id (and player id).
+    Insecure { client_id: u64, player_id: Option<String> },
+}
+
+#[derive(Debug)]
+pub enum AuthError {
+    IoError(std::io::Error),
+    TomlDeserializeError(toml::de::Error),
+    TomlSerializeError(toml::ser::Error),
+    InvalidKey(String),
+    TokenError(String),
+}
+
+impl std::fmt::Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthError::IoError(err) => write!(f, "io error: {}", err),
+            AuthError::TomlDeserializeError(err) => write!(f, "failed to parse toml: {}", err),
+            AuthError::TomlSerializeError(err) => write!(f, "failed to write toml: {}", err),
+            AuthError::InvalidKey(err) => write!(f, "invalid private key: {}", err),
+            AuthError::TokenError(err) => write!(f, "connect token error: {}", err),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ServerKeyFile {
+    /// Hex encoded private key that is shared between the server and the token issuer.
+    private_key: String,
+}
+
+/// Loads the private key from a toml file. Fails if the file doesn't exist.
+pub fn load_private_key(path: &str) -> Result<[u8; NETCODE_KEY_BYTES], AuthError> {
+    let contents = fs::read_to_string(path).map_err(AuthError::IoError)?;
+    let key_file: ServerKeyFile =
+        toml::from_str(&contents).map_err(AuthError::TomlDeserializeError)?;
+    decode_key(&key_file.private_key)
+}
+
+/// Loads the private key from a toml file. If the file doesn't exist, a new random key is generated and written to it.
+pub fn load_or_create_private_key(path: &str) -> Result<[u8; NETCODE_KEY_BYTES], AuthError> {
+    match load_private_key(path) {
+        Err(AuthError::IoError(err)) if err.kind() == ErrorKind::NotFound => {
+            debugger::warn(&format!(
+                "networking auth warning!\nprivate key file '{}' doesn't exist, generating a new key",
+                path
+            ));
+
+            let mut private_key = [0; NETCODE_KEY_BYTES];
+            rand::thread_rng().fill_bytes(&mut private_key);
+
+            let key_file = ServerKeyFile {
+                private_key: encode_key(&private_key),
+            };
+            let contents = toml::to_string(&key_file).map_err(AuthError::TomlSerializeError)?;
+            if let Some(parent) = Path::new(path).parent() {
+                if !parent.as_os_str().is_empty() {
+                    fs::create_dir_all(parent).map_err(AuthError::IoError)?;
+                }
+            }
+            write_key_file(path, &contents).map_err(AuthError::IoError)?;
+
+            Ok(private_key)
+        }
+        result => result,
+    }
+}
+
+// the key file is readable only by it's owner, anyone who can read it can issue tokens
+fn write_key_file(path: &str, contents: &str) -> std::io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()
+}
+
+/// Issues a connect token that lets the client with this id connect to one of the server addresses.
+/// The player id is the stable identity of the player (e.g. an account id) that is used to find it's saved data.
+pub fn issue_token(
+    private_key: &[u8; NETCODE_KEY_BYTES],
+    protocol_id: u64,
+    client_id: u64,
+    player_id: Option<&str>,
+    server_addresses: Vec<SocketAddr>,
+    expire_seconds: u64,
+) -> Result<ConnectToken, AuthError> {
+    let user_data = match player_id {
+        Some(player_id) => Some(player_id_to_user_data(player_id)?),
+        None => None,
+    };
+
+    let current_time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO);
+
+    ConnectToken::generate(
+        current_time,
+        protocol_id,
+        expire_seconds,
+        client_id,
+        TOKEN_TIMEOUT_SECONDS,
+        server_addresses,
+        user_data.as_ref(),
+        private_key,
+    )
+    .map_err(|err| AuthError::TokenError(err.to_string()))
+}
+
+pub fn player_id_to_user_data(player_id: &str) -> Result<[u8; NETCODE_USER_DATA_BYTES], AuthError> {
+    let bytes = player_id.as_bytes();
+    if bytes.is_empty() || bytes.len() > MAX_PLAYER_ID_BYTES {
+        return Err(AuthError::TokenError(format!(
+            "player id must be 1-{} bytes long, got {}",
+            MAX_PLAYER_ID_BYTES,
+            bytes.len()
+        )));
+    }
+
+    let mut user_data = [0; NETCODE_USER_DATA_BYTES];
+    user_data[0] = bytes.len() as u8;
+    user_data[1..=bytes.len()].copy_from_slice(bytes);
+    Ok(user_data)
+}
+
+pub fn player_id_from_user_data(user_data: &[u8; NETCODE_USER_DATA_BYTES]) -> Option<String> {
+    let length = user_data[0] as usize;
+    if length == 0 || length > MAX_PLAYER_ID_BYTES {
+        return None;
+    }
+
+    String::from_utf8(user_data[1..=length].to_vec()).ok()
+}
+
+/// Server: the stable identity of the connected client. Clients without a player id in their token
+/// are identified by their client id.
+pub fn get_player_id(client_id: u64) -> Option<String> {
+    unsafe { PLAYER_IDS.get(&client_id).cloned() }
+}
+
+/// Server: true if any of the connected clients (that didn't disconnect during the previous frame) has this player id.
+/// Several clients can share one player id, e.g. when the same account is connected twice.
+pub fn is_player_connected(player_id: &str) -> bool {
+    unsafe {
+        PLAYER_IDS
+            .iter()
+            .any(|(client_id, id)| id == player_id && !DISCONNECTED_CLIENTS.contains(client_id))
+    }
+}
+
+pub(crate) fn client_connected(client_id: u64, user_data: Option<[u8; NETCODE_USER_DATA_BYTES]>) {
+    let player_id = user_data
+        .and_then(|user_data| player_id_from_user_data(&user_data))
+        .unwrap_or(format!("client_{}", client_id));
+
+    unsafe {
+        DISCONNECTED_CLIENTS.retain(|id| *id != client_id);
+        PLAYER_IDS.insert(client_id, player_id);
+    }
+}
+
+pub(crate) fn client_disconnected(client_id: u64) {
+    unsafe {
+        DISCONNECTED_CLIENTS.push(client_id);
+    }
+}
+
+/// Forgets the players that were disconnected during the previous frame.
+pub(crate) fn update() {
+    unsafe {
+        for client_id in DISCONNECTED_CLIENTS.drain(..) {
+            PLAYER_IDS.remove(&client_id);
+        }
+    }
+}
+
+pub fn write_token_to_file(token: &ConnectToken, path: &str) -> Result<(), AuthError> {
+    let mut file = File::create(path).map_err(AuthError::IoError)?;
+    token.write(&mut file).map_err(AuthError::IoError)
+}
+
+pub fn read_token_from_file(path: &str) -> Result<ConnectToken, AuthError> {
+    let mut file = File::open(path).map_err(AuthError::IoError)?;
+    ConnectToken::read(&mut file).map_err(|err| AuthError::TokenError(err.to_string()))
+}
+
+fn encode_key(key: &[u8; NETCODE_KEY_BYTES]) -> String {
+    key.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_key(hex: &str) -> Result<[u8; NETCODE_KEY_BYTES], AuthError> {
+    let hex = hex.trim();
+    if hex.len() != NETCODE_KEY_BYTES * 2 || !hex.is_ascii() {
+        return Err(AuthError::InvalidKey(format!(
+            "expected {} hex characters, got {}",
+            NETCODE_KEY_BYTES * 2,
+            hex.len()
+        )));
+    }
+
+    let mut key = [0; NETCODE_KEY_BYTES];
+    for (idx, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[idx * 2..idx * 2 + 2], 16)
+            .map_err(|err| AuthError::InvalidKey(err.to_string()))?;
+    }
+
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn player_id_round_trips() {
+        let user_data = player_id_to_user_data("player_42").unwrap();
+        assert_eq!(player_id_from_user_data(&user_data).as_deref(), Some("player_42"));
+
+        let longest = "a".repeat(MAX_PLAYER_ID_BYTES);
+        let user_data = player_id_to_user_data(&longest).unwrap();
+        assert_eq!(player_id_from_user_data(&user_data), Some(longest));
+    }
+
+    #[test]
+    fn invalid_player_ids_are_rejected() {
+        assert!(player_id_to_user_data("").is_err());
+        assert!(player_id_to_user_data(&"a".repeat(MAX_PLAYER_ID_BYTES + 1)).is_err());
+    }
+
+    #[test]
+    fn user_data_without_a_valid_player_id_is_ignored() {
+        assert_eq!(player_id_from_user_data(&[0; NETCODE_USER_DATA_BYTES]), None);
+
+        let mut user_data = [0; NETCODE_USER_DATA_BYTES];
+        user_data[0] = 2;
+        user_data[1..3].copy_from_slice(&[0xff, 0xfe]);
+        assert_eq!(player_id_from_user_data(&user_data), None);
+    }
+}
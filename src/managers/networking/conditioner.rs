@@ -0,0 +1,120 @@
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+
+use super::send_message_to_system;
+
+static mut CONDITIONER_SETTINGS: Option<ConditionerSettings> = None;
+static mut DELAYED_MESSAGES: Vec<DelayedMessage> = vec![];
+// reliable messages can be delayed, but they must not overtake each other
+static mut LAST_RELIABLE_DELIVERY: Option<Instant> = None;
+
+/// Simulates a bad network for the received messages. Only for testing!
+/// The settings are applied on the receiving side, so with a local server and client
+/// that both use the conditioner the round trip gets the latency twice.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ConditionerSettings {
+    pub latency_ms: f32,
+    /// Random extra delay from 0 to jitter_ms.
+    pub jitter_ms: f32,
+    /// Chance (from 0 to 1) that an unreliable message is dropped.
+    pub loss: f32,
+    /// Chance (from 0 to 1) that an unreliable message is received twice.
+    pub duplication: f32,
+}
+
+#[derive(Debug)]
+struct DelayedMessage {
+    deliver_at: Instant,
+    bytes: Vec<u8>,
+    sender: Option<u64>,
+}
+
+/// Enables (Some) or disables (None) the conditioner. Disabling it delivers all of the delayed messages.
+pub fn set_conditioner(settings: Option<ConditionerSettings>) {
+    unsafe {
+        CONDITIONER_SETTINGS = settings.map(|settings| ConditionerSettings {
+            latency_ms: settings.latency_ms.max(0.0),
+            jitter_ms: settings.jitter_ms.max(0.0),
+            loss: settings.loss.clamp(0.0, 1.0),
+            duplication: settings.duplication.clamp(0.0, 1.0),
+        });
+    }
+
+    if settings.is_none() {
+        deliver_messages(true);
+    }
+}
+
+pub fn get_conditioner() -> Option<ConditionerSettings> {
+    unsafe { CONDITIONER_SETTINGS }
+}
+
+/// Passes the received message through the conditioner (or straight to the system if it's disabled).
+/// The sender is the client that the server got the message from, None on the client.
+pub(crate) fn receive_message(bytes: Vec<u8>, reliable: bool, sender: Option<u64>) {
+    let settings = match get_conditioner() {
+        Some(settings) => settings,
+        None => {
+            send_message_to_system(bytes, sender);
+            return;
+        }
+    };
+
+    let mut rng = rand::thread_rng();
+    if reliable {
+        let mut deliver_at = Instant::now() + random_delay(&settings, &mut rng);
+        unsafe {
+            if let Some(last_reliable_delivery) = LAST_RELIABLE_DELIVERY {
+                deliver_at = deliver_at.max(last_reliable_delivery);
+            }
+            LAST_RELIABLE_DELIVERY = Some(deliver_at);
+        }
+        delay_message(deliver_at, bytes, sender);
+        return;
+    }
+
+    if rng.gen::<f32>() < settings.loss {
+        return;
+    }
+
+    if rng.gen::<f32>() < settings.duplication {
+        delay_message(Instant::now() + random_delay(&settings, &mut rng), bytes.clone(), sender);
+    }
+    delay_message(Instant::now() + random_delay(&settings, &mut rng), bytes, sender);
+}
+
+/// Delivers the delayed messages whose time has come.
+pub(crate) fn update() {
+    deliver_messages(false);
+}
+
+fn deliver_messages(deliver_all: bool) {
+    let now = Instant::now();
+    let mut messages_to_deliver: Vec<DelayedMessage> = unsafe {
+        let (ready, delayed) = DELAYED_MESSAGES
+            .drain(..)
+            .partition(|message| deliver_all || message.deliver_at <= now);
+        DELAYED_MESSAGES = delayed;
+        ready
+    };
+    messages_to_deliver.sort_by_key(|message| message.deliver_at);
+
+    for message in messages_to_deliver {
+        send_message_to_system(message.bytes, message.sender);
+    }
+}
+
+fn delay_message(deliver_at: Instant, bytes: Vec<u8>, sender: Option<u64>) {
+    unsafe {
+        DELAYED_MESSAGES.push(DelayedMessage { deliver_at, bytes, sender });
+    }
+}
+
+fn random_delay(settings: &ConditionerSettings, rng: &mut impl Rng) -> Duration {
+    let jitter = match settings.jitter_ms > 0.0 {
+        true => rng.gen_range(0.0..settings.jitter_ms),
+        false => 0.0,
+    };
+    Duration::from_secs_f32((settings.latency_ms + jitter) / 1000.0)
+}
@@ -0,0 +1,78 @@
+use std::{
+    fs,
+    io::ErrorKind,
+    net::{IpAddr, Ipv4Addr},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::DEFAULT_PROTOCOL_ID;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ServerSettings {
+    /// Address the server's socket is bound to. Use 0.0.0.0 to accept connections on all interfaces.
+    pub bind_address: IpAddr,
+    /// Address the clients connect to (it's written into the connect tokens).
+    /// Defaults to bind_address, should be set when bind_address is 0.0.0.0.
+    pub public_address: Option<IpAddr>,
+    pub port: u16,
+    pub max_players: usize,
+    /// Server updates per second.
+    pub tick_rate: u32,
+    pub protocol_id: u64,
+    /// Seconds between the automatic saves of the loaded save, 0 disables them.
+    pub autosave_interval_seconds: u64,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        ServerSettings {
+            bind_address: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            public_address: None,
+            port: 7777,
+            max_players: 10,
+            tick_rate: 60,
+            protocol_id: DEFAULT_PROTOCOL_ID,
+            autosave_interval_seconds: 300,
+        }
+    }
+}
+
+impl ServerSettings {
+    pub fn public_address(&self) -> IpAddr {
+        self.public_address.unwrap_or(self.bind_address)
+    }
+
+    pub fn autosave_interval(&self) -> Option<Duration> {
+        match self.autosave_interval_seconds {
+            0 => None,
+            seconds => Some(Duration::from_secs(seconds)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ServerConfigError {
+    IoError(std::io::Error),
+    TomlDeserializeError(toml::de::Error),
+}
+
+impl std::fmt::Display for ServerConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServerConfigError::IoError(err) => write!(f, "io error: {}", err),
+            ServerConfigError::TomlDeserializeError(err) => write!(f, "failed to parse toml: {}", err),
+        }
+    }
+}
+
+/// Reads the server settings from a toml file. Missing fields (or a missing file) use the default values.
+pub fn load_server_settings(path: &str) -> Result<ServerSettings, ServerConfigError> {
+    match fs::read_to_string(path) {
+        Ok(contents) => toml::from_str(&contents).map_err(ServerConfigError::TomlDeserializeError),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(ServerSettings::default()),
+        Err(err) => Err(ServerConfigError::IoError(err)),
+    }
+}
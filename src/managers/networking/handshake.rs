@@ -0,0 +1,444 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+use renet::{DefaultChannel, RenetClient, RenetServer};
+
+use super::{set_network_event, NetworkEvent};
+use crate::{
+    managers::{debugger, systems},
+    math_utils::fnv1a,
+};
+
+// Bump it every time Message (or anything inside of it) changes, clients with a different version are rejected.
+pub const NETWORK_PROTOCOL_VERSION: u32 = 4;
+// Handshake packets don't use serde_bare and Message, so they can be read by any version of the game.
+const HANDSHAKE_MAGIC: [u8; 4] = *b"GHSK";
+// Handshake packets use their own channel, so they are never mixed with the messages.
+const HANDSHAKE_CHANNEL: DefaultChannel = DefaultChannel::ReliableUnordered;
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+// Rejected clients are disconnected a bit later, so the rejection reason has time to reach them.
+const REJECTED_CLIENT_DISCONNECT_DELAY: Duration = Duration::from_secs(1);
+
+// system id -> content name (empty for the main content) -> hash of the content
+static mut SYSTEMS_CONTENT_HASHES: Lazy<HashMap<String, BTreeMap<String, u64>>> = Lazy::new(|| HashMap::new());
+// server
+static mut PENDING_CLIENTS: Lazy<HashMap<u64, Instant>> = Lazy::new(|| HashMap::new());
+static mut ACCEPTED_CLIENTS: Vec<u64> = vec![];
+static mut REJECTED_CLIENTS: Lazy<HashMap<u64, Instant>> = Lazy::new(|| HashMap::new());
+// accepted clients that were told why they are disconnected, they stay accepted until the disconnect
+static mut KICKED_CLIENTS: Lazy<HashMap<u64, Instant>> = Lazy::new(|| HashMap::new());
+// client
+static mut REJECT_REASON: Option<RejectReason> = None;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RejectReason {
+    VersionMismatch { server_version: u32, client_version: u32 },
+    /// Systems or scripts that are loaded on the client differ from the server's ones.
+    ContentMismatch { server_hash: u64, client_hash: u64 },
+    InvalidHandshake,
+    HandshakeTimeout,
+    /// The server disconnected an accepted client (e.g. when it's shutting down).
+    Kicked(String),
+}
+
+impl RejectReason {
+    /// Short name of the reason that is used in Lua.
+    pub fn name(&self) -> &'static str {
+        match self {
+            RejectReason::VersionMismatch { .. } => "VersionMismatch",
+            RejectReason::ContentMismatch { .. } => "ContentMismatch",
+            RejectReason::InvalidHandshake => "InvalidHandshake",
+            RejectReason::HandshakeTimeout => "HandshakeTimeout",
+            RejectReason::Kicked(_) => "Kicked",
+        }
+    }
+}
+
+impl std::fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RejectReason::VersionMismatch { server_version, client_version } => write!(
+                f,
+                "version mismatch (server: {}, client: {})",
+                server_version, client_version
+            ),
+            RejectReason::ContentMismatch { server_hash, client_hash } => write!(
+                f,
+                "content mismatch (server: {:016x}, client: {:016x})",
+                server_hash, client_hash
+            ),
+            RejectReason::InvalidHandshake => write!(f, "invalid handshake"),
+            RejectReason::HandshakeTimeout => write!(f, "handshake timeout"),
+            RejectReason::Kicked(reason) => write!(f, "disconnected by the server: {}", reason),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum HandshakePacket {
+    Hello { version: u32, content_hash: u64 },
+    Accepted,
+    Rejected(RejectReason),
+}
+
+impl HandshakePacket {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = HANDSHAKE_MAGIC.to_vec();
+        match self {
+            HandshakePacket::Hello { version, content_hash } => {
+                bytes.push(0);
+                bytes.extend_from_slice(&version.to_le_bytes());
+                bytes.extend_from_slice(&content_hash.to_le_bytes());
+            }
+            HandshakePacket::Accepted => bytes.push(1),
+            HandshakePacket::Rejected(reason) => {
+                bytes.push(2);
+                match reason {
+                    RejectReason::VersionMismatch { server_version, client_version } => {
+                        bytes.push(0);
+                        bytes.extend_from_slice(&server_version.to_le_bytes());
+                        bytes.extend_from_slice(&client_version.to_le_bytes());
+                    }
+                    RejectReason::ContentMismatch { server_hash, client_hash } => {
+                        bytes.push(1);
+                        bytes.extend_from_slice(&server_hash.to_le_bytes());
+                        bytes.extend_from_slice(&client_hash.to_le_bytes());
+                    }
+                    RejectReason::InvalidHandshake => bytes.push(2),
+                    RejectReason::HandshakeTimeout => bytes.push(3),
+                    RejectReason::Kicked(reason) => {
+                        bytes.push(4);
+                        bytes.extend_from_slice(reason.as_bytes());
+                    }
+                }
+            }
+        }
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<HandshakePacket> {
+        if bytes.len() < 5 || bytes[0..4] != HANDSHAKE_MAGIC {
+            return None;
+        }
+
+        let body = &bytes[5..];
+        match bytes[4] {
+            0 => Some(HandshakePacket::Hello {
+                version: read_u32(body, 0)?,
+                content_hash: read_u64(body, 4)?,
+            }),
+            1 => Some(HandshakePacket::Accepted),
+            2 => {
+                let reason = match body.first()? {
+                    0 => RejectReason::VersionMismatch {
+                        server_version: read_u32(body, 1)?,
+                        client_version: read_u32(body, 5)?,
+                    },
+                    1 => RejectReason::ContentMismatch {
+                        server_hash: read_u64(body, 1)?,
+                        client_hash: read_u64(body, 9)?,
+                    },
+                    2 => RejectReason::InvalidHandshake,
+                    3 => RejectReason::HandshakeTimeout,
+                    4 => RejectReason::Kicked(String::from_utf8_lossy(&body[1..]).into()),
+                    _ => return None,
+                };
+                Some(HandshakePacket::Rejected(reason))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Adds the system's content (e.g. the script source) to the content hash that is compared during the handshake.
+/// Replaces everything that was registered for the system before.
+pub fn register_system_content(system_id: &str, content: &[u8]) {
+    unsafe {
+        SYSTEMS_CONTENT_HASHES.insert(system_id.into(), BTreeMap::from([(String::new(), fnv1a(content))]));
+    }
+}
+
+/// Adds more named content of the system (e.g. a module that the script loaded) to the content hash.
+pub fn add_system_content(system_id: &str, name: &str, content: &[u8]) {
+    unsafe {
+        SYSTEMS_CONTENT_HASHES
+            .entry(system_id.into())
+            .or_default()
+            .insert(name.into(), fnv1a(content));
+    }
+}
+
+/// Takes the content of the system out, so it can be put back with restore_system_content.
+pub fn take_system_content(system_id: &str) -> Option<BTreeMap<String, u64>> {
+    unsafe { SYSTEMS_CONTENT_HASHES.remove(system_id) }
+}
+
+pub fn restore_system_content(system_id: &str, content: Option<BTreeMap<String, u64>>) {
+    unsafe {
+        match content {
+            Some(content) => SYSTEMS_CONTENT_HASHES.insert(system_id.into(), content),
+            None => SYSTEMS_CONTENT_HASHES.remove(system_id),
+        };
+    }
+}
+
+/// Hash of the ids of all loaded systems and their registered content.
+pub fn content_hash() -> u64 {
+    let mut system_ids: Vec<&str> = systems::get_systems_iter()
+        .map(|system| system.system_id())
+        .collect();
+    system_ids.sort();
+
+    let mut bytes = Vec::new();
+    for system_id in system_ids {
+        let system_content_hash = unsafe {
+            match SYSTEMS_CONTENT_HASHES.get(system_id) {
+                Some(content) => {
+                    let mut content_bytes = Vec::new();
+                    for (name, hash) in content {
+                        content_bytes.extend_from_slice(name.as_bytes());
+                        content_bytes.push(0);
+                        content_bytes.extend_from_slice(&hash.to_le_bytes());
+                    }
+                    fnv1a(&content_bytes)
+                },
+                None => 0,
+            }
+        };
+        bytes.extend_from_slice(system_id.as_bytes());
+        bytes.push(0);
+        bytes.extend_from_slice(&system_content_hash.to_le_bytes());
+    }
+
+    fnv1a(&bytes)
+}
+
+/// Client: the reason why the server rejected this client, if it did.
+pub fn get_reject_reason() -> Option<RejectReason> {
+    unsafe { REJECT_REASON.clone() }
+}
+
+pub fn is_client_accepted(client_id: u64) -> bool {
+    unsafe { ACCEPTED_CLIENTS.contains(&client_id) }
+}
+
+pub(crate) fn get_accepted_clients() -> Vec<u64> {
+    unsafe { ACCEPTED_CLIENTS.clone() }
+}
+
+pub(crate) fn client_connected(client_id: u64) {
+    unsafe {
+        PENDING_CLIENTS.insert(client_id, Instant::now());
+    }
+}
+
+/// Returns true if the client has passed the handshake before disconnecting.
+pub(crate) fn client_disconnected(client_id: u64) -> bool {
+    unsafe {
+        PENDING_CLIENTS.remove(&client_id);
+        REJECTED_CLIENTS.remove(&client_id);
+        KICKED_CLIENTS.remove(&client_id);
+        let was_accepted = ACCEPTED_CLIENTS.contains(&client_id);
+        ACCEPTED_CLIENTS.retain(|id| *id != client_id);
+        was_accepted
+    }
+}
+
+/// Checks the handshakes of the pending clients, disconnects the rejected and timed out ones.
+pub(crate) fn server_update(server: &mut RenetServer) {
+    let server_content_hash = content_hash();
+    let pending_clients: Vec<(u64, Instant)> =
+        unsafe { PENDING_CLIENTS.iter().map(|(id, time)| (*id, *time)).collect() };
+
+    for (client_id, connected_time) in pending_clients {
+        let mut result = None;
+        while let Some(bytes) = server.receive_message(client_id, HANDSHAKE_CHANNEL) {
+            result = Some(match HandshakePacket::from_bytes(&bytes) {
+                Some(HandshakePacket::Hello { version, content_hash }) => {
+                    if version != NETWORK_PROTOCOL_VERSION {
+                        Err(RejectReason::VersionMismatch {
+                            server_version: NETWORK_PROTOCOL_VERSION,
+                            client_version: version,
+                        })
+                    } else if content_hash != server_content_hash {
+                        Err(RejectReason::ContentMismatch {
+                            server_hash: server_content_hash,
+                            client_hash: content_hash,
+                        })
+                    } else {
+                        Ok(())
+                    }
+                }
+                _ => Err(RejectReason::InvalidHandshake),
+            });
+        }
+
+        if result.is_none() && connected_time.elapsed() > HANDSHAKE_TIMEOUT {
+            result = Some(Err(RejectReason::HandshakeTimeout));
+        }
+
+        match result {
+            Some(Ok(())) => unsafe {
+                PENDING_CLIENTS.remove(&client_id);
+                ACCEPTED_CLIENTS.push(client_id);
+                server.send_message(client_id, HANDSHAKE_CHANNEL, HandshakePacket::Accepted.to_bytes());
+                println!("client connected! client_id: {}", client_id);
+                set_network_event(NetworkEvent::ClientConnected(client_id.to_string()));
+            },
+            Some(Err(reason)) => unsafe {
+                PENDING_CLIENTS.remove(&client_id);
+                REJECTED_CLIENTS.insert(client_id, Instant::now());
+                server.send_message(
+                    client_id,
+                    HANDSHAKE_CHANNEL,
+                    HandshakePacket::Rejected(reason.clone()).to_bytes(),
+                );
+                debugger::warn(&format!(
+                    "networking handshake warning!\nrejected client {}\nreason: {}",
+                    client_id, reason
+                ));
+                set_network_event(NetworkEvent::ClientRejected(client_id.to_string(), reason));
+            },
+            None => (),
+        }
+    }
+
+    let rejected_clients: Vec<u64> = unsafe {
+        REJECTED_CLIENTS
+            .iter()
+            .filter(|(_, rejected_time)| rejected_time.elapsed() > REJECTED_CLIENT_DISCONNECT_DELAY)
+            .map(|(id, _)| *id)
+            .collect()
+    };
+    for client_id in rejected_clients {
+        unsafe {
+            REJECTED_CLIENTS.remove(&client_id);
+        }
+        server.disconnect(client_id);
+    }
+
+    let kicked_clients: Vec<u64> = unsafe {
+        KICKED_CLIENTS
+            .iter()
+            .filter(|(_, kicked_time)| kicked_time.elapsed() > REJECTED_CLIENT_DISCONNECT_DELAY)
+            .map(|(id, _)| *id)
+            .collect()
+    };
+    for client_id in kicked_clients {
+        unsafe {
+            KICKED_CLIENTS.remove(&client_id);
+        }
+        server.disconnect(client_id);
+    }
+}
+
+/// Server: sends the reason to the client and disconnects it a bit later.
+pub(crate) fn kick_client(server: &mut RenetServer, client_id: u64, reason: &str) {
+    unsafe {
+        if KICKED_CLIENTS.contains_key(&client_id) {
+            return;
+        }
+        KICKED_CLIENTS.insert(client_id, Instant::now());
+    }
+
+    server.send_message(
+        client_id,
+        HANDSHAKE_CHANNEL,
+        HandshakePacket::Rejected(RejectReason::Kicked(reason.into())).to_bytes(),
+    );
+    println!("disconnecting client {}, reason: {}", client_id, reason);
+}
+
+/// Server: true while some of the kicked clients are still waiting to be disconnected.
+pub(crate) fn has_kicked_clients() -> bool {
+    unsafe { !KICKED_CLIENTS.is_empty() }
+}
+
+/// Client: sends the protocol version and the content hash to the server.
+pub(crate) fn send_hello(client: &mut RenetClient) {
+    unsafe {
+        REJECT_REASON = None;
+    }
+
+    let hello = HandshakePacket::Hello {
+        version: NETWORK_PROTOCOL_VERSION,
+        content_hash: content_hash(),
+    };
+    client.send_message(HANDSHAKE_CHANNEL, hello.to_bytes());
+}
+
+/// Client: returns Some when the server has answered the handshake (or kicked the connected client).
+pub(crate) fn receive_server_response(client: &mut RenetClient) -> Option<Result<(), RejectReason>> {
+    while let Some(bytes) = client.receive_message(HANDSHAKE_CHANNEL) {
+        match HandshakePacket::from_bytes(&bytes) {
+            Some(HandshakePacket::Accepted) => return Some(Ok(())),
+            Some(HandshakePacket::Rejected(reason)) => {
+                unsafe {
+                    REJECT_REASON = Some(reason.clone());
+                }
+                return Some(Err(reason));
+            }
+            _ => debugger::warn("networking handshake warning!\ngot an invalid handshake packet from the server"),
+        }
+    }
+
+    None
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let bytes = bytes.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    let bytes = bytes.get(offset..offset + 8)?;
+    Some(u64::from_le_bytes(bytes.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packets_survive_encoding() {
+        let packets = vec![
+            HandshakePacket::Hello { version: NETWORK_PROTOCOL_VERSION, content_hash: 0x0123_4567_89ab_cdef },
+            HandshakePacket::Accepted,
+            HandshakePacket::Rejected(RejectReason::VersionMismatch { server_version: 4, client_version: 3 }),
+            HandshakePacket::Rejected(RejectReason::ContentMismatch { server_hash: 1, client_hash: u64::MAX }),
+            HandshakePacket::Rejected(RejectReason::InvalidHandshake),
+            HandshakePacket::Rejected(RejectReason::HandshakeTimeout),
+            HandshakePacket::Rejected(RejectReason::Kicked("server is shutting down".into())),
+        ];
+
+        for packet in packets {
+            assert_eq!(HandshakePacket::from_bytes(&packet.to_bytes()), Some(packet));
+        }
+    }
+
+    #[test]
+    fn wrong_magic_is_rejected() {
+        let mut bytes = HandshakePacket::Accepted.to_bytes();
+        bytes[0] = b'X';
+        assert_eq!(HandshakePacket::from_bytes(&bytes), None);
+    }
+
+    #[test]
+    fn truncated_packets_are_rejected() {
+        let bytes = HandshakePacket::Hello { version: 1, content_hash: 2 }.to_bytes();
+        for length in 0..bytes.len() {
+            assert_eq!(HandshakePacket::from_bytes(&bytes[..length]), None);
+        }
+    }
+
+    #[test]
+    fn unknown_packet_type_is_rejected() {
+        let mut bytes = HANDSHAKE_MAGIC.to_vec();
+        bytes.push(9);
+        assert_eq!(HandshakePacket::from_bytes(&bytes), None);
+    }
+}
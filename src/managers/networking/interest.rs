@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+
+use glam::Vec3;
+use once_cell::sync::Lazy;
+
+use crate::managers::systems;
+
+static mut CLIENTS_INTEREST: Lazy<HashMap<u64, ClientInterest>> = Lazy::new(|| HashMap::new());
+static mut OBJECTS_RELEVANCY: Lazy<HashMap<(String, String), ObjectRelevancy>> = Lazy::new(|| HashMap::new());
+
+/// Area around the client's position in which objects are relevant for it.
+#[derive(Debug, Clone)]
+pub struct ClientInterest {
+    pub position: Vec3,
+    pub radius: f32,
+    /// If Some, the position follows this object (system id, object name).
+    pub attached_object: Option<(String, String)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ObjectRelevancy {
+    /// Object is relevant for clients whose area of interest touches this radius around the object.
+    Radius(f32),
+    /// Object is relevant for everyone no matter where they are.
+    Always,
+}
+
+impl Default for ObjectRelevancy {
+    fn default() -> Self {
+        ObjectRelevancy::Radius(0.0)
+    }
+}
+
+/// Sets the client's area of interest. Clients without it get everything.
+pub fn set_client_interest(client_id: u64, position: Vec3, radius: f32) {
+    unsafe {
+        CLIENTS_INTEREST.insert(
+            client_id,
+            ClientInterest {
+                position,
+                radius: radius.max(0.0),
+                attached_object: None,
+            },
+        );
+    }
+}
+
+/// Makes the client's area of interest follow the object (usually the player's character).
+pub fn attach_client_interest(client_id: u64, system_id: &str, object_name: &str, radius: f32) {
+    let position = object_position(system_id, object_name).unwrap_or_default();
+    unsafe {
+        CLIENTS_INTEREST.insert(
+            client_id,
+            ClientInterest {
+                position,
+                radius: radius.max(0.0),
+                attached_object: Some((system_id.into(), object_name.into())),
+            },
+        );
+    }
+}
+
+pub fn remove_client_interest(client_id: u64) {
+    unsafe {
+        CLIENTS_INTEREST.remove(&client_id);
+    }
+}
+
+pub fn get_client_interest(client_id: u64) -> Option<ClientInterest> {
+    unsafe { CLIENTS_INTEREST.get(&client_id).cloned() }
+}
+
+pub fn set_object_relevancy(system_id: &str, object_name: &str, relevancy: ObjectRelevancy) {
+    unsafe {
+        OBJECTS_RELEVANCY.insert((system_id.into(), object_name.into()), relevancy);
+    }
+}
+
+pub fn get_object_relevancy(system_id: &str, object_name: &str) -> ObjectRelevancy {
+    unsafe {
+        OBJECTS_RELEVANCY
+            .get(&(system_id.to_string(), object_name.to_string()))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// Returns true if the object should be sent to the client.
+pub fn is_object_relevant(client_id: u64, system_id: &str, object_name: &str) -> bool {
+    let interest = match get_client_interest(client_id) {
+        Some(interest) => interest,
+        None => return true,
+    };
+
+    let radius = match get_object_relevancy(system_id, object_name) {
+        ObjectRelevancy::Always => return true,
+        ObjectRelevancy::Radius(radius) => radius,
+    };
+
+    match object_position(system_id, object_name) {
+        Some(position) => interest.position.distance(position) <= interest.radius + radius,
+        // objects without a position can't be filtered
+        None => true,
+    }
+}
+
+/// Returns the clients whose area of interest touches the sphere. Clients without an area of interest are always included.
+pub fn clients_near(clients: &[u64], position: Vec3, radius: f32) -> Vec<u64> {
+    clients
+        .iter()
+        .copied()
+        .filter(|client_id| match get_client_interest(*client_id) {
+            Some(interest) => interest.position.distance(position) <= interest.radius + radius,
+            None => true,
+        })
+        .collect()
+}
+
+/// Moves the attached areas of interest to their objects.
+pub fn update() {
+    unsafe {
+        for interest in CLIENTS_INTEREST.values_mut() {
+            if let Some((system_id, object_name)) = &interest.attached_object {
+                if let Some(position) = object_position(system_id, object_name) {
+                    interest.position = position;
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn forget_client(client_id: u64) {
+    remove_client_interest(client_id);
+}
+
+pub(crate) fn forget_object(system_id: &str, object_name: &str) {
+    unsafe {
+        OBJECTS_RELEVANCY.remove(&(system_id.to_string(), object_name.to_string()));
+    }
+}
+
+fn object_position(system_id: &str, object_name: &str) -> Option<Vec3> {
+    let system = systems::get_system_with_id(system_id)?;
+    let object = system.find_object(object_name)?;
+    Some(object.global_transform().position)
+}
@@ -0,0 +1,179 @@
+use std::{collections::{HashMap, VecDeque}, time::Instant};
+
+use glam::Vec3;
+use once_cell::sync::Lazy;
+
+use crate::{managers::systems, objects::{Object, Transform}};
+
+static START_TIME: Lazy<Instant> = Lazy::new(|| Instant::now());
+static mut INTERPOLATION_BUFFERS: Lazy<HashMap<u128, InterpolationBuffer>> = Lazy::new(|| HashMap::new());
+
+#[derive(Debug, Clone, Copy)]
+pub struct InterpolationSettings {
+    /// How far in the past (in seconds) the object is rendered. Should be bigger than the time between syncs.
+    pub delay: f32,
+    /// How long (in seconds) the object keeps moving with it's last velocity when no new syncs arrive.
+    pub max_extrapolation: f32,
+    /// If the object moved further than this between two syncs, it's teleported instead of interpolated.
+    pub snap_distance: f32,
+}
+
+impl Default for InterpolationSettings {
+    fn default() -> Self {
+        InterpolationSettings {
+            delay: 0.1,
+            max_extrapolation: 0.25,
+            snap_distance: 5.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TransformSample {
+    time: f32,
+    transform: Transform,
+}
+
+#[derive(Debug)]
+struct InterpolationBuffer {
+    settings: InterpolationSettings,
+    samples: VecDeque<TransformSample>,
+}
+
+/// Enables (Some) or disables (None) the interpolation of synced transforms for the object.
+pub fn set_interpolation(object_id: u128, settings: Option<InterpolationSettings>) {
+    unsafe {
+        match settings {
+            Some(settings) => match INTERPOLATION_BUFFERS.get_mut(&object_id) {
+                Some(buffer) => buffer.settings = settings,
+                None => {
+                    INTERPOLATION_BUFFERS.insert(
+                        object_id,
+                        InterpolationBuffer {
+                            settings,
+                            samples: VecDeque::new(),
+                        },
+                    );
+                }
+            },
+            None => {
+                INTERPOLATION_BUFFERS.remove(&object_id);
+            }
+        }
+    }
+}
+
+pub fn get_interpolation_settings(object_id: u128) -> Option<InterpolationSettings> {
+    unsafe {
+        INTERPOLATION_BUFFERS
+            .get(&object_id)
+            .map(|buffer| buffer.settings)
+    }
+}
+
+pub fn is_interpolated(object_id: u128) -> bool {
+    unsafe { INTERPOLATION_BUFFERS.contains_key(&object_id) }
+}
+
+/// Adds a new synced transform to the object's buffer.
+/// Returns false if the object is not interpolated, so the transform should be applied directly.
+pub fn push_transform(object_id: u128, transform: Transform) -> bool {
+    let time = current_time();
+
+    unsafe {
+        match INTERPOLATION_BUFFERS.get_mut(&object_id) {
+            Some(buffer) => {
+                if let Some(last_sample) = buffer.samples.back() {
+                    let distance = last_sample.transform.position.distance(transform.position);
+                    if distance > buffer.settings.snap_distance {
+                        buffer.samples.clear();
+                    }
+                }
+
+                buffer.samples.push_back(TransformSample { time, transform });
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Pushes the transform to the buffer of an object found by it's name.
+pub fn push_object_transform(system_id: &str, object_name: &str, transform: Transform) -> bool {
+    let object_id = match systems::get_system_with_id(system_id) {
+        Some(system) => match system.find_object(object_name) {
+            Some(object) => *object.object_id(),
+            None => return false,
+        },
+        None => return false,
+    };
+
+    push_transform(object_id, transform)
+}
+
+/// Returns the transform that the object should have right now, if it's interpolated and has received any syncs.
+pub fn sample_transform(object_id: u128) -> Option<Transform> {
+    let time = current_time();
+
+    unsafe {
+        let buffer = INTERPOLATION_BUFFERS.get_mut(&object_id)?;
+        let render_time = time - buffer.settings.delay;
+
+        // removing the samples that won't be used anymore
+        while buffer.samples.len() > 2 && buffer.samples[1].time <= render_time {
+            buffer.samples.pop_front();
+        }
+
+        let first = *buffer.samples.front()?;
+        if buffer.samples.len() == 1 || render_time <= first.time {
+            return Some(first.transform);
+        }
+
+        let second = buffer.samples[1];
+        if render_time <= second.time {
+            let t = (render_time - first.time) / (second.time - first.time).max(f32::EPSILON);
+            return Some(lerp_transform(&first.transform, &second.transform, t));
+        }
+
+        // no new samples, extrapolating using the last velocity
+        let extrapolation_time = (render_time - second.time).min(buffer.settings.max_extrapolation);
+        let t = 1.0 + extrapolation_time / (second.time - first.time).max(f32::EPSILON);
+        Some(lerp_transform(&first.transform, &second.transform, t))
+    }
+}
+
+pub fn remove_object(object_id: u128) {
+    unsafe {
+        INTERPOLATION_BUFFERS.remove(&object_id);
+    }
+}
+
+/// Removes the buffers of the object and all of it's children.
+pub fn remove_object_tree(object: &dyn Object) {
+    remove_object(*object.object_id());
+    for child in object.children_list() {
+        remove_object_tree(&**child);
+    }
+}
+
+fn current_time() -> f32 {
+    START_TIME.elapsed().as_secs_f32()
+}
+
+fn lerp_transform(from: &Transform, to: &Transform, t: f32) -> Transform {
+    Transform {
+        position: from.position.lerp(to.position, t),
+        rotation: lerp_rotation(from.rotation, to.rotation, t),
+        scale: from.scale.lerp(to.scale, t),
+    }
+}
+
+// rotation is in degrees, so going from 350 to 10 should be 20 degrees and not 340
+fn lerp_rotation(from: Vec3, to: Vec3, t: f32) -> Vec3 {
+    let mut difference = to - from;
+    difference.x = (difference.x + 180.0).rem_euclid(360.0) - 180.0;
+    difference.y = (difference.y + 180.0).rem_euclid(360.0) - 180.0;
+    difference.z = (difference.z + 180.0).rem_euclid(360.0) - 180.0;
+
+    from + difference * t
+}
@@ -0,0 +1,793 @@
+pub mod auth;
+pub mod conditioner;
+pub mod config;
+pub mod handshake;
+pub mod interest;
+pub mod interpolation;
+pub mod prediction;
+pub mod replication;
+pub mod rpc;
+pub mod spawn;
+pub mod stats;
+
+use crate::objects::Transform;
+use glam::Vec3;
+use auth::{ClientCredentials, NetworkAuthentication};
+use config::ServerSettings;
+use handshake::RejectReason;
+use machineid_rs::{HWIDComponent, IdBuilder};
+use renet::{
+    transport::{
+        ClientAuthentication, NetcodeClientTransport, NetcodeServerTransport, ServerAuthentication,
+        ServerConfig,
+    },
+    ConnectionConfig, DefaultChannel, DisconnectReason, RenetClient, RenetServer, ServerEvent,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    net::{IpAddr, SocketAddr, UdpSocket},
+    num::ParseIntError,
+    time::{Duration, SystemTime},
+};
+
+use super::{debugger, events, systems::{get_system_mut_with_id, SystemValue}};
+
+//static mut MAX_BYTES_PER_TICK: u64 = 100 * 1024 * 1024;
+static mut CURRENT_NETWORKING_MODE: NetworkingMode = NetworkingMode::Disconnected(None);
+static mut CURRENT_NETWORK_EVENTS: Vec<NetworkEvent> = vec![];
+static mut CLIENT_ID: u64 = 0;
+// Clients with a different protocol id can't connect and tokens issued for another protocol id are rejected.
+pub const DEFAULT_PROTOCOL_ID: u64 = 0x6d6b_6770_0000_0001;
+
+#[derive(Debug)]
+pub struct ServerHandle {
+    server: RenetServer,
+    transport: NetcodeServerTransport,
+}
+
+#[derive(Debug)]
+pub struct ClientHandle {
+    client: RenetClient,
+    transport: NetcodeClientTransport,
+    status: ClientStatus,
+}
+
+#[derive(Debug)]
+pub enum MessageReliability {
+    Reliable,
+    Unreliable,
+}
+
+impl MessageReliability {
+    pub fn channel_name(&self) -> &'static str {
+        match self {
+            MessageReliability::Reliable => "ReliableOrdered",
+            MessageReliability::Unreliable => "Unreliable",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum MessageReceiver {
+    Everybody,
+    EverybodyExcept(u64),
+    OneClient(u64),
+    /// Clients whose area of interest touches the sphere with this position and radius.
+    EverybodyNear(Vec3, f32),
+}
+
+#[derive(Debug)]
+pub enum NetworkingMode {
+    Server(ServerHandle),
+    Client(ClientHandle),
+    Disconnected(Option<DisconnectReason>),
+}
+
+#[derive(Debug)]
+pub enum ClientStatus {
+    Connecting,
+    /// Transport is connected, waiting for the server to accept the handshake.
+    Handshaking,
+    Connected,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Message {
+    //pub sender: Option<u64>,
+    //pub receiver: MessageReceiver,
+    pub message_type: MessageType,
+    pub system_id: String,
+    pub message_id: String,
+    pub contents: MessageContents,
+}
+impl Message {
+    pub fn new_from_server(
+        receiver: MessageReceiver,
+        contents: MessageContents,
+        system_id: String,
+        message_id: String,
+    ) -> Self {
+        Message {
+            message_type: MessageType::FromServer(receiver),
+            system_id,
+            message_id,
+            contents,
+        }
+    }
+
+    pub fn new_from_client(
+        contents: MessageContents,
+        system_id: String,
+        message_id: String,
+    ) -> Self {
+        Message {
+            message_type: MessageType::FromClient(unsafe { CLIENT_ID.to_string() }),
+            system_id,
+            message_id,
+            contents,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum MessageContents {
+    SyncObject(SyncObjectMessage),
+    Custom(Vec<SystemValue>),
+    Snapshot(replication::SnapshotMessage),
+    SnapshotAck(u32),
+    ControllerInput(prediction::ControllerInputMessage),
+    ControllerState(prediction::ControllerStateMessage),
+    RpcCall(rpc::RpcCallMessage),
+    RpcResponse(rpc::RpcResponseMessage),
+    SpawnObject(spawn::SpawnObjectMessage),
+    DespawnObject(spawn::DespawnObjectMessage),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum MessageType {
+    FromServer(MessageReceiver),
+    FromClient(String),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SyncObjectMessage {
+    pub object_name: String,
+    pub transform: Transform,
+}
+
+#[derive(Debug)]
+pub enum NetworkError {
+    MessageSerializeErr(serde_bare::error::Error),
+    NotDisconnected,
+    IsDisconnected,
+    SocketError(std::io::Error),
+    WrongClientStatus,
+}
+
+#[derive(Debug, Clone)]
+pub enum NetworkEvent {
+    ClientConnected(String),
+    ClientDisconnected(String, String),
+    ConnectedSuccessfully,
+    Disconnected(Option<DisconnectReason>),
+    /// Server: the client failed the handshake and is going to be disconnected.
+    ClientRejected(String, RejectReason),
+    /// Client: the server refused the connection.
+    Rejected(RejectReason),
+}
+
+fn set_current_networking_mode(mode: NetworkingMode) {
+    unsafe {
+        CURRENT_NETWORKING_MODE = mode;
+    }
+}
+
+pub fn get_current_networking_mode() -> &'static NetworkingMode {
+    unsafe { &CURRENT_NETWORKING_MODE }
+}
+
+pub fn new_server(settings: &ServerSettings, authentication: NetworkAuthentication) -> Result<(), NetworkError> {
+    match get_current_networking_mode() {
+        NetworkingMode::Disconnected(_) => (),
+        _ => {
+            debugger::error(&format!(
+                "new_server call error!\nCURRENT_NETWORK_MODE is not Disconnected"
+            ));
+            return Err(NetworkError::NotDisconnected);
+        }
+    }
+
+    let bind_address: SocketAddr = SocketAddr::new(settings.bind_address, settings.port);
+    let public_address: SocketAddr = SocketAddr::new(settings.public_address(), settings.port);
+    let cfg = ConnectionConfig::default();
+
+    let server = RenetServer::new(cfg);
+    let socket: UdpSocket = match UdpSocket::bind(bind_address) {
+        Ok(socket) => socket,
+        Err(err) => {
+            debugger::error(&format!(
+                "new_server call error!\nfailed to bind the socket to {}\nerr: {}",
+                bind_address, err
+            ));
+            return Err(NetworkError::SocketError(err));
+        }
+    };
+
+    let authentication = match authentication {
+        NetworkAuthentication::Secure { private_key } => ServerAuthentication::Secure { private_key },
+        NetworkAuthentication::Insecure => {
+            debugger::warn("new_server warning!\nserver is running in insecure mode, anyone can connect with any client id");
+            ServerAuthentication::Unsecure
+        }
+    };
+
+    let server_config = ServerConfig {
+        max_clients: settings.max_players,
+        protocol_id: settings.protocol_id,
+        public_addr: public_address,
+        authentication,
+    };
+    let current_time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap();
+    let transport = NetcodeServerTransport::new(current_time, server_config, socket).unwrap();
+
+    let handle = ServerHandle { server, transport };
+    println!("creating server");
+    set_current_networking_mode(NetworkingMode::Server(handle));
+    Ok(())
+}
+
+pub fn new_client(ip_address: IpAddr, port: u16, protocol_id: u64, credentials: ClientCredentials) -> Result<(), NetworkError> {
+    match get_current_networking_mode() {
+        NetworkingMode::Disconnected(_) => (),
+        _ => {
+            debugger::error(&format!(
+                "new_client call error!\nCURRENT_NETWORK_MODE is not Disconnected"
+            ));
+            return Err(NetworkError::NotDisconnected);
+        }
+    }
+
+    let cfg = ConnectionConfig::default();
+
+    let client = RenetClient::new(cfg);
+
+    let server_addr: SocketAddr = SocketAddr::new(ip_address, port);
+    // with a token the client connects to the addresses from it, so the socket should match their family
+    let target_address = match &credentials {
+        ClientCredentials::Token(connect_token) => connect_token
+            .server_addresses
+            .iter()
+            .flatten()
+            .next()
+            .map(|address| address.ip())
+            .unwrap_or(ip_address),
+        ClientCredentials::Insecure { .. } => ip_address,
+    };
+    let local_address = match (target_address.is_ipv6(), target_address.is_loopback()) {
+        (false, true) => "127.0.0.1:0",
+        (false, false) => "0.0.0.0:0",
+        (true, true) => "[::1]:0",
+        (true, false) => "[::]:0",
+    };
+    let socket: UdpSocket = match UdpSocket::bind(local_address) {
+        Ok(socket) => socket,
+        Err(err) => {
+            debugger::error(&format!(
+                "new_client call error!\nfailed to bind the socket to {}\nerr: {}",
+                local_address, err
+            ));
+            return Err(NetworkError::SocketError(err));
+        }
+    };
+    let current_time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap();
+    let auth = match credentials {
+        ClientCredentials::Token(connect_token) => {
+            unsafe { CLIENT_ID = connect_token.client_id }
+            ClientAuthentication::Secure { connect_token }
+        }
+        ClientCredentials::Insecure { client_id, player_id } => {
+            unsafe { CLIENT_ID = client_id }
+            let user_data = match player_id {
+                Some(player_id) => match auth::player_id_to_user_data(&player_id) {
+                    Ok(user_data) => Some(user_data),
+                    Err(err) => {
+                        debugger::error(&format!("new_client call error!\ninvalid player id\nerr: {}", err));
+                        None
+                    }
+                },
+                None => None,
+            };
+            ClientAuthentication::Unsecure {
+                protocol_id,
+                client_id,
+                server_addr,
+                user_data,
+            }
+        }
+    };
+
+    let transport = NetcodeClientTransport::new(current_time, auth, socket).unwrap();
+
+    reset_client_state();
+    let status = ClientStatus::Connecting;
+    let handle = ClientHandle {
+        client,
+        transport,
+        status,
+    };
+    set_current_networking_mode(NetworkingMode::Client(handle));
+    Ok(())
+}
+
+impl ServerHandle {
+    pub fn send_message(
+        &mut self,
+        message_reliability: MessageReliability,
+        message: Message,
+    ) -> Result<(), NetworkError> {
+        let renet_message_reliability = match message_reliability {
+            MessageReliability::Reliable => DefaultChannel::ReliableOrdered,
+            MessageReliability::Unreliable => DefaultChannel::Unreliable,
+        };
+
+        let message_bytes_vec = match serde_bare::to_vec(&message) {
+            Ok(vec) => vec,
+            Err(err) => {
+                debugger::error(&format!("got an error when calling send_message in ServerHandle\nfailed to serialize message to bytes vec\nerr: {}", err));
+                return Err(NetworkError::MessageSerializeErr(err));
+            }
+        };
+        stats::record_sent(message_reliability.channel_name(), &message.system_id, message_bytes_vec.len());
+
+        let receivers = match &message.message_type {
+            MessageType::FromServer(receiver) => match receiver {
+                MessageReceiver::Everybody => handshake::get_accepted_clients(),
+                MessageReceiver::EverybodyExcept(except_client_id) => handshake::get_accepted_clients()
+                    .into_iter()
+                    .filter(|client_id| client_id != except_client_id)
+                    .collect(),
+                MessageReceiver::OneClient(client_id) => vec![*client_id],
+                MessageReceiver::EverybodyNear(position, radius) => {
+                    interest::clients_near(&handshake::get_accepted_clients(), *position, *radius)
+                }
+            },
+            _ => vec![],
+        };
+
+        // syncs of objects are not sent to the clients that are too far from them
+        let receivers: Vec<u64> = match (&message.message_type, &message.contents) {
+            (MessageType::FromServer(MessageReceiver::OneClient(_)), _) => receivers,
+            (_, MessageContents::SyncObject(sync_message)) => receivers
+                .into_iter()
+                .filter(|client_id| {
+                    interest::is_object_relevant(*client_id, &message.system_id, &sync_message.object_name)
+                })
+                .collect(),
+            _ => receivers,
+        };
+
+        let channel_id: u8 = renet_message_reliability.into();
+        for client_id in receivers {
+            self.server
+                .send_message(client_id, channel_id, message_bytes_vec.clone());
+        }
+
+        Ok(())
+    }
+
+    pub fn update(&mut self, delta_time: Duration) {
+        self.server.update(delta_time);
+        match self.transport.update(delta_time, &mut self.server) {
+            Ok(_) => (),
+            Err(err) => debugger::warn(&format!(
+                "failed to update server transport\nerror: {}",
+                err
+            )),
+        }
+
+        while let Some(ev) = self.server.get_event() {
+            match ev {
+                ServerEvent::ClientConnected { client_id } => {
+                    println!("client connected, waiting for the handshake! client_id: {}", client_id);
+                    auth::client_connected(client_id, self.transport.user_data(client_id));
+                    handshake::client_connected(client_id);
+                }
+                ServerEvent::ClientDisconnected { client_id, reason } => {
+                    println!("client disconnected! client_id: {}", client_id);
+                    replication::forget_client(client_id);
+                    interest::forget_client(client_id);
+                    spawn::forget_client(client_id);
+                    auth::client_disconnected(client_id);
+                    if handshake::client_disconnected(client_id) {
+                        set_network_event(NetworkEvent::ClientDisconnected(
+                            client_id.to_string(),
+                            reason.to_string(),
+                        ));
+                    }
+                }
+            }
+        }
+
+        handshake::server_update(&mut self.server);
+
+        // messages of the clients that haven't passed the handshake yet can't be trusted to be deserialized correctly
+        for client_id in handshake::get_accepted_clients() {
+            while let Some(message_bytes) = self
+                .server
+                .receive_message(client_id, DefaultChannel::ReliableOrdered)
+            {
+                stats::record_received_on_channel(MessageReliability::Reliable.channel_name(), message_bytes.len());
+                conditioner::receive_message(message_bytes.into(), true, Some(client_id));
+            }
+
+            while let Some(message_bytes) = self
+                .server
+                .receive_message(client_id, DefaultChannel::Unreliable)
+            {
+                stats::record_received_on_channel(MessageReliability::Unreliable.channel_name(), message_bytes.len());
+                conditioner::receive_message(message_bytes.into(), false, Some(client_id));
+            }
+        }
+
+        self.transport.send_packets(&mut self.server);
+    }
+}
+
+impl ClientHandle {
+    pub fn send_message(
+        &mut self,
+        message_reliability: MessageReliability,
+        message: Message,
+    ) -> Result<(), NetworkError> {
+        match self.status {
+            ClientStatus::Connected => (),
+            _ => {
+                debugger::error(&format!(
+                    "failed to send message\nclient status is not Connected\nstatus: {:?}",
+                    self.status
+                ));
+                return Err(NetworkError::WrongClientStatus);
+            }
+        }
+
+        let renet_message_reliability = match message_reliability {
+            MessageReliability::Reliable => DefaultChannel::ReliableOrdered,
+            MessageReliability::Unreliable => DefaultChannel::Unreliable,
+        };
+
+        let message_bytes_vec = match serde_bare::to_vec(&message) {
+            Ok(vec) => vec,
+            Err(err) => {
+                debugger::error(&format!("got an error when calling send_message in ServerHandle\nfailed to serialize message to bytes vec\nerr: {}", err));
+                return Err(NetworkError::MessageSerializeErr(err));
+            }
+        };
+        stats::record_sent(message_reliability.channel_name(), &message.system_id, message_bytes_vec.len());
+
+        self.client
+            .send_message(renet_message_reliability, message_bytes_vec);
+        Ok(())
+    }
+
+    pub fn update(&mut self, delta_time: Duration) {
+        self.set_client_status();
+        match get_current_networking_mode() {
+            NetworkingMode::Disconnected(_) => return,
+            _ => (),
+        }
+
+        self.client.update(delta_time);
+        match self.transport.update(delta_time, &mut self.client) {
+            Ok(_) => (),
+            Err(err) => debugger::warn(&format!("failed to update client transport\nerr: {}", err)),
+        }
+
+        if let ClientStatus::Connected = self.status {
+            if let Some(Err(reason)) = handshake::receive_server_response(&mut self.client) {
+                println!("the server disconnected this client!\nreason: {}", reason);
+                set_network_event(NetworkEvent::Rejected(reason));
+                self.client.disconnect();
+            }
+        }
+
+        if let ClientStatus::Handshaking = self.status {
+            match handshake::receive_server_response(&mut self.client) {
+                Some(Ok(())) => {
+                    self.status = ClientStatus::Connected;
+                    set_network_event(NetworkEvent::ConnectedSuccessfully);
+                    println!("Connected successfully!");
+                }
+                Some(Err(reason)) => {
+                    println!("the server rejected the connection!\nreason: {}", reason);
+                    set_network_event(NetworkEvent::Rejected(reason));
+                    self.client.disconnect();
+                }
+                None => (),
+            }
+        }
+
+        // messages stay queued until the handshake is accepted
+        if let ClientStatus::Connected = self.status {
+            self.receive_messages();
+        }
+
+        match self.transport.send_packets(&mut self.client) {
+            Ok(_) => (),
+            Err(err) => debugger::warn(&format!("failed to send packets\nerr: {}", err)),
+        };
+    }
+
+    fn receive_messages(&mut self) {
+        while let Some(message_bytes) = self.client.receive_message(DefaultChannel::ReliableOrdered)
+        {
+            stats::record_received_on_channel(MessageReliability::Reliable.channel_name(), message_bytes.len());
+            conditioner::receive_message(message_bytes.into(), true, None);
+        }
+
+        while let Some(message_bytes) = self.client.receive_message(DefaultChannel::Unreliable) {
+            stats::record_received_on_channel(MessageReliability::Unreliable.channel_name(), message_bytes.len());
+            conditioner::receive_message(message_bytes.into(), false, None);
+        }
+    }
+
+    fn set_client_status(&mut self) {
+        if self.transport.is_connected() {
+            if let ClientStatus::Connecting = self.status {
+                handshake::send_hello(&mut self.client);
+                self.status = ClientStatus::Handshaking;
+            }
+        } else if self.transport.is_connecting() {
+            self.status = ClientStatus::Connecting
+        } else if let Some(reason) = self.client.disconnect_reason() {
+            reset_client_state();
+            set_network_event(NetworkEvent::Disconnected(Some(reason)));
+            set_current_networking_mode(NetworkingMode::Disconnected(Some(reason)));
+            println!("disconnected!\nreason: {}", reason);
+        } else if self.client.is_disconnected() {
+            reset_client_state();
+            set_network_event(NetworkEvent::Disconnected(None));
+            set_current_networking_mode(NetworkingMode::Disconnected(None));
+            println!("disconnected!\nreason is None");
+        }
+    }
+}
+
+// The state that the client got from the server is only valid during the connection.
+fn reset_client_state() {
+    replication::reset_client();
+    spawn::reset_client();
+}
+
+pub fn update(delta_time: Duration) {
+    unsafe {
+        CURRENT_NETWORK_EVENTS.clear();
+        auth::update();
+
+        match &mut CURRENT_NETWORKING_MODE {
+            NetworkingMode::Server(server) => {
+                interest::update();
+                server.update(delta_time);
+            }
+            NetworkingMode::Client(client) => client.update(delta_time),
+            NetworkingMode::Disconnected(_) => (),
+        }
+    }
+
+    conditioner::update();
+}
+
+pub fn send_message(reliability: MessageReliability, message: Message) -> Result<(), NetworkError> {
+    unsafe {
+        match &mut CURRENT_NETWORKING_MODE {
+            NetworkingMode::Server(server) => server.send_message(reliability, message),
+            NetworkingMode::Client(client) => client.send_message(reliability, message),
+            NetworkingMode::Disconnected(_) => {
+                debugger::error(&format!("can't send a message while being disconnected!"));
+                return Err(NetworkError::IsDisconnected);
+            }
+        }
+    }
+}
+
+/// The sender is the client that the server got the bytes from. It replaces the sender written in the message,
+/// because clients can put any id there.
+pub fn send_message_to_system(message_bytes: Vec<u8>, sender: Option<u64>) {
+    let mut message: Message = match serde_bare::from_slice(&message_bytes) {
+        Ok(message) => message,
+        Err(err) => {
+            debugger::error(&format!("networking manager error\ngot an error in update(client)!\nfailed to deserialize message\nerr: {}", err));
+            return;
+        }
+    };
+    stats::record_received_by_system(&message.system_id, message_bytes.len());
+    if let Some(client_id) = sender {
+        message.message_type = MessageType::FromClient(client_id.to_string());
+    }
+
+    let message = match replication::intercept_message(message) {
+        Some(message) => message,
+        None => return,
+    };
+    let message = match spawn::intercept_message(message) {
+        Some(message) => message,
+        None => return,
+    };
+    let message = match prediction::intercept_message(message) {
+        Some(message) => message,
+        None => return,
+    };
+    let message = match rpc::intercept_message(message) {
+        Some(message) => message,
+        None => return,
+    };
+
+    if let MessageContents::SyncObject(sync_message) = &message.contents {
+        if is_client() {
+            interpolation::push_object_transform(
+                &message.system_id,
+                &sync_message.object_name,
+                sync_message.transform,
+            );
+        }
+    }
+
+    match get_system_mut_with_id(&message.system_id.clone()) {
+        Some(system) => system.reg_message(message),
+        None => {
+            debugger::error(&format!("networking manager error\ngot an error in update(client)!\nfailed to get system {}", &message.system_id));
+            ()
+        }
+    }
+}
+
+pub(crate) fn set_network_event(event: NetworkEvent) {
+    // the same events on the event bus
+    match &event {
+        NetworkEvent::ClientConnected(client_id) => {
+            events::emit(None, events::CLIENT_CONNECTED, vec![SystemValue::String(client_id.clone())])
+        },
+        NetworkEvent::ClientDisconnected(client_id, reason) => events::emit(
+            None,
+            events::CLIENT_DISCONNECTED,
+            vec![SystemValue::String(client_id.clone()), SystemValue::String(reason.clone())],
+        ),
+        NetworkEvent::ConnectedSuccessfully => events::emit(None, events::CONNECTED, vec![]),
+        NetworkEvent::Disconnected(reason) => {
+            let reason = reason.as_ref().map(|reason| reason.to_string()).unwrap_or_default();
+            events::emit(None, events::DISCONNECTED, vec![SystemValue::String(reason)])
+        },
+        NetworkEvent::ClientRejected(_, _) | NetworkEvent::Rejected(_) => (),
+    }
+
+    unsafe {
+        CURRENT_NETWORK_EVENTS.push(event);
+    }
+}
+
+pub fn get_network_events() -> Vec<NetworkEvent> {
+    unsafe { CURRENT_NETWORK_EVENTS.clone() }
+}
+
+fn generate_client_id() -> u64 {
+    let mut id_in_binary = "".to_string();
+
+    let mut id_builer = IdBuilder::new(machineid_rs::Encryption::SHA256);
+    id_builer.add_component(HWIDComponent::CPUID);
+    id_builer.add_component(HWIDComponent::OSName);
+    id_builer.add_component(HWIDComponent::SystemID);
+
+    match id_builer.build("really great key") {
+        Ok(id_str) => {
+            for character in id_str.clone().into_bytes() {
+                if id_in_binary.len() < 18 {
+                    id_in_binary += &format!("{}", character);
+                } else {
+                    break;
+                }
+            }
+
+            let id_u64_result: Result<u64, ParseIntError> = id_in_binary.parse();
+            println!("CLIENT_ID: {:?}", id_u64_result);
+
+            match id_u64_result {
+                Ok(id) => return id,
+                Err(err) => {
+                    debugger::crash(&format!("Failed to generate CLIENT_ID! Error: {}", err));
+                    unreachable!()
+                }
+            }
+        }
+        Err(err) => {
+            debugger::crash(&format!("Failed to generate CLIENT_ID! Error: {}", err));
+            unreachable!();
+        }
+    }
+}
+
+pub fn is_server() -> bool {
+    match get_current_networking_mode() {
+        NetworkingMode::Server(_) => true,
+        NetworkingMode::Client(_) => false,
+        NetworkingMode::Disconnected(_) => false,
+    }
+}
+
+pub fn get_id() -> Option<String> {
+    unsafe {
+        match get_current_networking_mode() {
+            NetworkingMode::Server(_) => None,
+            NetworkingMode::Client(_) => Some(CLIENT_ID.to_string()),
+            NetworkingMode::Disconnected(_) => None,
+        }
+    }
+}
+
+pub fn get_connected_clients() -> Vec<u64> {
+    match get_current_networking_mode() {
+        NetworkingMode::Server(_) => handshake::get_accepted_clients(),
+        NetworkingMode::Client(_) => vec![],
+        NetworkingMode::Disconnected(_) => vec![],
+    }
+}
+
+/// Client: why the last connection was closed. Rejection by the server is preferred over the transport's reason.
+pub fn get_disconnect_reason() -> Option<String> {
+    if let Some(reason) = handshake::get_reject_reason() {
+        return Some(reason.to_string());
+    }
+
+    match get_current_networking_mode() {
+        NetworkingMode::Disconnected(Some(reason)) => Some(reason.to_string()),
+        _ => None,
+    }
+}
+
+pub fn is_client() -> bool {
+    match get_current_networking_mode() {
+        NetworkingMode::Server(_) => false,
+        NetworkingMode::Client(_) => true,
+        NetworkingMode::Disconnected(_) => false,
+    }
+}
+
+/// Server: tells the client why it's disconnected (see get_disconnect_reason) and disconnects it.
+pub fn disconnect_client(client_id: u64, reason: &str) {
+    unsafe {
+        match &mut CURRENT_NETWORKING_MODE {
+            NetworkingMode::Server(server) => handshake::kick_client(&mut server.server, client_id, reason),
+            _ => debugger::error("failed to disconnect the client!\ncurrent networking mode is not Server"),
+        }
+    }
+}
+
+/// Server: disconnects every connected client with the reason.
+pub fn disconnect_all_clients(reason: &str) {
+    for client_id in get_connected_clients() {
+        disconnect_client(client_id, reason);
+    }
+}
+
+/// Server: true while the clients disconnected with a reason are still connected.
+pub fn is_disconnecting_clients() -> bool {
+    is_server() && handshake::has_kicked_clients()
+}
+
+pub fn disconnect() {
+    unsafe {
+        match &mut CURRENT_NETWORKING_MODE {
+            NetworkingMode::Server(_) => {
+                debugger::error("failed to disconnect!\ncurrent networking mode is Server");
+            }
+            NetworkingMode::Client(client) => {
+                client.client.disconnect();
+            }
+            NetworkingMode::Disconnected(_) => {
+                debugger::error("failed to disconnect!\ncurrent networking mode is Disconnected");
+            }
+        }
+    }
+}
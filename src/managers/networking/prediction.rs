@@ -0,0 +1,157 @@
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    framework::Framework,
+    managers::{debugger, systems},
+    objects::character_controller::CharacterController,
+};
+
+use super::{
+    send_message, Message, MessageContents, MessageReceiver, MessageReliability, MessageType,
+};
+
+// Inputs that were sent but weren't acknowledged yet are sent again in every message,
+// so losing an unreliable message doesn't lose the input. That's the limit of how many are resent.
+const MAX_RESENT_INPUTS: usize = 32;
+// Server won't simulate a single input for longer than that, so a single message can't move the controller too far.
+// The speed itself is limited by the controller's max speed, see PredictionMode::Authoritative.
+pub const MAX_INPUT_DELTA_TIME: f32 = 0.1;
+// Max speed of authoritative controllers when it's not specified.
+pub const DEFAULT_MAX_INPUT_SPEED: f32 = 10.0;
+
+static mut RECEIVED_INPUTS: Vec<(u64, String, ControllerInputMessage)> = vec![];
+static mut RECEIVED_STATES: Vec<(String, ControllerStateMessage)> = vec![];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct InputCommand {
+    pub sequence: u32,
+    /// Movement direction in world space (rotation is already applied).
+    pub direction: Vec3,
+    pub delta_time: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ControllerInputMessage {
+    pub object_name: String,
+    pub commands: Vec<InputCommand>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ControllerStateMessage {
+    pub object_name: String,
+    pub last_sequence: u32,
+    pub position: Vec3,
+}
+
+/// Sends the unacknowledged inputs of a predicted controller to the server.
+pub fn send_inputs(system_id: &str, object_name: &str, commands: Vec<InputCommand>) {
+    let skip = commands.len().saturating_sub(MAX_RESENT_INPUTS);
+    let commands = commands.into_iter().skip(skip).collect();
+
+    let message = Message::new_from_client(
+        MessageContents::ControllerInput(ControllerInputMessage {
+            object_name: object_name.into(),
+            commands,
+        }),
+        system_id.into(),
+        String::new(),
+    );
+    let _ = send_message(MessageReliability::Unreliable, message);
+}
+
+/// Returns the message back if it's not related to prediction.
+pub(crate) fn intercept_message(message: Message) -> Option<Message> {
+    match message.contents {
+        MessageContents::ControllerInput(input) => {
+            match &message.message_type {
+                MessageType::FromClient(client_id) => match client_id.parse::<u64>() {
+                    Ok(client_id) => unsafe {
+                        RECEIVED_INPUTS.push((client_id, message.system_id, input));
+                    },
+                    Err(err) => debugger::error(&format!(
+                        "prediction error!\nfailed to parse the id of a client that sent inputs\nerr: {}",
+                        err
+                    )),
+                },
+                MessageType::FromServer(_) => debugger::error(
+                    "prediction error!\ngot controller inputs from the server, only clients can send them",
+                ),
+            }
+            None
+        }
+        MessageContents::ControllerState(state) => {
+            unsafe {
+                RECEIVED_STATES.push((message.system_id, state));
+            }
+            None
+        }
+        _ => Some(message),
+    }
+}
+
+/// Server: replays the received inputs and sends the acknowledged state back.
+/// Client: reconciles the predicted controllers with the received states.
+pub fn update(framework: &mut Framework) {
+    let received_inputs: Vec<(u64, String, ControllerInputMessage)> =
+        unsafe { RECEIVED_INPUTS.drain(..).collect() };
+    for (client_id, system_id, input) in received_inputs {
+        let state = match find_controller(&system_id, &input.object_name) {
+            Some(controller) => controller.apply_remote_inputs(framework, client_id, &input.commands),
+            None => None,
+        };
+
+        if let Some((last_sequence, position)) = state {
+            let message = Message::new_from_server(
+                MessageReceiver::OneClient(client_id),
+                MessageContents::ControllerState(ControllerStateMessage {
+                    object_name: input.object_name,
+                    last_sequence,
+                    position,
+                }),
+                system_id,
+                String::new(),
+            );
+            let _ = send_message(MessageReliability::Unreliable, message);
+        }
+    }
+
+    let received_states: Vec<(String, ControllerStateMessage)> =
+        unsafe { RECEIVED_STATES.drain(..).collect() };
+    for (system_id, state) in received_states {
+        if let Some(controller) = find_controller(&system_id, &state.object_name) {
+            controller.reconcile(framework, state.last_sequence, state.position);
+        }
+    }
+}
+
+fn find_controller<'a>(system_id: &'a str, object_name: &str) -> Option<&'a mut CharacterController> {
+    match systems::get_system_mut_with_id(system_id) {
+        Some(system) => match system.find_object_mut(object_name) {
+            Some(object) => match object.downcast_mut::<CharacterController>() {
+                Some(controller) => Some(controller),
+                None => {
+                    debugger::error(&format!(
+                        "prediction error!\nobject '{}' in system '{}' is not a CharacterController",
+                        object_name, system_id
+                    ));
+                    None
+                }
+            },
+            None => {
+                debugger::warn(&format!(
+                    "prediction warning!\nfailed to find CharacterController '{}' in system '{}'",
+                    object_name, system_id
+                ));
+                None
+            }
+        },
+        None => {
+            debugger::warn(&format!(
+                "prediction warning!\nfailed to find system '{}' to get CharacterController '{}'",
+                system_id, object_name
+            ));
+            None
+        }
+    }
+}
@@ -0,0 +1,520 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use glam::Vec3;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    framework::Framework,
+    managers::{debugger, systems},
+    objects::{character_controller::CharacterController, Transform},
+};
+
+use super::{
+    get_connected_clients, interest, interpolation, is_client, is_server, send_message, Message,
+    MessageContents, MessageReceiver, MessageReliability, MessageType,
+};
+
+// How many snapshots are kept to be used as a baseline for delta compression.
+// If the last acknowledged snapshot of some client is older than that, the client gets a full snapshot.
+const SNAPSHOT_HISTORY_SIZE: usize = 64;
+// Transform components that changed less than this are not sent.
+const TRANSFORM_EPSILON: f32 = 0.0001;
+pub const REPLICATION_SYSTEM_ID: &str = "replication";
+
+static mut REPLICATED_OBJECTS: Vec<ReplicationKey> = vec![];
+static mut CURRENT_TICK: u32 = 0;
+// server: snapshots that were sent to clients
+// client: snapshots reconstructed from the received deltas
+static mut SNAPSHOTS_HISTORY: VecDeque<WorldSnapshot> = VecDeque::new();
+static mut CLIENTS_ACKED_TICKS: Lazy<HashMap<u64, u32>> = Lazy::new(|| HashMap::new());
+// objects that were relevant for the client in the sent snapshots, None means that all of them were
+static mut CLIENTS_RELEVANT_OBJECTS: Lazy<HashMap<u64, VecDeque<(u32, Option<HashSet<ReplicationKey>>)>>> =
+    Lazy::new(|| HashMap::new());
+static mut RECEIVED_SNAPSHOTS: Vec<SnapshotMessage> = vec![];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ReplicationKey {
+    pub system_id: String,
+    pub object_name: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct WorldSnapshot {
+    pub tick: u32,
+    pub objects: HashMap<ReplicationKey, Transform>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TransformDelta {
+    pub position: Option<Vec3>,
+    pub rotation: Option<Vec3>,
+    pub scale: Option<Vec3>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SnapshotMessage {
+    pub tick: u32,
+    /// None means that the snapshot is full and doesn't depend on any previous one.
+    pub baseline_tick: Option<u32>,
+    pub changed: Vec<(ReplicationKey, TransformDelta)>,
+    pub removed: Vec<ReplicationKey>,
+}
+
+/// Marks the object as replicated. Server will send it's transform to all clients every tick.
+pub fn replicate_object(system_id: &str, object_name: &str) {
+    let key = ReplicationKey {
+        system_id: system_id.into(),
+        object_name: object_name.into(),
+    };
+
+    unsafe {
+        if !REPLICATED_OBJECTS.contains(&key) {
+            REPLICATED_OBJECTS.push(key);
+        }
+    }
+}
+
+pub fn stop_replicating_object(system_id: &str, object_name: &str) {
+    unsafe {
+        REPLICATED_OBJECTS
+            .retain(|key| key.system_id != system_id || key.object_name != object_name);
+    }
+}
+
+pub fn is_object_replicated(system_id: &str, object_name: &str) -> bool {
+    unsafe {
+        REPLICATED_OBJECTS
+            .iter()
+            .any(|key| key.system_id == system_id && key.object_name == object_name)
+    }
+}
+
+/// Builds and sends snapshots when running as a server, applies the received ones when running as a client.
+pub fn update(framework: &mut Framework) {
+    if is_server() {
+        server_update();
+    } else if is_client() {
+        client_update(framework);
+    }
+}
+
+pub(crate) fn forget_client(client_id: u64) {
+    unsafe {
+        CLIENTS_ACKED_TICKS.remove(&client_id);
+        CLIENTS_RELEVANT_OBJECTS.remove(&client_id);
+    }
+}
+
+/// Returns the message back if it's not related to replication.
+pub(crate) fn intercept_message(message: Message) -> Option<Message> {
+    match message.contents {
+        MessageContents::Snapshot(snapshot) => {
+            unsafe {
+                RECEIVED_SNAPSHOTS.push(snapshot);
+            }
+            None
+        }
+        MessageContents::SnapshotAck(tick) => {
+            if let MessageType::FromClient(client_id) = &message.message_type {
+                match client_id.parse::<u64>() {
+                    Ok(client_id) => unsafe {
+                        match CLIENTS_ACKED_TICKS.get_mut(&client_id) {
+                            Some(acked_tick) => *acked_tick = (*acked_tick).max(tick),
+                            None => {
+                                CLIENTS_ACKED_TICKS.insert(client_id, tick);
+                            }
+                        }
+                    },
+                    Err(err) => debugger::error(&format!(
+                        "replication error!\nfailed to parse the id of a client that sent snapshot ack\nerr: {}",
+                        err
+                    )),
+                }
+            }
+            None
+        }
+        _ => Some(message),
+    }
+}
+
+fn server_update() {
+    let snapshot = unsafe {
+        CURRENT_TICK = CURRENT_TICK.wrapping_add(1);
+        build_snapshot(CURRENT_TICK)
+    };
+
+    for client_id in get_connected_clients() {
+        let baseline = unsafe {
+            match CLIENTS_ACKED_TICKS.get(&client_id) {
+                Some(acked_tick) => SNAPSHOTS_HISTORY
+                    .iter()
+                    .find(|snapshot| snapshot.tick == *acked_tick)
+                    .map(|baseline| filter_snapshot(baseline, relevant_objects_at_tick(client_id, baseline.tick))),
+                None => None,
+            }
+        };
+
+        let relevant_objects = relevant_objects(client_id, &snapshot);
+        let client_snapshot = filter_snapshot(&snapshot, relevant_objects.as_ref());
+        remember_relevant_objects(client_id, snapshot.tick, relevant_objects);
+
+        let snapshot_message = delta_snapshot(baseline.as_ref(), &client_snapshot);
+        let message = Message::new_from_server(
+            MessageReceiver::OneClient(client_id),
+            MessageContents::Snapshot(snapshot_message),
+            REPLICATION_SYSTEM_ID.into(),
+            String::new(),
+        );
+        let _ = send_message(MessageReliability::Unreliable, message);
+    }
+
+    push_snapshot_to_history(snapshot);
+}
+
+/// Client: forgets the snapshots of the previous connection. The server of the next one starts from it's own tick,
+/// so the old snapshots would make all of the new ones look stale.
+pub(crate) fn reset_client() {
+    unsafe {
+        SNAPSHOTS_HISTORY.clear();
+        RECEIVED_SNAPSHOTS.clear();
+    }
+}
+
+fn client_update(framework: &mut Framework) {
+    let mut received_snapshots: Vec<SnapshotMessage> =
+        unsafe { RECEIVED_SNAPSHOTS.drain(..).collect() };
+    received_snapshots.sort_by_key(|snapshot| snapshot.tick);
+
+    for snapshot_message in received_snapshots {
+        let previous_snapshot = unsafe { SNAPSHOTS_HISTORY.back().cloned() };
+        let snapshot = match reconstruct_snapshot(snapshot_message) {
+            Some(snapshot) => snapshot,
+            None => continue,
+        };
+        apply_snapshot(framework, previous_snapshot.as_ref(), &snapshot);
+
+        let ack = Message::new_from_client(
+            MessageContents::SnapshotAck(snapshot.tick),
+            REPLICATION_SYSTEM_ID.into(),
+            String::new(),
+        );
+        let _ = send_message(MessageReliability::Unreliable, ack);
+
+        push_snapshot_to_history(snapshot);
+    }
+}
+
+// Applies the delta to it's baseline from the history. None if the snapshot is stale or the baseline is gone.
+fn reconstruct_snapshot(snapshot_message: SnapshotMessage) -> Option<WorldSnapshot> {
+    unsafe {
+        if let Some(previous_snapshot) = SNAPSHOTS_HISTORY.back() {
+            if snapshot_message.tick <= previous_snapshot.tick {
+                return None;
+            }
+        }
+    }
+
+    let mut objects = match snapshot_message.baseline_tick {
+        Some(baseline_tick) => {
+            let baseline = unsafe {
+                SNAPSHOTS_HISTORY
+                    .iter()
+                    .find(|snapshot| snapshot.tick == baseline_tick)
+            };
+            // if we don't have the baseline anymore, waiting for the server to send a newer one
+            baseline?.objects.clone()
+        }
+        None => HashMap::new(),
+    };
+
+    for key in snapshot_message.removed {
+        objects.remove(&key);
+    }
+
+    for (key, delta) in snapshot_message.changed {
+        let mut transform = objects.get(&key).cloned().unwrap_or_default();
+        if let Some(position) = delta.position {
+            transform.position = position;
+        }
+        if let Some(rotation) = delta.rotation {
+            transform.rotation = rotation;
+        }
+        if let Some(scale) = delta.scale {
+            transform.scale = scale;
+        }
+        objects.insert(key, transform);
+    }
+
+    Some(WorldSnapshot {
+        tick: snapshot_message.tick,
+        objects,
+    })
+}
+
+fn build_snapshot(tick: u32) -> WorldSnapshot {
+    let mut objects = HashMap::new();
+
+    unsafe {
+        // objects that were deleted are not replicated anymore
+        REPLICATED_OBJECTS.retain(|key| {
+            let transform = match systems::get_system_with_id(&key.system_id) {
+                Some(system) => match system.find_object(&key.object_name) {
+                    Some(object) => Some(object.local_transform()),
+                    None => None,
+                },
+                None => None,
+            };
+
+            match transform {
+                Some(transform) => {
+                    objects.insert(key.clone(), transform);
+                    true
+                }
+                None => false,
+            }
+        });
+    }
+
+    WorldSnapshot { tick, objects }
+}
+
+// None if the client doesn't have an area of interest and gets everything
+fn relevant_objects(client_id: u64, snapshot: &WorldSnapshot) -> Option<HashSet<ReplicationKey>> {
+    interest::get_client_interest(client_id)?;
+
+    Some(
+        snapshot
+            .objects
+            .keys()
+            .filter(|key| interest::is_object_relevant(client_id, &key.system_id, &key.object_name))
+            .cloned()
+            .collect(),
+    )
+}
+
+fn relevant_objects_at_tick(client_id: u64, tick: u32) -> Option<&'static HashSet<ReplicationKey>> {
+    unsafe {
+        let history = CLIENTS_RELEVANT_OBJECTS.get(&client_id)?;
+        let (_, relevant_objects) = history.iter().find(|(history_tick, _)| *history_tick == tick)?;
+        relevant_objects.as_ref()
+    }
+}
+
+fn remember_relevant_objects(client_id: u64, tick: u32, relevant_objects: Option<HashSet<ReplicationKey>>) {
+    unsafe {
+        if !CLIENTS_RELEVANT_OBJECTS.contains_key(&client_id) {
+            CLIENTS_RELEVANT_OBJECTS.insert(client_id, VecDeque::new());
+        }
+        if let Some(history) = CLIENTS_RELEVANT_OBJECTS.get_mut(&client_id) {
+            history.push_back((tick, relevant_objects));
+            while history.len() > SNAPSHOT_HISTORY_SIZE {
+                history.pop_front();
+            }
+        }
+    }
+}
+
+fn filter_snapshot(snapshot: &WorldSnapshot, relevant_objects: Option<&HashSet<ReplicationKey>>) -> WorldSnapshot {
+    match relevant_objects {
+        Some(relevant_objects) => WorldSnapshot {
+            tick: snapshot.tick,
+            objects: snapshot
+                .objects
+                .iter()
+                .filter(|(key, _)| relevant_objects.contains(key))
+                .map(|(key, transform)| (key.clone(), *transform))
+                .collect(),
+        },
+        None => snapshot.clone(),
+    }
+}
+
+fn delta_snapshot(baseline: Option<&WorldSnapshot>, snapshot: &WorldSnapshot) -> SnapshotMessage {
+    let mut changed = Vec::new();
+    let mut removed = Vec::new();
+
+    for (key, transform) in &snapshot.objects {
+        let baseline_transform = match baseline {
+            Some(baseline) => baseline.objects.get(key),
+            None => None,
+        };
+
+        let delta = match baseline_transform {
+            Some(baseline_transform) => TransformDelta {
+                position: changed_vector(baseline_transform.position, transform.position),
+                rotation: changed_vector(baseline_transform.rotation, transform.rotation),
+                scale: changed_vector(baseline_transform.scale, transform.scale),
+            },
+            None => TransformDelta {
+                position: Some(transform.position),
+                rotation: Some(transform.rotation),
+                scale: Some(transform.scale),
+            },
+        };
+
+        if delta.position.is_some() || delta.rotation.is_some() || delta.scale.is_some() {
+            changed.push((key.clone(), delta));
+        }
+    }
+
+    if let Some(baseline) = baseline {
+        for key in baseline.objects.keys() {
+            if !snapshot.objects.contains_key(key) {
+                removed.push(key.clone());
+            }
+        }
+    }
+
+    SnapshotMessage {
+        tick: snapshot.tick,
+        baseline_tick: baseline.map(|baseline| baseline.tick),
+        changed,
+        removed,
+    }
+}
+
+fn changed_vector(old: Vec3, new: Vec3) -> Option<Vec3> {
+    match old.abs_diff_eq(new, TRANSFORM_EPSILON) {
+        true => None,
+        false => Some(new),
+    }
+}
+
+fn apply_snapshot(framework: &mut Framework, previous: Option<&WorldSnapshot>, snapshot: &WorldSnapshot) {
+    for (key, transform) in &snapshot.objects {
+        if let Some(previous) = previous {
+            if previous.objects.get(key) == Some(transform) {
+                continue;
+            }
+        }
+
+        match systems::get_system_mut_with_id(&key.system_id) {
+            Some(system) => match system.find_object_mut(&key.object_name) {
+                Some(object) => {
+                    // predicted controllers are corrected by reconciliation, not by snapshots
+                    if let Some(controller) = object.downcast_ref::<CharacterController>() {
+                        if controller.is_predicted() {
+                            continue;
+                        }
+                    }
+
+                    if interpolation::push_transform(*object.object_id(), *transform) {
+                        continue;
+                    }
+
+                    object.set_position(framework, transform.position, true);
+                    object.set_rotation(framework, transform.rotation, true);
+                    object.set_scale(framework, transform.scale, true);
+                }
+                None => debugger::warn(&format!(
+                    "replication warning!\nfailed to apply a snapshot to object '{}' in system '{}'\nobject not found",
+                    key.object_name, key.system_id
+                )),
+            },
+            None => debugger::warn(&format!(
+                "replication warning!\nfailed to apply a snapshot to object '{}'\nsystem '{}' not found",
+                key.object_name, key.system_id
+            )),
+        }
+    }
+}
+
+fn push_snapshot_to_history(snapshot: WorldSnapshot) {
+    unsafe {
+        SNAPSHOTS_HISTORY.push_back(snapshot);
+        while SNAPSHOTS_HISTORY.len() > SNAPSHOT_HISTORY_SIZE {
+            SNAPSHOTS_HISTORY.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(object_name: &str) -> ReplicationKey {
+        ReplicationKey {
+            system_id: "test".into(),
+            object_name: object_name.into(),
+        }
+    }
+
+    fn snapshot(tick: u32, objects: Vec<(&str, Vec3)>) -> WorldSnapshot {
+        WorldSnapshot {
+            tick,
+            objects: objects
+                .into_iter()
+                .map(|(object_name, position)| (key(object_name), Transform { position, ..Default::default() }))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn delta_without_baseline_is_full() {
+        let message = delta_snapshot(None, &snapshot(1, vec![("a", Vec3::ONE)]));
+
+        assert_eq!(message.baseline_tick, None);
+        assert_eq!(message.changed.len(), 1);
+        let (_, delta) = &message.changed[0];
+        assert_eq!(delta.position, Some(Vec3::ONE));
+        assert!(delta.rotation.is_some() && delta.scale.is_some());
+        assert!(message.removed.is_empty());
+    }
+
+    #[test]
+    fn delta_has_only_changed_components() {
+        let baseline = snapshot(1, vec![("a", Vec3::ZERO), ("b", Vec3::ZERO)]);
+        let message = delta_snapshot(Some(&baseline), &snapshot(2, vec![("a", Vec3::X), ("b", Vec3::ZERO)]));
+
+        assert_eq!(message.tick, 2);
+        assert_eq!(message.baseline_tick, Some(1));
+        assert_eq!(message.changed.len(), 1);
+        let (changed_key, delta) = &message.changed[0];
+        assert_eq!(*changed_key, key("a"));
+        assert_eq!(delta.position, Some(Vec3::X));
+        assert_eq!(delta.rotation, None);
+        assert_eq!(delta.scale, None);
+    }
+
+    #[test]
+    fn delta_ignores_changes_below_epsilon() {
+        let baseline = snapshot(1, vec![("a", Vec3::ZERO)]);
+        let message = delta_snapshot(Some(&baseline), &snapshot(2, vec![("a", Vec3::splat(TRANSFORM_EPSILON / 2.0))]));
+
+        assert!(message.changed.is_empty());
+    }
+
+    #[test]
+    fn delta_lists_removed_objects() {
+        let baseline = snapshot(1, vec![("a", Vec3::ZERO), ("b", Vec3::ZERO)]);
+        let message = delta_snapshot(Some(&baseline), &snapshot(2, vec![("a", Vec3::ZERO)]));
+
+        assert!(message.changed.is_empty());
+        assert_eq!(message.removed, vec![key("b")]);
+    }
+
+    #[test]
+    fn reconnecting_accepts_lower_ticks() {
+        reset_client();
+        let first_connection = reconstruct_snapshot(delta_snapshot(None, &snapshot(100, vec![("a", Vec3::X)]))).unwrap();
+        push_snapshot_to_history(first_connection);
+
+        // the restarted server counts it's ticks from the beginning
+        let full_snapshot = delta_snapshot(None, &snapshot(1, vec![("b", Vec3::Y)]));
+        assert!(reconstruct_snapshot(full_snapshot.clone()).is_none());
+
+        reset_client();
+        let second_connection = reconstruct_snapshot(full_snapshot).unwrap();
+        assert_eq!(second_connection.tick, 1);
+        assert!(second_connection.objects.contains_key(&key("b")));
+        assert!(!second_connection.objects.contains_key(&key("a")));
+        push_snapshot_to_history(second_connection);
+
+        let baseline = snapshot(1, vec![("b", Vec3::Y)]);
+        let delta = delta_snapshot(Some(&baseline), &snapshot(2, vec![("b", Vec3::Z)]));
+        let next_snapshot = reconstruct_snapshot(delta).unwrap();
+        assert_eq!(next_snapshot.objects[&key("b")].position, Vec3::Z);
+        reset_client();
+    }
+}
@@ -0,0 +1,498 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    is_client, is_server, send_message, Message, MessageContents, MessageReceiver,
+    MessageReliability, MessageType,
+};
+use crate::{
+    framework::Framework,
+    managers::{
+        debugger,
+        systems::{self, SystemValue},
+    },
+};
+
+// If the response doesn't arrive in this time, the caller gets an error.
+const RPC_RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+static mut RPC_SIGNATURES: Lazy<HashMap<(String, String), Vec<RpcArgType>>> = Lazy::new(|| HashMap::new());
+static mut NEXT_CALL_ID: u32 = 0;
+static mut PENDING_CALLS: Lazy<HashMap<u32, PendingCall>> = Lazy::new(|| HashMap::new());
+static mut RECEIVED_CALLS: Vec<(String, Option<u64>, RpcCallMessage)> = vec![];
+// responses with the client that sent them, None if it's the server
+static mut RECEIVED_RESPONSES: Vec<(Option<u64>, RpcResponseMessage)> = vec![];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RpcArgType {
+    String,
+    Int,
+    UInt,
+    Float,
+    Bool,
+    Spline,
+    Vec,
+    Map,
+    Int64,
+    Vec2,
+    Vec3,
+    Quat,
+    Bytes,
+    Nil,
+    Any,
+}
+
+impl RpcArgType {
+    pub fn from_name(name: &str) -> Option<RpcArgType> {
+        match name {
+            "String" => Some(RpcArgType::String),
+            "Int" => Some(RpcArgType::Int),
+            "UInt" => Some(RpcArgType::UInt),
+            "Float" => Some(RpcArgType::Float),
+            "Bool" => Some(RpcArgType::Bool),
+            "Spline" => Some(RpcArgType::Spline),
+            "Vec" => Some(RpcArgType::Vec),
+            "Map" => Some(RpcArgType::Map),
+            "Int64" => Some(RpcArgType::Int64),
+            "Vec2" => Some(RpcArgType::Vec2),
+            "Vec3" => Some(RpcArgType::Vec3),
+            "Quat" => Some(RpcArgType::Quat),
+            "Bytes" => Some(RpcArgType::Bytes),
+            "Nil" => Some(RpcArgType::Nil),
+            "Any" => Some(RpcArgType::Any),
+            _ => None,
+        }
+    }
+
+    /// Returns the value converted to this type or None if it can't be converted.
+    /// Numbers are converted between each other when no precision is lost (Lua doesn't tell 1 from 1.0 to us).
+    pub fn coerce(&self, value: SystemValue) -> Option<SystemValue> {
+        match (self, value) {
+            (RpcArgType::Any, value) => Some(value),
+            (RpcArgType::String, SystemValue::String(value)) => Some(SystemValue::String(value)),
+            (RpcArgType::Bool, SystemValue::Bool(value)) => Some(SystemValue::Bool(value)),
+            (RpcArgType::Spline, SystemValue::Spline(value)) => Some(SystemValue::Spline(value)),
+            (RpcArgType::Vec, SystemValue::Vec(value)) => Some(SystemValue::Vec(value)),
+            (RpcArgType::Map, SystemValue::Map(value)) => Some(SystemValue::Map(value)),
+            (RpcArgType::Bytes, SystemValue::Bytes(value)) => Some(SystemValue::Bytes(value)),
+            (RpcArgType::Bytes, SystemValue::String(value)) => Some(SystemValue::Bytes(value.into_bytes())),
+            (RpcArgType::Nil, SystemValue::Nil) => Some(SystemValue::Nil),
+            // vectors may still come as lists of numbers
+            (RpcArgType::Vec2, value) => value.as_vec2().map(SystemValue::Vec2),
+            (RpcArgType::Vec3, value) => value.as_vec3().map(SystemValue::Vec3),
+            (RpcArgType::Quat, value) => value.as_quat().map(SystemValue::Quat),
+            (RpcArgType::Int64, SystemValue::Float(value)) => match value.fract() == 0.0 && value.abs() <= i64::MAX as f32 {
+                true => Some(SystemValue::Int64(value as i64)),
+                false => None,
+            },
+            (RpcArgType::Int64, value) => value.as_i64().map(SystemValue::Int64),
+            (RpcArgType::Float, SystemValue::Int64(value)) => Some(SystemValue::Float(value as f32)),
+            (RpcArgType::Int, SystemValue::Int64(value)) => i32::try_from(value).ok().map(SystemValue::Int),
+            (RpcArgType::UInt, SystemValue::Int64(value)) => u32::try_from(value).ok().map(SystemValue::UInt),
+            (RpcArgType::Float, SystemValue::Float(value)) => Some(SystemValue::Float(value)),
+            (RpcArgType::Float, SystemValue::Int(value)) => Some(SystemValue::Float(value as f32)),
+            (RpcArgType::Float, SystemValue::UInt(value)) => Some(SystemValue::Float(value as f32)),
+            (RpcArgType::Int, SystemValue::Int(value)) => Some(SystemValue::Int(value)),
+            (RpcArgType::Int, SystemValue::UInt(value)) => i32::try_from(value).ok().map(SystemValue::Int),
+            (RpcArgType::Int, SystemValue::Float(value)) => match value.fract() == 0.0 && value.abs() <= i32::MAX as f32 {
+                true => Some(SystemValue::Int(value as i32)),
+                false => None,
+            },
+            (RpcArgType::UInt, SystemValue::UInt(value)) => Some(SystemValue::UInt(value)),
+            (RpcArgType::UInt, SystemValue::Int(value)) => u32::try_from(value).ok().map(SystemValue::UInt),
+            (RpcArgType::UInt, SystemValue::Float(value)) => match value.fract() == 0.0 && value >= 0.0 && value <= u32::MAX as f32 {
+                true => Some(SystemValue::UInt(value as u32)),
+                false => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RpcCallMessage {
+    pub name: String,
+    pub args: Vec<SystemValue>,
+    /// System that gets the response. None if the caller doesn't need it.
+    pub response: Option<(String, u32)>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RpcResponseMessage {
+    pub call_id: u32,
+    pub result: Result<Vec<SystemValue>, String>,
+}
+
+#[derive(Debug)]
+struct PendingCall {
+    caller_system_id: String,
+    sent_time: Instant,
+    // the only client that may answer a call made with MessageReceiver::OneClient
+    callee: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RpcContext {
+    pub name: String,
+    /// Id of the client that made the call, None if it was made by the server.
+    /// It's the client that the server got the call from, not the one written in the message.
+    pub caller: Option<u64>,
+}
+
+#[derive(Debug)]
+pub enum RpcError {
+    NotConnected,
+    WrongArguments(String),
+    SendError(super::NetworkError),
+}
+
+impl std::fmt::Display for RpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RpcError::NotConnected => write!(f, "not connected"),
+            RpcError::WrongArguments(err) => write!(f, "wrong arguments: {}", err),
+            RpcError::SendError(err) => write!(f, "failed to send the message: {:?}", err),
+        }
+    }
+}
+
+/// Registers the signature of an RPC handler. Calls with wrong arguments are rejected before reaching the system.
+/// The system gets the calls in System::handle_rpc.
+pub fn register_rpc(system_id: &str, name: &str, arg_types: Vec<RpcArgType>) {
+    unsafe {
+        RPC_SIGNATURES.insert((system_id.into(), name.into()), arg_types);
+    }
+}
+
+pub fn unregister_rpc(system_id: &str, name: &str) {
+    unsafe {
+        RPC_SIGNATURES.remove(&(system_id.to_string(), name.to_string()));
+    }
+}
+
+pub fn is_rpc_registered(system_id: &str, name: &str) -> bool {
+    unsafe { RPC_SIGNATURES.contains_key(&(system_id.to_string(), name.to_string())) }
+}
+
+/// Calls the RPC handler in the system on the other side. Clients always call the server, server calls the receivers.
+/// If caller_system_id is Some, the response is sent to System::rpc_response of that system.
+/// When the server calls several clients at once, only the first response is delivered.
+/// Returns the id of the call that the response will have.
+pub fn call(
+    caller_system_id: Option<&str>,
+    system_id: &str,
+    name: &str,
+    args: Vec<SystemValue>,
+    receiver: MessageReceiver,
+) -> Result<Option<u32>, RpcError> {
+    if !is_server() && !is_client() {
+        debugger::error(&format!(
+            "rpc error!\nfailed to call '{}' in system '{}', not connected",
+            name, system_id
+        ));
+        return Err(RpcError::NotConnected);
+    }
+
+    // handlers are registered by the same scripts on both sides, so they can be checked before sending
+    let args = match validate_args(system_id, name, args) {
+        Ok(args) => args,
+        Err(err) => {
+            debugger::error(&format!(
+                "rpc error!\nfailed to call '{}' in system '{}'\nerr: {}",
+                name, system_id, err
+            ));
+            return Err(RpcError::WrongArguments(err));
+        }
+    };
+
+    let response = match caller_system_id {
+        Some(caller_system_id) => unsafe {
+            let call_id = NEXT_CALL_ID;
+            NEXT_CALL_ID = NEXT_CALL_ID.wrapping_add(1);
+            PENDING_CALLS.insert(
+                call_id,
+                PendingCall {
+                    caller_system_id: caller_system_id.into(),
+                    sent_time: Instant::now(),
+                    callee: match &receiver {
+                        MessageReceiver::OneClient(client_id) if is_server() => Some(*client_id),
+                        _ => None,
+                    },
+                },
+            );
+            Some((caller_system_id.to_string(), call_id))
+        },
+        None => None,
+    };
+    let call_id = response.as_ref().map(|(_, call_id)| *call_id);
+
+    let contents = MessageContents::RpcCall(RpcCallMessage {
+        name: name.into(),
+        args,
+        response,
+    });
+    let message = match is_server() {
+        true => Message::new_from_server(receiver, contents, system_id.into(), name.into()),
+        false => Message::new_from_client(contents, system_id.into(), name.into()),
+    };
+
+    match send_message(MessageReliability::Reliable, message) {
+        Ok(_) => Ok(call_id),
+        Err(err) => {
+            if let Some(call_id) = call_id {
+                unsafe {
+                    PENDING_CALLS.remove(&call_id);
+                }
+            }
+            Err(RpcError::SendError(err))
+        }
+    }
+}
+
+/// Returns the message back if it's not related to RPC.
+pub(crate) fn intercept_message(message: Message) -> Option<Message> {
+    match message.contents {
+        MessageContents::RpcCall(call) => {
+            let caller = match &message.message_type {
+                MessageType::FromClient(client_id) => match client_id.parse::<u64>() {
+                    Ok(client_id) => Some(client_id),
+                    Err(err) => {
+                        debugger::error(&format!(
+                            "rpc error!\nfailed to parse the id of a client that made the call\nerr: {}",
+                            err
+                        ));
+                        return None;
+                    }
+                },
+                MessageType::FromServer(_) => None,
+            };
+            unsafe {
+                RECEIVED_CALLS.push((message.system_id, caller, call));
+            }
+            None
+        }
+        MessageContents::RpcResponse(response) => {
+            let responder = match &message.message_type {
+                MessageType::FromClient(client_id) => client_id.parse::<u64>().ok(),
+                MessageType::FromServer(_) => None,
+            };
+            unsafe {
+                RECEIVED_RESPONSES.push((responder, response));
+            }
+            None
+        }
+        _ => Some(message),
+    }
+}
+
+/// Runs the received calls, delivers the received responses and times out the old pending calls.
+pub fn update(framework: &mut Framework) {
+    let received_calls: Vec<(String, Option<u64>, RpcCallMessage)> =
+        unsafe { RECEIVED_CALLS.drain(..).collect() };
+    for (system_id, caller, call) in received_calls {
+        let result = run_call(framework, &system_id, caller, call.name.clone(), call.args);
+
+        if let Some((caller_system_id, call_id)) = call.response {
+            let contents = MessageContents::RpcResponse(RpcResponseMessage { call_id, result });
+            let message = match caller {
+                Some(client_id) => Message::new_from_server(
+                    MessageReceiver::OneClient(client_id),
+                    contents,
+                    caller_system_id,
+                    call.name,
+                ),
+                None => Message::new_from_client(contents, caller_system_id, call.name),
+            };
+            let _ = send_message(MessageReliability::Reliable, message);
+        }
+    }
+
+    let received_responses: Vec<(Option<u64>, RpcResponseMessage)> =
+        unsafe { RECEIVED_RESPONSES.drain(..).collect() };
+    for (responder, response) in received_responses {
+        // a client can't answer the calls that were made to another client
+        let is_callee = unsafe {
+            match PENDING_CALLS.get(&response.call_id) {
+                Some(PendingCall { callee: Some(callee), .. }) => responder == Some(*callee),
+                _ => true,
+            }
+        };
+        if !is_callee {
+            debugger::warn(&format!(
+                "rpc warning!\nclient {:?} sent a response to call {} that was made to another client",
+                responder, response.call_id
+            ));
+            continue;
+        }
+
+        let pending_call = unsafe { PENDING_CALLS.remove(&response.call_id) };
+        match pending_call {
+            Some(pending_call) => deliver_response(
+                framework,
+                &pending_call.caller_system_id,
+                response.call_id,
+                response.result,
+            ),
+            None => debugger::warn(&format!(
+                "rpc warning!\ngot a response to an unknown call {}",
+                response.call_id
+            )),
+        }
+    }
+
+    let timed_out_calls: Vec<u32> = unsafe {
+        PENDING_CALLS
+            .iter()
+            .filter(|(_, call)| call.sent_time.elapsed() > RPC_RESPONSE_TIMEOUT)
+            .map(|(call_id, _)| *call_id)
+            .collect()
+    };
+    for call_id in timed_out_calls {
+        if let Some(pending_call) = unsafe { PENDING_CALLS.remove(&call_id) } {
+            deliver_response(
+                framework,
+                &pending_call.caller_system_id,
+                call_id,
+                Err("timed out".into()),
+            );
+        }
+    }
+}
+
+fn run_call(
+    framework: &mut Framework,
+    system_id: &str,
+    caller: Option<u64>,
+    name: String,
+    args: Vec<SystemValue>,
+) -> Result<Vec<SystemValue>, String> {
+    if !is_rpc_registered(system_id, &name) {
+        debugger::warn(&format!(
+            "rpc warning!\ngot a call to '{}' in system '{}', but it's not registered",
+            name, system_id
+        ));
+        return Err(format!("rpc '{}' is not registered in system '{}'", name, system_id));
+    }
+
+    let args = match validate_args(system_id, &name, args) {
+        Ok(args) => args,
+        Err(err) => {
+            debugger::warn(&format!(
+                "rpc warning!\ngot a call to '{}' in system '{}' with wrong arguments (caller: {:?})\nerr: {}",
+                name, system_id, caller, err
+            ));
+            return Err(err);
+        }
+    };
+
+    match systems::get_system_mut_with_id(system_id) {
+        Some(system) => system.handle_rpc(framework, RpcContext { name, caller }, args),
+        None => {
+            debugger::error(&format!(
+                "rpc error!\ngot a call to '{}', but system '{}' doesn't exist",
+                name, system_id
+            ));
+            Err(format!("system '{}' doesn't exist", system_id))
+        }
+    }
+}
+
+fn deliver_response(
+    framework: &mut Framework,
+    caller_system_id: &str,
+    call_id: u32,
+    result: Result<Vec<SystemValue>, String>,
+) {
+    match systems::get_system_mut_with_id(caller_system_id) {
+        Some(system) => system.rpc_response(framework, call_id, result),
+        None => debugger::warn(&format!(
+            "rpc warning!\nfailed to deliver the response to call {}, system '{}' doesn't exist",
+            call_id, caller_system_id
+        )),
+    }
+}
+
+fn validate_args(system_id: &str, name: &str, args: Vec<SystemValue>) -> Result<Vec<SystemValue>, String> {
+    let arg_types = match unsafe { RPC_SIGNATURES.get(&(system_id.to_string(), name.to_string())) } {
+        Some(arg_types) => arg_types.clone(),
+        // the handler is registered only on the other side
+        None => return Ok(args),
+    };
+
+    if arg_types.len() != args.len() {
+        return Err(format!(
+            "expected {} arguments, got {}",
+            arg_types.len(),
+            args.len()
+        ));
+    }
+
+    let mut validated_args = Vec::new();
+    for (idx, (arg_type, arg)) in arg_types.iter().zip(args).enumerate() {
+        let arg_type_name = arg.type_name();
+        match arg_type.coerce(arg) {
+            Some(arg) => validated_args.push(arg),
+            None => {
+                return Err(format!(
+                    "argument {} should be {:?}, got {}",
+                    idx + 1,
+                    arg_type,
+                    arg_type_name
+                ))
+            }
+        }
+    }
+
+    Ok(validated_args)
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::*;
+
+    #[test]
+    fn whole_numbers_are_converted() {
+        assert!(matches!(RpcArgType::Int.coerce(SystemValue::Float(3.0)), Some(SystemValue::Int(3))));
+        assert!(matches!(RpcArgType::UInt.coerce(SystemValue::Int(7)), Some(SystemValue::UInt(7))));
+        assert!(matches!(RpcArgType::Float.coerce(SystemValue::Int(5)), Some(SystemValue::Float(value)) if value == 5.0));
+    }
+
+    #[test]
+    fn lossy_numbers_are_rejected() {
+        assert!(RpcArgType::Int.coerce(SystemValue::Float(1.5)).is_none());
+        assert!(RpcArgType::UInt.coerce(SystemValue::Int(-1)).is_none());
+        assert!(RpcArgType::UInt.coerce(SystemValue::Float(-1.0)).is_none());
+        assert!(RpcArgType::Int.coerce(SystemValue::UInt(u32::MAX)).is_none());
+    }
+
+    #[test]
+    fn wrong_types_are_rejected() {
+        assert!(RpcArgType::String.coerce(SystemValue::Int(1)).is_none());
+        assert!(RpcArgType::Bool.coerce(SystemValue::String("true".into())).is_none());
+        assert!(matches!(RpcArgType::Any.coerce(SystemValue::Bool(false)), Some(SystemValue::Bool(false))));
+    }
+
+    #[test]
+    fn typed_values_are_converted() {
+        assert!(matches!(RpcArgType::Int64.coerce(SystemValue::Int(-2)), Some(SystemValue::Int64(-2))));
+        assert!(RpcArgType::Int.coerce(SystemValue::Int64(i64::MAX)).is_none());
+        assert!(matches!(RpcArgType::Bytes.coerce(SystemValue::String("ab".into())), Some(SystemValue::Bytes(bytes)) if bytes == b"ab"));
+        assert!(RpcArgType::Nil.coerce(SystemValue::Bool(false)).is_none());
+    }
+
+    #[test]
+    fn vectors_can_be_lists_of_numbers() {
+        let list = SystemValue::Vec(vec![SystemValue::Float(1.0), SystemValue::Int(2), SystemValue::Float(3.0)]);
+        assert!(matches!(RpcArgType::Vec3.coerce(list), Some(SystemValue::Vec3(value)) if value == Vec3::new(1.0, 2.0, 3.0)));
+
+        let too_short = SystemValue::Vec(vec![SystemValue::Float(1.0)]);
+        assert!(RpcArgType::Vec3.coerce(too_short).is_none());
+    }
+}
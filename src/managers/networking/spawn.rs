@@ -0,0 +1,588 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use glam::Vec3;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    get_connected_clients, interest, is_client, is_server, replication, send_message, Message, MessageContents,
+    MessageReceiver, MessageReliability,
+};
+use crate::{
+    framework::Framework,
+    managers::{
+        debugger,
+        physics::{BodyColliderType, CollisionGroups},
+        render::RenderLayer,
+        systems::{self, SystemValue},
+    },
+    objects::{Object, Transform},
+    systems::System,
+};
+
+static mut NEXT_NETWORK_ID: u64 = 1;
+// server: everything that was spawned and is still alive, ordered by network id so parents are spawned before children
+// client: objects that were spawned by the server
+static mut SPAWNED_OBJECTS: Lazy<BTreeMap<u64, SpawnedObject>> = Lazy::new(|| BTreeMap::new());
+// server: network ids of the objects that each client got, objects are sent in update() so they include
+// the properties and groups set after spawning
+static mut CLIENTS_SPAWNED_OBJECTS: Lazy<HashMap<u64, HashSet<u64>>> = Lazy::new(|| HashMap::new());
+// client: spawn and despawn messages waiting for update()
+static mut RECEIVED_MESSAGES: Vec<(String, SpawnMessage)> = vec![];
+// client: objects of the previous connection, update() deletes them
+static mut STALE_OBJECTS: Vec<SpawnedObject> = vec![];
+
+/// Everything that is needed to create the same object on another machine.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum SpawnObjectKind {
+    Empty,
+    Model {
+        model_asset_id: String,
+        texture_asset_id: Option<String>,
+        shader_asset_id: Option<String>,
+        is_transparent: bool,
+        layer: Option<u8>,
+    },
+    SoundEmitter {
+        sound_asset_id: String,
+        should_loop: bool,
+        is_positional: bool,
+        max_distance: f32,
+    },
+    Trigger {
+        shape: SpawnColliderShape,
+        membership_groups: Option<u32>,
+        mask: Option<u32>,
+    },
+    CharacterController {
+        shape: SpawnColliderShape,
+        membership_groups: Option<u32>,
+        mask: Option<u32>,
+    },
+    Ray {
+        direction: Vec3,
+        mask: Option<u32>,
+    },
+    NavObstacle {
+        size_x: f32,
+        size_z: f32,
+    },
+    DynamicNavObject {
+        radius: f32,
+    },
+}
+
+/// Serializable version of BodyColliderType (without triangle meshes).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub enum SpawnColliderShape {
+    Ball(f32),
+    Cuboid(f32, f32, f32),
+    Capsule(f32, f32),
+    Cylinder(f32, f32),
+}
+
+impl SpawnColliderShape {
+    fn to_body_collider_type(self) -> BodyColliderType {
+        match self {
+            SpawnColliderShape::Ball(radius) => BodyColliderType::Ball(radius),
+            SpawnColliderShape::Cuboid(x, y, z) => BodyColliderType::Cuboid(x, y, z),
+            SpawnColliderShape::Capsule(radius, height) => BodyColliderType::Capsule(radius, height),
+            SpawnColliderShape::Cylinder(radius, height) => BodyColliderType::Cylinder(radius, height),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SpawnObjectMessage {
+    pub network_id: u64,
+    pub object_name: String,
+    pub parent: Option<String>,
+    pub kind: SpawnObjectKind,
+    pub transform: Transform,
+    pub properties: HashMap<String, Vec<SystemValue>>,
+    pub groups: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DespawnObjectMessage {
+    pub network_id: u64,
+}
+
+#[derive(Debug)]
+pub enum SpawnError {
+    NotServer,
+    SystemNotFound(String),
+    ObjectAlreadyExists(String),
+    ParentNotFound(String),
+    AssetNotFound(String),
+}
+
+impl std::fmt::Display for SpawnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpawnError::NotServer => write!(f, "only the server can spawn replicated objects"),
+            SpawnError::SystemNotFound(system_id) => write!(f, "system '{}' not found", system_id),
+            SpawnError::ObjectAlreadyExists(name) => write!(f, "object '{}' already exists", name),
+            SpawnError::ParentNotFound(name) => write!(f, "parent object '{}' not found", name),
+            SpawnError::AssetNotFound(asset_id) => write!(f, "asset '{}' not found", asset_id),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SpawnedObject {
+    system_id: String,
+    object_name: String,
+    parent: Option<String>,
+    kind: SpawnObjectKind,
+}
+
+#[derive(Debug)]
+enum SpawnMessage {
+    Spawn(SpawnObjectMessage),
+    Despawn(DespawnObjectMessage),
+}
+
+/// Server: creates the object and spawns it on all current and future clients that it's relevant for.
+/// Returns the network id of the object. Deleting the object with delete_object destroys it everywhere.
+pub fn spawn_object(
+    framework: &mut Framework,
+    system_id: &str,
+    object_name: &str,
+    parent: Option<&str>,
+    kind: SpawnObjectKind,
+    transform: Transform,
+) -> Result<u64, SpawnError> {
+    if !is_server() {
+        return Err(SpawnError::NotServer);
+    }
+
+    let system = match systems::get_system_mut_with_id(system_id) {
+        Some(system) => system,
+        None => return Err(SpawnError::SystemNotFound(system_id.into())),
+    };
+    if system.find_object(object_name).is_some() {
+        return Err(SpawnError::ObjectAlreadyExists(object_name.into()));
+    }
+
+    let object = create_object(framework, object_name, &kind, transform)?;
+    add_object(system, parent, object)?;
+
+    let network_id = unsafe {
+        let network_id = NEXT_NETWORK_ID;
+        NEXT_NETWORK_ID += 1;
+        SPAWNED_OBJECTS.insert(
+            network_id,
+            SpawnedObject {
+                system_id: system_id.into(),
+                object_name: object_name.into(),
+                parent: parent.map(|parent| parent.into()),
+                kind,
+            },
+        );
+        network_id
+    };
+
+    Ok(network_id)
+}
+
+pub fn get_network_id(system_id: &str, object_name: &str) -> Option<u64> {
+    unsafe {
+        SPAWNED_OBJECTS
+            .iter()
+            .find(|(_, object)| object.system_id == system_id && object.object_name == object_name)
+            .map(|(network_id, _)| *network_id)
+    }
+}
+
+/// Returns the system id and the name of the object with this network id.
+pub fn find_object_by_network_id(network_id: u64) -> Option<(String, String)> {
+    unsafe {
+        SPAWNED_OBJECTS
+            .get(&network_id)
+            .map(|object| (object.system_id.clone(), object.object_name.clone()))
+    }
+}
+
+/// Server: spawns the objects on the clients when they become relevant for them and despawns them when they stop being relevant.
+/// Client: creates and destroys the objects that the server told about.
+pub fn update(framework: &mut Framework) {
+    delete_stale_objects(framework);
+
+    if is_server() {
+        server_update();
+    } else if is_client() {
+        client_update(framework);
+    }
+}
+
+/// Returns the message back if it's not related to spawning.
+pub(crate) fn intercept_message(message: Message) -> Option<Message> {
+    let spawn_message = match message.contents {
+        MessageContents::SpawnObject(spawn) => SpawnMessage::Spawn(spawn),
+        MessageContents::DespawnObject(despawn) => SpawnMessage::Despawn(despawn),
+        _ => return Some(message),
+    };
+
+    if is_client() {
+        unsafe {
+            RECEIVED_MESSAGES.push((message.system_id, spawn_message));
+        }
+    } else {
+        debugger::warn("networking spawn warning!\ngot a spawn message from a client, ignoring it");
+    }
+    None
+}
+
+/// Called by System::delete_object before the object (and it's children) is removed.
+pub(crate) fn object_deleted(system_id: &str, object: &Box<dyn Object>) {
+    let mut names = Vec::new();
+    collect_names(object, &mut names);
+
+    for name in names {
+        let network_id = match get_network_id(system_id, &name) {
+            Some(network_id) => network_id,
+            None => continue,
+        };
+
+        unsafe {
+            SPAWNED_OBJECTS.remove(&network_id);
+        }
+
+        if is_server() {
+            replication::stop_replicating_object(system_id, &name);
+
+            // only the clients that got the object hear about it
+            let clients: Vec<u64> = unsafe {
+                CLIENTS_SPAWNED_OBJECTS
+                    .iter_mut()
+                    .filter_map(|(client_id, spawned)| spawned.remove(&network_id).then_some(*client_id))
+                    .collect()
+            };
+            for client_id in clients {
+                send_despawn(client_id, system_id, network_id);
+            }
+        }
+    }
+}
+
+pub(crate) fn forget_client(client_id: u64) {
+    unsafe {
+        CLIENTS_SPAWNED_OBJECTS.remove(&client_id);
+    }
+}
+
+/// Client: forgets the objects of the previous connection and deletes them on the next update.
+/// The server of the next connection counts network ids from the beginning and won't despawn them.
+pub(crate) fn reset_client() {
+    unsafe {
+        let spawned_objects = std::mem::take(&mut *SPAWNED_OBJECTS);
+        STALE_OBJECTS.extend(spawned_objects.into_values());
+        RECEIVED_MESSAGES.clear();
+    }
+}
+
+fn delete_stale_objects(framework: &mut Framework) {
+    let stale_objects: Vec<SpawnedObject> = unsafe { STALE_OBJECTS.drain(..).collect() };
+
+    // parents have lower network ids, so their children may be already deleted with them
+    for stale_object in stale_objects {
+        if let Some(system) = systems::get_system_mut_with_id(&stale_object.system_id) {
+            if system.find_object(&stale_object.object_name).is_some() {
+                system.delete_object(framework, &stale_object.object_name);
+            }
+        }
+    }
+}
+
+fn server_update() {
+    let spawned_objects: Vec<(u64, SpawnedObject)> = unsafe {
+        SPAWNED_OBJECTS
+            .iter()
+            .map(|(network_id, object)| (*network_id, object.clone()))
+            .collect()
+    };
+    let network_ids: HashMap<(&str, &str), u64> = spawned_objects
+        .iter()
+        .map(|(network_id, object)| ((object.system_id.as_str(), object.object_name.as_str()), *network_id))
+        .collect();
+
+    for client_id in get_connected_clients() {
+        let mut client_objects = unsafe { CLIENTS_SPAWNED_OBJECTS.remove(&client_id).unwrap_or_default() };
+
+        // parents are always before their children, so the parent's state for this client is already known
+        for (network_id, object) in &spawned_objects {
+            let parent_spawned = match &object.parent {
+                Some(parent) => match network_ids.get(&(object.system_id.as_str(), parent.as_str())) {
+                    Some(parent_network_id) => client_objects.contains(parent_network_id),
+                    // parent wasn't spawned through the network, so every client has it
+                    None => true,
+                },
+                None => true,
+            };
+            let is_relevant = parent_spawned && interest::is_object_relevant(client_id, &object.system_id, &object.object_name);
+            let is_spawned = client_objects.contains(network_id);
+
+            if is_relevant && !is_spawned {
+                if let Some((system_id, spawn_message)) = build_spawn_message(*network_id) {
+                    let message = Message::new_from_server(
+                        MessageReceiver::OneClient(client_id),
+                        MessageContents::SpawnObject(spawn_message),
+                        system_id,
+                        String::new(),
+                    );
+                    let _ = send_message(MessageReliability::Reliable, message);
+                    client_objects.insert(*network_id);
+                }
+            } else if !is_relevant && is_spawned {
+                // if the parent was despawned, the client already deleted this object with it
+                if parent_spawned {
+                    send_despawn(client_id, &object.system_id, *network_id);
+                }
+                client_objects.remove(network_id);
+            }
+        }
+
+        unsafe {
+            CLIENTS_SPAWNED_OBJECTS.insert(client_id, client_objects);
+        }
+    }
+}
+
+fn send_despawn(client_id: u64, system_id: &str, network_id: u64) {
+    let message = Message::new_from_server(
+        MessageReceiver::OneClient(client_id),
+        MessageContents::DespawnObject(DespawnObjectMessage { network_id }),
+        system_id.into(),
+        String::new(),
+    );
+    let _ = send_message(MessageReliability::Reliable, message);
+}
+
+fn client_update(framework: &mut Framework) {
+    let received_messages: Vec<(String, SpawnMessage)> = unsafe { RECEIVED_MESSAGES.drain(..).collect() };
+
+    for (system_id, message) in received_messages {
+        match message {
+            SpawnMessage::Spawn(spawn) => client_spawn(framework, &system_id, spawn),
+            SpawnMessage::Despawn(despawn) => {
+                let spawned_object = unsafe { SPAWNED_OBJECTS.get(&despawn.network_id).cloned() };
+                match spawned_object {
+                    Some(spawned_object) => match systems::get_system_mut_with_id(&spawned_object.system_id) {
+                        // removes it from SPAWNED_OBJECTS through object_deleted
+                        Some(system) => system.delete_object(framework, &spawned_object.object_name),
+                        None => unsafe {
+                            SPAWNED_OBJECTS.remove(&despawn.network_id);
+                        },
+                    },
+                    None => (),
+                }
+            }
+        }
+    }
+}
+
+fn client_spawn(framework: &mut Framework, system_id: &str, spawn: SpawnObjectMessage) {
+    if unsafe { SPAWNED_OBJECTS.contains_key(&spawn.network_id) } {
+        return;
+    }
+
+    let system = match systems::get_system_mut_with_id(system_id) {
+        Some(system) => system,
+        None => {
+            debugger::error(&format!(
+                "networking spawn error!\nfailed to spawn object '{}'\nsystem '{}' not found",
+                spawn.object_name, system_id
+            ));
+            return;
+        }
+    };
+
+    match system.find_object(&spawn.object_name) {
+        Some(_) => debugger::warn(&format!(
+            "networking spawn warning!\nobject '{}' in system '{}' already exists, using it instead of spawning a new one",
+            spawn.object_name, system_id
+        )),
+        None => {
+            let mut object = match create_object(framework, &spawn.object_name, &spawn.kind, spawn.transform) {
+                Ok(object) => object,
+                Err(err) => {
+                    debugger::error(&format!(
+                        "networking spawn error!\nfailed to spawn object '{}'\nerr: {}",
+                        spawn.object_name, err
+                    ));
+                    return;
+                }
+            };
+
+            object.set_object_properties(spawn.properties);
+            for group in &spawn.groups {
+                object.add_to_group(group);
+            }
+
+            if let Err(err) = add_object(system, spawn.parent.as_deref(), object) {
+                debugger::error(&format!(
+                    "networking spawn error!\nfailed to spawn object '{}'\nerr: {}",
+                    spawn.object_name, err
+                ));
+                return;
+            }
+        }
+    }
+
+    unsafe {
+        SPAWNED_OBJECTS.insert(
+            spawn.network_id,
+            SpawnedObject {
+                system_id: system_id.into(),
+                object_name: spawn.object_name,
+                parent: spawn.parent,
+                kind: spawn.kind,
+            },
+        );
+    }
+}
+
+// The message is built from the current state of the object, so late joining clients get up to date properties and groups.
+fn build_spawn_message(network_id: u64) -> Option<(String, SpawnObjectMessage)> {
+    let spawned_object = unsafe { SPAWNED_OBJECTS.get(&network_id)?.clone() };
+    let system = systems::get_system_mut_with_id(&spawned_object.system_id)?;
+    let object = system.find_object_mut(&spawned_object.object_name)?;
+
+    let spawn_message = SpawnObjectMessage {
+        network_id,
+        object_name: spawned_object.object_name,
+        parent: spawned_object.parent,
+        kind: spawned_object.kind,
+        transform: object.local_transform(),
+        properties: object.object_properties().clone(),
+        groups: object
+            .groups_list()
+            .iter()
+            .map(|group| group.as_raw().to_string())
+            .collect(),
+    };
+
+    Some((spawned_object.system_id, spawn_message))
+}
+
+fn create_object(
+    framework: &mut Framework,
+    name: &str,
+    kind: &SpawnObjectKind,
+    transform: Transform,
+) -> Result<Box<dyn Object>, SpawnError> {
+    let mut object: Box<dyn Object> = match kind {
+        SpawnObjectKind::Empty => Box::new(framework.new_empty_object(name)),
+        SpawnObjectKind::Model {
+            model_asset_id,
+            texture_asset_id,
+            shader_asset_id,
+            is_transparent,
+            layer,
+        } => {
+            let model_asset = match framework.get_model_asset(model_asset_id) {
+                Some(asset) => asset,
+                None => return Err(SpawnError::AssetNotFound(model_asset_id.clone())),
+            };
+            let texture_asset = match texture_asset_id {
+                Some(texture_asset_id) => match framework.get_texture_asset(texture_asset_id) {
+                    Some(asset) => Some(asset),
+                    None => return Err(SpawnError::AssetNotFound(texture_asset_id.clone())),
+                },
+                None => None,
+            };
+            let shader_asset_id = shader_asset_id.clone().unwrap_or("default".into());
+            let shader_asset = match framework.get_shader_asset(&shader_asset_id) {
+                Some(asset) => asset,
+                None => return Err(SpawnError::AssetNotFound(shader_asset_id)),
+            };
+            let layer = match layer {
+                Some(2) => RenderLayer::Layer2,
+                _ => RenderLayer::Layer1,
+            };
+
+            Box::new(framework.new_model_object(
+                name,
+                model_asset,
+                texture_asset,
+                shader_asset,
+                *is_transparent,
+                layer,
+            ))
+        }
+        SpawnObjectKind::SoundEmitter {
+            sound_asset_id,
+            should_loop,
+            is_positional,
+            max_distance,
+        } => {
+            let sound_asset = match framework.get_sound_asset(sound_asset_id) {
+                Some(asset) => asset,
+                None => return Err(SpawnError::AssetNotFound(sound_asset_id.clone())),
+            };
+            let mut object = framework.new_sound_emitter(name, sound_asset, *is_positional);
+            if *is_positional {
+                let _ = object.set_max_distance(*max_distance);
+            }
+            object.set_looping(*should_loop);
+            Box::new(object)
+        }
+        SpawnObjectKind::Trigger {
+            shape,
+            membership_groups,
+            mask,
+        } => Box::new(framework.new_trigger(
+            name,
+            membership_groups.map(CollisionGroups::from),
+            mask.map(CollisionGroups::from),
+            shape.to_body_collider_type(),
+        )),
+        SpawnObjectKind::CharacterController {
+            shape,
+            membership_groups,
+            mask,
+        } => Box::new(framework.new_character_controller_object(
+            name,
+            shape.to_body_collider_type(),
+            membership_groups.map(CollisionGroups::from),
+            mask.map(CollisionGroups::from),
+        )),
+        SpawnObjectKind::Ray { direction, mask } => {
+            Box::new(framework.new_ray(name, *direction, mask.map(CollisionGroups::from)))
+        }
+        SpawnObjectKind::NavObstacle { size_x, size_z } => {
+            Box::new(framework.new_nav_obstacle(name, Vec3::new(*size_x, 1.0, *size_z)))
+        }
+        SpawnObjectKind::DynamicNavObject { radius } => Box::new(framework.new_dynamic_nav_object(name, *radius)),
+    };
+
+    object.set_position(framework, transform.position, true);
+    object.set_rotation(framework, transform.rotation, true);
+    object.set_scale(framework, transform.scale, true);
+
+    Ok(object)
+}
+
+fn add_object(system: &mut Box<dyn System>, parent: Option<&str>, object: Box<dyn Object>) -> Result<(), SpawnError> {
+    match parent {
+        Some(parent) => match system.find_object_mut(parent) {
+            Some(parent_object) => {
+                parent_object.add_child(object);
+                Ok(())
+            }
+            None => Err(SpawnError::ParentNotFound(parent.into())),
+        },
+        None => {
+            system.add_object(object);
+            Ok(())
+        }
+    }
+}
+
+fn collect_names(object: &Box<dyn Object>, names: &mut Vec<String>) {
+    names.push(object.name().into());
+    for child in object.children_list() {
+        collect_names(child, names);
+    }
+}
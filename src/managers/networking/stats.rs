@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+
+use once_cell::sync::Lazy;
+
+use super::{get_current_networking_mode, handshake, NetworkingMode};
+
+static mut CHANNELS_TRAFFIC: Lazy<BTreeMap<&'static str, TrafficCounter>> = Lazy::new(|| BTreeMap::new());
+static mut SYSTEMS_TRAFFIC: Lazy<BTreeMap<String, TrafficCounter>> = Lazy::new(|| BTreeMap::new());
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TrafficCounter {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub messages_sent: u64,
+    pub messages_received: u64,
+}
+
+/// Connection quality reported by renet.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConnectionStats {
+    pub rtt_ms: f64,
+    /// From 0 to 1.
+    pub packet_loss: f64,
+    pub bytes_sent_per_second: f64,
+    pub bytes_received_per_second: f64,
+}
+
+/// Server: stats of every accepted client. Client: the connection to the server (client id is the own one).
+pub fn get_connections_stats() -> Vec<(u64, ConnectionStats)> {
+    match get_current_networking_mode() {
+        NetworkingMode::Server(server) => handshake::get_accepted_clients()
+            .into_iter()
+            .filter_map(|client_id| match server.server.network_info(client_id) {
+                Ok(info) => Some((
+                    client_id,
+                    ConnectionStats {
+                        rtt_ms: info.rtt,
+                        packet_loss: info.packet_loss,
+                        bytes_sent_per_second: info.bytes_sent_per_second,
+                        bytes_received_per_second: info.bytes_received_per_second,
+                    },
+                )),
+                Err(_) => None,
+            })
+            .collect(),
+        NetworkingMode::Client(client) => {
+            let info = client.client.network_info();
+            vec![(
+                unsafe { super::CLIENT_ID },
+                ConnectionStats {
+                    rtt_ms: info.rtt,
+                    packet_loss: info.packet_loss,
+                    bytes_sent_per_second: info.bytes_sent_per_second,
+                    bytes_received_per_second: info.bytes_received_per_second,
+                },
+            )]
+        }
+        NetworkingMode::Disconnected(_) => vec![],
+    }
+}
+
+pub fn get_client_stats(client_id: u64) -> Option<ConnectionStats> {
+    get_connections_stats()
+        .into_iter()
+        .find(|(id, _)| *id == client_id)
+        .map(|(_, stats)| stats)
+}
+
+/// Bytes and messages sent and received on every channel since the start (or the last reset).
+pub fn get_channels_traffic() -> Vec<(&'static str, TrafficCounter)> {
+    unsafe { CHANNELS_TRAFFIC.iter().map(|(name, counter)| (*name, *counter)).collect() }
+}
+
+/// Bytes and messages sent and received by every system since the start (or the last reset).
+pub fn get_systems_traffic() -> Vec<(String, TrafficCounter)> {
+    unsafe { SYSTEMS_TRAFFIC.iter().map(|(id, counter)| (id.clone(), *counter)).collect() }
+}
+
+pub fn reset_traffic_counters() {
+    unsafe {
+        CHANNELS_TRAFFIC.clear();
+        SYSTEMS_TRAFFIC.clear();
+    }
+}
+
+pub(crate) fn record_sent(channel: &'static str, system_id: &str, bytes: usize) {
+    unsafe {
+        let channel_counter = CHANNELS_TRAFFIC.entry(channel).or_default();
+        channel_counter.bytes_sent += bytes as u64;
+        channel_counter.messages_sent += 1;
+
+        let system_counter = system_counter(system_id);
+        system_counter.bytes_sent += bytes as u64;
+        system_counter.messages_sent += 1;
+    }
+}
+
+pub(crate) fn record_received_on_channel(channel: &'static str, bytes: usize) {
+    unsafe {
+        let counter = CHANNELS_TRAFFIC.entry(channel).or_default();
+        counter.bytes_received += bytes as u64;
+        counter.messages_received += 1;
+    }
+}
+
+pub(crate) fn record_received_by_system(system_id: &str, bytes: usize) {
+    unsafe {
+        let counter = system_counter(system_id);
+        counter.bytes_received += bytes as u64;
+        counter.messages_received += 1;
+    }
+}
+
+unsafe fn system_counter(system_id: &str) -> &'static mut TrafficCounter {
+    if !SYSTEMS_TRAFFIC.contains_key(system_id) {
+        SYSTEMS_TRAFFIC.insert(system_id.into(), TrafficCounter::default());
+    }
+    SYSTEMS_TRAFFIC
+        .get_mut(system_id)
+        .expect("the counter was inserted above")
+}
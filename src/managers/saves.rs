@@ -1,61 +1,279 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
     fs::{self, File},
     io::{self, Read, Write},
     path::Path,
+    sync::{Arc, Mutex},
+    time::{Instant, SystemTime, UNIX_EPOCH},
 };
-use serde::Serialize;
-use crate::managers::debugger; 
-use super::{assets::get_full_asset_path, systems::SystemValue}; 
-
-#[derive(Default)] 
-pub struct SavesManager { 
-    save_system_values: Vec<String>, 
-    current_save_name: Option<String>, 
-    lazy_values_to_save: HashMap<String, Vec<SystemValue>> 
-} 
-
-impl SavesManager { 
-    pub fn load_save(&mut self, save_name: &str) -> Result<HashMap<String, Vec<SystemValue>>, ()> {
-        let mut global_values: HashMap<String, Vec<SystemValue>> = HashMap::new();
-        let save_value_names_file_path = "saves/".to_string() + save_name + "/save_values.val";
-        let save_value_names_file_path = get_full_asset_path(&save_value_names_file_path);
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use crate::{managers::debugger, math_utils::fnv1a};
+#[cfg(not(test))]
+use super::assets::get_full_asset_path;
+use super::systems::SystemValue;
 
-        match fs::read(&save_value_names_file_path) {
-            Ok(save_values_names) => {
-                match serde_bare::from_slice::<Vec<String>>(&save_values_names) {
-                    Ok(save_value_names) => {
-                        for value_name in save_value_names {
-                            if let Some(value) = load_value_from_file(save_name, &value_name) {
-                                global_values.insert(value_name, value);
-                            }
-                        }
+// Bump it when the layout of the save files changes and add a step to migrate_format.
+// 0 is the old format without a header, 1 had a header and one file per value,
+// 2 writes every save as a new generation with a manifest.
+pub const SAVE_FORMAT_VERSION: u32 = 2;
+/// How many older generations of a save are kept. load_save falls back to them if the newest one is corrupt.
+pub const SAVE_BACKUP_GENERATIONS: usize = 3;
+// The magic and the format version are written before the serde_bare part of the header and the manifest,
+// so a save from a newer version can be recognized without parsing the rest of it.
+const SAVE_HEADER_MAGIC: [u8; 4] = *b"GSAV";
+// only in the saves of format 1
+const SAVE_HEADER_FILE_NAME: &str = "save_header";
+const MANIFEST_FILE_PREFIX: &str = "save.";
+const MANIFEST_FILE_EXTENSION: &str = ".manifest";
+const METADATA_FILE_NAME: &str = "save.meta";
+// Archives are single-file saves in saves/<name>.gsave, the metadata is stored uncompressed before the values,
+// so list_saves doesn't have to decompress them.
+pub const SAVE_ARCHIVE_EXTENSION: &str = ".gsave";
+const SAVE_ARCHIVE_MAGIC: [u8; 4] = *b"GSAR";
+const SAVE_ARCHIVE_VERSION: u32 = 1;
+/// Values with this prefix hold the persistent objects of a system (see objects::serialization)
+/// and are saved without registration.
+pub const OBJECT_TREES_VALUE_PREFIX: &str = "objects:";
+/// The values of every player are stored as a lazy value named `player:<player id>`.
+/// They are written on save_game and when the player disconnects.
+pub const PLAYER_VALUE_PREFIX: &str = "player:";
+// Value names are used in the file names, the longer ones are cut and made unique with their hash,
+// so the names stay below the file name limits of the file systems.
+const MAX_VALUE_FILE_NAME_LENGTH: usize = 128;
 
-                        self.current_save_name = Some(save_name.into());
-                        return Ok(global_values)
-                    },
-                    Err(err) => {
-                        debugger::error(
-                            &format!(
-                                "save manager's error!\nfailed to deserialize save values list!\nfile path: {}\nerr: {}", 
-                                save_value_names_file_path, err
-                            )
-                        );
-                    },
-                }
-            },
-            Err(err) => {
+/// Regions are stored as lazy values named `region:<group>:<key>`.
+/// Versions and migrations of the regions are registered for `region:<group>`.
+pub const REGION_VALUE_PREFIX: &str = "region:";
+
+/// Upgrades a value from the version passed as the first argument to the next one.
+pub type SaveMigration = Box<dyn Fn(u32, Vec<SystemValue>) -> Result<Vec<SystemValue>, String>>;
+
+#[derive(Default)]
+pub struct SavesManager {
+    save_system_values: Vec<String>,
+    current_save_name: Option<String>,
+    lazy_values_to_save: HashMap<String, Vec<SystemValue>>,
+    // current versions of the values declared by the systems, values without one have version 0
+    values_versions: HashMap<String, u32>,
+    migrations: HashMap<String, SaveMigration>,
+    // loaded values that wait for their systems to register the version or the migration (saves are usually
+    // loaded before the systems start), they're written back unchanged until then
+    pending_values: HashMap<String, PendingValue>,
+    // values in the files of the current save
+    saved_values: HashMap<String, SavedValue>,
+    // generation of the newest manifest of the current save, the next save_game writes the next one
+    current_generation: u64,
+    metadata: SaveMetadata,
+    // playtime since this moment isn't in the metadata yet
+    session_started_at: Option<Instant>,
+    // values of the loaded (connected) players by player id
+    players: HashMap<String, HashMap<String, Vec<SystemValue>>>,
+    // requested regions by value name
+    regions: HashMap<String, Region>,
+    // regions that are read in the background push their values here, update_regions takes them
+    finished_region_loads: Arc<Mutex<Vec<FinishedRegionLoad>>>,
+    // regions that became ready without a background load, update_regions reports them
+    ready_regions: Vec<(String, String)>,
+    next_region_load_id: u64,
+}
+
+struct Region {
+    group: String,
+    key: String,
+    // results of the older loads of the region (it was unloaded or set while loading) are dropped
+    load_id: u64,
+    state: RegionState,
+    // changed since it was loaded, written with the next save
+    is_dirty: bool,
+}
+
+enum RegionState {
+    /// Version of the value that is being read.
+    Loading(u32),
+    /// None if the save doesn't have this region.
+    Loaded(Option<Vec<SystemValue>>),
+    /// The saved region can't be read or migrated. It stays in the save and set_region can't replace it,
+    /// until the region is unloaded and requested again.
+    Failed(String),
+}
+
+struct PendingValue {
+    version: u32,
+    value: Vec<SystemValue>,
+}
+
+// value name, load id and the read value
+type FinishedRegionLoad = (String, u64, Result<Vec<SystemValue>, SaveError>);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SaveMetadata {
+    /// Unix time in seconds.
+    pub created_at: u64,
+    /// Unix time in seconds.
+    pub last_played_at: u64,
+    pub playtime_seconds: u64,
+    pub engine_version: String,
+    pub format_version: u32,
+    /// PNG image.
+    pub thumbnail: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SaveInfo {
+    pub name: String,
+    /// The save is a single file archive that is imported on load_save.
+    pub is_archive: bool,
+    pub metadata: SaveMetadata,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    IoError(io::Error),
+    InvalidHeader(String),
+    DeserializeError(serde_bare::error::Error),
+    SerializeError(serde_bare::error::Error),
+    /// Wrong checksum or size of a file, usually after a crash or a full disk.
+    Corrupted(String),
+    NoCurrentSave,
+    NewerFormat { save_version: u32, supported_version: u32 },
+    NewerValue { value_name: String, save_version: u32, supported_version: u32 },
+    MigrationError { value_name: String, from_version: u32, err: String },
+    MigrationNotRegistered { value_name: String, from_version: u32 },
+    UnsupportedFormat(String),
+    InvalidSaveName(String),
+    SaveNotFound(String),
+    SaveAlreadyExists(String),
+}
+
+impl std::fmt::Display for SaveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SaveError::IoError(err) => write!(f, "io error: {}", err),
+            SaveError::InvalidHeader(err) => write!(f, "invalid save header: {}", err),
+            SaveError::DeserializeError(err) => write!(f, "failed to deserialize: {}", err),
+            SaveError::SerializeError(err) => write!(f, "failed to serialize: {}", err),
+            SaveError::Corrupted(err) => write!(f, "save is corrupted: {}", err),
+            SaveError::NoCurrentSave => write!(f, "current save is none! load/create one first"),
+            SaveError::NewerFormat { save_version, supported_version } => write!(
+                f,
+                "the save was made by a newer version of the game (save format: {}, supported: {})",
+                save_version, supported_version
+            ),
+            SaveError::NewerValue { value_name, save_version, supported_version } => write!(
+                f,
+                "value '{}' was saved by a newer version of the game (value version: {}, supported: {})",
+                value_name, save_version, supported_version
+            ),
+            SaveError::MigrationError { value_name, from_version, err } => write!(
+                f,
+                "failed to migrate value '{}' from version {}: {}",
+                value_name, from_version, err
+            ),
+            SaveError::MigrationNotRegistered { value_name, from_version } => write!(
+                f,
+                "no migration of value '{}' from version {} is registered",
+                value_name, from_version
+            ),
+            SaveError::UnsupportedFormat(err) => write!(f, "unsupported format: {}", err),
+            SaveError::InvalidSaveName(err) => write!(f, "invalid save name: {}", err),
+            SaveError::SaveNotFound(save_name) => write!(f, "save '{}' doesn't exist", save_name),
+            SaveError::SaveAlreadyExists(save_name) => write!(f, "save '{}' already exists", save_name),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct SaveHeader {
+    values_versions: HashMap<String, u32>,
+}
+
+/// Where a value of the save is stored and how to check it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct SavedValue {
+    version: u32,
+    /// File name inside of the save directory.
+    file: String,
+    /// None for the values of old saves that were written without checksums.
+    checksum: Option<u64>,
+    size: u64,
+    /// Lazy values are loaded only when they are asked for.
+    is_lazy: bool,
+}
+
+/// Written after all of the values of the generation, so the save is either fully old or fully new.
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct SaveManifest {
+    generation: u64,
+    values: HashMap<String, SavedValue>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ArchivedValue {
+    version: u32,
+    is_lazy: bool,
+    bytes: Vec<u8>,
+}
+
+impl SavesManager {
+    pub fn load_save(&mut self, save_name: &str) -> Result<HashMap<String, Vec<SystemValue>>, SaveError> {
+        if let Err(err) = validate_save_name(save_name) {
+            debugger::error(&format!("save manager's load_save error!\nerr: {}", err));
+            return Err(err);
+        }
+
+        // saves that only exist as archives are unpacked into directories first
+        let archive_path = save_archive_path(save_name);
+        if !Path::new(&save_file_path(save_name, "")).exists() && Path::new(&archive_path).exists() {
+            if let Err(err) = self.import_save_archive(&archive_path, save_name) {
                 debugger::error(
-                    &format!(
-                        "save manager's error!\nfailed to read {}\nerr: {}", 
-                        save_value_names_file_path, err
-                    )
+                    &format!("save manager's error!\nfailed to import archive of save '{}'\nerr: {}", save_name, err)
                 );
-            },
+                return Err(err);
+            }
         }
 
-        self.current_save_name = Some(save_name.into());
-        Err(())
+        let generations = manifest_generations(save_name);
+        if generations.is_empty() {
+            return self.load_old_format_save(save_name);
+        }
+
+        let mut last_err = None;
+        for generation in &generations {
+            match load_generation(save_name, *generation) {
+                Ok((format_version, manifest, values)) => {
+                    if last_err.is_some() {
+                        debugger::warn(&format!(
+                            "save manager's warning!\nloaded backup generation {} of save '{}'",
+                            generation, save_name
+                        ));
+                    }
+
+                    // the corrupt generations are newer, the next save must not overwrite them with the same number
+                    let newest_generation = generations[0];
+                    return self.finish_loading(save_name, format_version, newest_generation, manifest.values, values);
+                },
+                Err(err @ SaveError::NewerFormat { .. }) => {
+                    debugger::error(
+                        &format!("save manager's error!\nrefusing to load save '{}'\nerr: {}", save_name, err)
+                    );
+                    return Err(err);
+                },
+                Err(err) => {
+                    debugger::warn(&format!(
+                        "save manager's warning!\ngeneration {} of save '{}' can't be loaded, trying an older one\nerr: {}",
+                        generation, save_name, err
+                    ));
+                    last_err = Some(err);
+                },
+            }
+        }
+
+        let err = last_err.unwrap_or(SaveError::Corrupted("no generations".into()));
+        debugger::error(
+            &format!("save manager's error!\nall generations of save '{}' are corrupted\nerr: {}", save_name, err)
+        );
+        Err(err)
     }
 
     pub fn register_save_value(&mut self, system_value_name: &str) {
@@ -67,6 +285,52 @@ impl SavesManager {
         }
     }
 
+    /// Registers the value and declares it's current version. Older saved values are upgraded with the migration on load.
+    pub fn register_save_value_with_version(&mut self, system_value_name: &str, version: u32) {
+        self.register_save_value(system_value_name);
+        self.values_versions.insert(system_value_name.into(), version);
+    }
+
+    /// The migration is called once for every version between the saved and the current one.
+    pub fn register_save_migration(&mut self, system_value_name: &str, migration: SaveMigration) {
+        self.migrations.insert(system_value_name.into(), migration);
+    }
+
+    pub fn unregister_save_migration(&mut self, system_value_name: &str) {
+        self.migrations.remove(system_value_name);
+    }
+
+    /// Returns the loaded value that waited for the version or the migration to be registered,
+    /// if it can be upgraded to the current version now.
+    pub fn take_migrated_value(&mut self, system_value_name: &str) -> Option<Vec<SystemValue>> {
+        let pending_value = self.pending_values.get(system_value_name)?;
+        match self.migrate_value(system_value_name, pending_value.version, pending_value.value.clone()) {
+            Ok(value) => {
+                self.pending_values.remove(system_value_name);
+                Some(value)
+            },
+            // waiting for the migration
+            Err(SaveError::MigrationNotRegistered { .. }) => None,
+            Err(err @ SaveError::NewerValue { .. }) => {
+                debugger::error(
+                    &format!("save manager's error!\nvalue '{}' is not loaded and is kept in the save unchanged\nerr: {}", system_value_name, err)
+                );
+                None
+            },
+            Err(err) => {
+                debugger::error(
+                    &format!("save manager's error!\nvalue '{}' is skipped\nerr: {}", system_value_name, err)
+                );
+                self.pending_values.remove(system_value_name);
+                None
+            },
+        }
+    }
+
+    pub fn get_save_value_version(&self, system_value_name: &str) -> u32 {
+        self.values_versions.get(system_value_name).copied().unwrap_or(0)
+    }
+
     pub fn unregister_save_value(&mut self, system_value_name: &str) {
         self.save_system_values
             .retain(|value| value != system_value_name);
@@ -77,8 +341,12 @@ impl SavesManager {
         save_name: &str,
         global_values: &HashMap<String, Vec<SystemValue>>,
     ) -> Result<(), io::Error> {
-        let save_dir_path = "saves/";
-        let save_dir_path = get_full_asset_path(save_dir_path);
+        if let Err(err) = validate_save_name(save_name) {
+            debugger::error(&format!("saves manager's new_save error!\nerr: {}", err));
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, err.to_string()));
+        }
+
+        let save_dir_path = saves_dir_path();
 
         if !Path::new(&save_dir_path).exists() {
             if let Err(err) = fs::create_dir(&save_dir_path) {
@@ -101,15 +369,6 @@ impl SavesManager {
                 ));
                 return Err(err);
             }
-            if let Err(err) = File::create_new(save_path + "save_values.val") {
-                debugger::error(
-                    &format!(
-                        "saves manager's new_save error!\nfailed to create 'saves/{}/save_values.val' file!\nerr: {}",
-                        save_name, err
-                    )
-                );
-                return Err(err);
-            }
         }
 
         println!(
@@ -117,42 +376,409 @@ impl SavesManager {
             save_name
         );
         self.current_save_name = Some(save_name.into());
-        self.save_game(global_values);
+        self.saved_values.clear();
+        self.pending_values.clear();
+        self.current_generation = manifest_generations(save_name).first().copied().unwrap_or(0);
+        self.metadata = SaveMetadata {
+            created_at: unix_time(),
+            ..Default::default()
+        };
+        self.session_started_at = Some(Instant::now());
+        self.reload_players();
+        self.reset_regions();
+        if let Err(err) = self.save_game(global_values) {
+            return Err(io::Error::new(io::ErrorKind::Other, err.to_string()));
+        }
 
         Ok(())
     }
 
-    pub fn save_game(&mut self, global_values: &HashMap<String, Vec<SystemValue>>) {
-        let mut values_list: HashMap<&str, Vec<SystemValue>> = HashMap::new();
-        let mut save_values_keys_list: Vec<&str> = Vec::new();
+    /// Writes a new generation of the current save. If anything fails, the previous generation stays untouched.
+    pub fn save_game(&mut self, global_values: &HashMap<String, Vec<SystemValue>>) -> Result<(), SaveError> {
+        let current_save_name = match &self.current_save_name {
+            Some(current_save_name) => current_save_name.clone(),
+            None => {
+                debugger::error("save manager's save_game error!\ncurrent save file is none! load/create one first");
+                return Err(SaveError::NoCurrentSave);
+            },
+        };
 
-        for value_name in &self.save_system_values {
-            let value = global_values.get(value_name);
-            match value {
-                Some(value) => {
-                    values_list.insert(value_name, value.to_vec());
-                    save_values_keys_list.push(&value_name);
+        let manifest = match self.write_generation(&current_save_name, self.current_generation + 1, global_values) {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                debugger::error(
+                    &format!("save manager's save_game error!\nfailed to save '{}', the previous save is kept\nerr: {}", current_save_name, err)
+                );
+                return Err(err);
+            },
+        };
+
+        self.current_generation = manifest.generation;
+        self.saved_values = manifest.values;
+        remove_old_generations(&current_save_name);
+
+        // the regions are in the files now
+        for region in self.regions.values_mut() {
+            region.is_dirty = false;
+        }
+        self.lazy_values_to_save.retain(|value_name, _| !value_name.starts_with(REGION_VALUE_PREFIX));
+
+        if let Some(session_started_at) = self.session_started_at.replace(Instant::now()) {
+            self.metadata.playtime_seconds += session_started_at.elapsed().as_secs();
+        }
+        self.metadata.last_played_at = unix_time();
+        self.metadata.engine_version = env!("CARGO_PKG_VERSION").into();
+        self.metadata.format_version = SAVE_FORMAT_VERSION;
+        // the metadata is only for the menus, the save is complete without it
+        if let Err(err) = write_metadata(&current_save_name, &self.metadata) {
+            debugger::warn(
+                &format!("save manager's save_game warning!\nfailed to write the metadata of '{}'\nerr: {}", current_save_name, err)
+            );
+        }
+
+        Ok(())
+    }
+
+    pub fn current_save_name(&self) -> Option<&str> {
+        self.current_save_name.as_deref()
+    }
+
+    /// Sets the PNG image that is stored with the next saves (e.g. a screenshot for the load game menu).
+    pub fn set_save_thumbnail(&mut self, thumbnail: Option<Vec<u8>>) {
+        self.metadata.thumbnail = thumbnail;
+    }
+
+    pub fn get_current_save_metadata(&self) -> Option<SaveMetadata> {
+        self.current_save_name.as_ref()?;
+        let mut metadata = self.metadata.clone();
+        if let Some(session_started_at) = self.session_started_at {
+            metadata.playtime_seconds += session_started_at.elapsed().as_secs();
+        }
+        Some(metadata)
+    }
+
+    /// Returns the saves in the saves directory (both directories and archives), the last played first.
+    pub fn list_saves(&self) -> Vec<SaveInfo> {
+        let entries = match fs::read_dir(saves_dir_path()) {
+            Ok(entries) => entries,
+            Err(_) => return vec![],
+        };
+
+        let mut saves: Vec<SaveInfo> = Vec::new();
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let file_name = match entry.file_name().into_string() {
+                Ok(file_name) => file_name,
+                Err(_) => continue,
+            };
+
+            if entry.path().is_dir() {
+                if validate_save_name(&file_name).is_err() {
+                    continue;
                 }
-                None => debugger::warn(
-                    &format!(
-                        "saves manager's save_game warning!\nfailed to get global system value '{}'",
-                        value_name
-                    )
-                ),
+                saves.retain(|save| save.name != file_name);
+                saves.push(SaveInfo {
+                    metadata: read_metadata(&file_name).unwrap_or_default(),
+                    name: file_name,
+                    is_archive: false,
+                });
+            } else if let Some(save_name) = file_name.strip_suffix(SAVE_ARCHIVE_EXTENSION) {
+                if validate_save_name(save_name).is_err() {
+                    continue;
+                }
+                // if both exist, the directory is the one that is loaded
+                if saves.iter().any(|save| save.name == save_name) || Path::new(&save_file_path(save_name, "")).exists() {
+                    continue;
+                }
+                match read_archive_metadata(&save_archive_path(save_name)) {
+                    Ok(metadata) => saves.push(SaveInfo {
+                        name: save_name.into(),
+                        is_archive: true,
+                        metadata,
+                    }),
+                    Err(err) => debugger::warn(
+                        &format!("save manager's list_saves warning!\nfailed to read archive {}\nerr: {}", file_name, err)
+                    ),
+                }
+            }
+        }
+
+        saves.sort_by(|a, b| b.metadata.last_played_at.cmp(&a.metadata.last_played_at));
+        saves
+    }
+
+    pub fn save_exists(&self, save_name: &str) -> bool {
+        validate_save_name(save_name).is_ok()
+            && (Path::new(&save_file_path(save_name, "")).exists() || Path::new(&save_archive_path(save_name)).exists())
+    }
+
+    /// Renames both the directory and the archive of the save. Renaming the current save keeps it current.
+    pub fn rename_save(&mut self, save_name: &str, new_save_name: &str) -> Result<(), SaveError> {
+        self.check_save_operation(save_name, new_save_name)?;
+
+        let paths = [
+            (save_file_path(save_name, ""), save_file_path(new_save_name, "")),
+            (save_archive_path(save_name), save_archive_path(new_save_name)),
+        ];
+        let mut renamed_paths = vec![];
+        for (path, new_path) in paths {
+            if Path::new(&path).exists() {
+                if let Err(err) = fs::rename(&path, &new_path) {
+                    // the save is either renamed completely or not at all
+                    for (path, new_path) in renamed_paths {
+                        if let Err(rollback_err) = fs::rename(&new_path, &path) {
+                            debugger::error(&format!(
+                                "saves manager's rename_save error!\nfailed to rename '{}' back to '{}'\nerr: {}",
+                                new_path, path, rollback_err
+                            ));
+                        }
+                    }
+                    return Err(SaveError::IoError(err));
+                }
+                renamed_paths.push((path, new_path));
+            }
+        }
+
+        if self.current_save_name.as_deref() == Some(save_name) {
+            self.current_save_name = Some(new_save_name.into());
+        }
+
+        println!("saves manager: save '{}' renamed to '{}'", save_name, new_save_name);
+        Ok(())
+    }
+
+    /// Copies both the directory and the archive of the save, the copy is never the current save.
+    pub fn copy_save(&mut self, save_name: &str, new_save_name: &str) -> Result<(), SaveError> {
+        self.check_save_operation(save_name, new_save_name)?;
+
+        let save_path = save_file_path(save_name, "");
+        if Path::new(&save_path).exists() {
+            let new_save_path = save_file_path(new_save_name, "");
+            fs::create_dir_all(&new_save_path).map_err(SaveError::IoError)?;
+
+            for file_name in save_dir_files(save_name) {
+                // half written files of the original are garbage
+                if file_name.ends_with(".tmp") {
+                    continue;
+                }
+                let result = fs::copy(save_file_path(save_name, &file_name), save_file_path(new_save_name, &file_name));
+                if let Err(err) = result {
+                    let _ = fs::remove_dir_all(&new_save_path);
+                    return Err(SaveError::IoError(err));
+                }
+            }
+        }
+
+        let archive_path = save_archive_path(save_name);
+        if Path::new(&archive_path).exists() {
+            if let Err(err) = fs::copy(&archive_path, save_archive_path(new_save_name)) {
+                let _ = fs::remove_dir_all(save_file_path(new_save_name, ""));
+                return Err(SaveError::IoError(err));
             }
         }
 
-        if let Some(current_save_name) = &self.current_save_name {
-            for (value_name, value) in values_list.iter() {
-                save_value_to_file(current_save_name, value, value_name);
+        println!("saves manager: save '{}' copied to '{}'", save_name, new_save_name);
+        Ok(())
+    }
+
+    /// Deletes both the directory and the archive of the save. Deleting the current save leaves no current save,
+    /// the loaded players and regions lose their values.
+    pub fn delete_save(&mut self, save_name: &str) -> Result<(), SaveError> {
+        validate_save_name(save_name)?;
+        if !self.save_exists(save_name) {
+            return Err(SaveError::SaveNotFound(save_name.into()));
+        }
+
+        let save_path = save_file_path(save_name, "");
+        if Path::new(&save_path).exists() {
+            fs::remove_dir_all(&save_path).map_err(SaveError::IoError)?;
+        }
+        let archive_path = save_archive_path(save_name);
+        if Path::new(&archive_path).exists() {
+            fs::remove_file(&archive_path).map_err(SaveError::IoError)?;
+        }
+
+        if self.current_save_name.as_deref() == Some(save_name) {
+            self.current_save_name = None;
+            self.saved_values.clear();
+            self.pending_values.clear();
+            self.lazy_values_to_save.clear();
+            self.current_generation = 0;
+            self.metadata = SaveMetadata::default();
+            self.session_started_at = None;
+            self.reload_players();
+            self.reset_regions();
+        }
+
+        println!("saves manager: save '{}' deleted", save_name);
+        Ok(())
+    }
+
+    fn check_save_operation(&self, save_name: &str, new_save_name: &str) -> Result<(), SaveError> {
+        validate_save_name(save_name)?;
+        validate_save_name(new_save_name)?;
+        if !self.save_exists(save_name) {
+            return Err(SaveError::SaveNotFound(save_name.into()));
+        }
+        if self.save_exists(new_save_name) {
+            return Err(SaveError::SaveAlreadyExists(new_save_name.into()));
+        }
+        Ok(())
+    }
+
+    /// Packs the newest valid generation of the save into a single compressed file.
+    /// If archive_path is None, the archive is written to saves/<save_name>.gsave.
+    pub fn export_save_archive(&self, save_name: &str, archive_path: Option<&str>) -> Result<(), SaveError> {
+        validate_save_name(save_name)?;
+        let generations = manifest_generations(save_name);
+        if generations.is_empty() {
+            return Err(SaveError::UnsupportedFormat(format!(
+                "save '{}' was made by an older version, load and save it before exporting",
+                save_name
+            )));
+        }
+
+        let mut last_err = None;
+        for generation in generations {
+            let result = read_manifest(save_name, generation).and_then(|(_, manifest)| {
+                let mut values = HashMap::new();
+                for (value_name, saved_value) in manifest.values {
+                    let bytes = read_checked_file(save_name, &saved_value)?;
+                    values.insert(value_name, ArchivedValue {
+                        version: saved_value.version,
+                        is_lazy: saved_value.is_lazy,
+                        bytes,
+                    });
+                }
+                Ok(values)
+            });
+
+            match result {
+                Ok(values) => {
+                    let metadata = read_metadata(save_name).unwrap_or_default();
+                    let archive_path = match archive_path {
+                        Some(archive_path) => archive_path.to_string(),
+                        None => save_archive_path(save_name),
+                    };
+                    return write_archive(&archive_path, &metadata, &values);
+                },
+                Err(err) => last_err = Some(err),
             }
+        }
+
+        Err(last_err.unwrap_or(SaveError::Corrupted("no generations".into())))
+    }
+
+    /// Unpacks the archive into saves/<save_name>/. If the save already exists, the archive becomes it's newest generation.
+    pub fn import_save_archive(&mut self, archive_path: &str, save_name: &str) -> Result<(), SaveError> {
+        validate_save_name(save_name)?;
+        let (metadata, values) = read_archive(archive_path)?;
 
-            for (value_name, value) in self.lazy_values_to_save.iter() {
-                save_value_to_file(current_save_name, value, value_name);
+        let save_path = save_file_path(save_name, "");
+        fs::create_dir_all(&save_path).map_err(SaveError::IoError)?;
+
+        let generation = manifest_generations(save_name).first().copied().unwrap_or(0) + 1;
+        let mut manifest = SaveManifest {
+            generation,
+            values: HashMap::new(),
+        };
+        for (value_name, archived_value) in values {
+            // the value name came from the archive, escaping it keeps the file inside of the save directory
+            let file = value_file_name(&value_name, generation);
+            write_file_atomically(&save_file_path(save_name, &file), &archived_value.bytes).map_err(SaveError::IoError)?;
+            manifest.values.insert(value_name, SavedValue {
+                version: archived_value.version,
+                file,
+                checksum: Some(fnv1a(&archived_value.bytes)),
+                size: archived_value.bytes.len() as u64,
+                is_lazy: archived_value.is_lazy,
+            });
+        }
+
+        write_manifest(save_name, &manifest)?;
+        write_metadata(save_name, &metadata).map_err(SaveError::IoError)?;
+        remove_old_generations(save_name);
+
+        println!("saves manager: archive '{}' imported as save '{}'", archive_path, save_name);
+        Ok(())
+    }
+
+    /// Loads the saved values of the player from the current save. Players without saved data start with no values.
+    pub fn load_player(&mut self, player_id: &str) -> Result<(), SaveError> {
+        let saved_value = self.saved_values.get(&player_value_name(player_id));
+        let values = match (&self.current_save_name, saved_value) {
+            (Some(save_name), Some(saved_value)) => player_values_from_value(read_value_file(save_name, saved_value)?)?,
+            _ => HashMap::new(),
+        };
+        self.players.insert(player_id.into(), values);
+        Ok(())
+    }
+
+    /// Writes the values of the loaded player into the current save. The save gets a new generation
+    /// in which everything else stays in the files of the current one.
+    pub fn save_player(&mut self, player_id: &str) -> Result<(), SaveError> {
+        let save_name = match &self.current_save_name {
+            Some(save_name) => save_name.clone(),
+            None => return Err(SaveError::NoCurrentSave),
+        };
+        let values = match self.players.get(player_id) {
+            Some(values) => values,
+            None => return Ok(()),
+        };
+
+        let mut manifest = SaveManifest {
+            generation: self.current_generation + 1,
+            values: self.saved_values.clone(),
+        };
+        let value_name = player_value_name(player_id);
+        let saved_value = write_value_file(&save_name, &value_name, manifest.generation, 0, &player_value(values), true)?;
+        manifest.values.insert(value_name, saved_value);
+        write_manifest(&save_name, &manifest)?;
+
+        self.current_generation = manifest.generation;
+        self.saved_values = manifest.values;
+        remove_old_generations(&save_name);
+        Ok(())
+    }
+
+    /// Saves the player and forgets it's values.
+    pub fn unload_player(&mut self, player_id: &str) -> Result<(), SaveError> {
+        let result = match self.current_save_name {
+            Some(_) => self.save_player(player_id),
+            None => Ok(()),
+        };
+        self.players.remove(player_id);
+        result
+    }
+
+    pub fn is_player_loaded(&self, player_id: &str) -> bool {
+        self.players.contains_key(player_id)
+    }
+
+    /// Returns false if the player isn't loaded.
+    pub fn set_player_value(&mut self, player_id: &str, value_name: &str, value: Vec<SystemValue>) -> bool {
+        match self.players.get_mut(player_id) {
+            Some(values) => {
+                values.insert(value_name.into(), value);
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn get_player_value(&self, player_id: &str, value_name: &str) -> Option<Vec<SystemValue>> {
+        self.players.get(player_id)?.get(value_name).cloned()
+    }
+
+    // the loaded players get their values from the new current save
+    fn reload_players(&mut self) {
+        let player_ids: Vec<String> = self.players.keys().cloned().collect();
+        for player_id in player_ids {
+            if let Err(err) = self.load_player(&player_id) {
+                debugger::error(
+                    &format!("save manager's error!\nfailed to load player '{}'\nerr: {}", player_id, err)
+                );
+                self.players.insert(player_id, HashMap::new());
             }
-            save_value_to_file(current_save_name, &save_values_keys_list, "save_values");
-        } else {
-            debugger::error("save manager's save_game error!\ncurrent save file is none! load/create one first");
         }
     }
 
@@ -165,7 +791,25 @@ impl SavesManager {
             Some(value) => Some(value.to_vec()),
             None => {
                 if let Some(current_save_name) = &self.current_save_name {
-                    load_value_from_file(current_save_name, value_name)
+                    let saved_value = self.saved_values.get(value_name)?;
+                    let value = match read_value_file(current_save_name, saved_value) {
+                        Ok(value) => value,
+                        Err(err) => {
+                            debugger::error(
+                                &format!("save manager's load_lazy_value error!\nfailed to load value {}\nerr: {}", value_name, err)
+                            );
+                            return None;
+                        },
+                    };
+                    match self.migrate_value(value_name, saved_value.version, value) {
+                        Ok(value) => Some(value),
+                        Err(err) => {
+                            debugger::error(
+                                &format!("save manager's load_lazy_value error!\nfailed to load value {}\nerr: {}", value_name, err)
+                            );
+                            None
+                        },
+                    }
                 } else {
                     debugger::error(
                         &format!(
@@ -179,63 +823,1033 @@ impl SavesManager {
             },
         }
     }
-}
 
-fn save_value_to_file(current_save_name: &str, value: &impl Serialize, value_name: &str) {
-    let result = serde_bare::to_vec(value);
+    /// Starts loading the region in the background. Check it with is_region_ready or wait for update_regions to report it.
+    /// Group names can't contain ':'.
+    pub fn request_region(&mut self, group: &str, key: &str) {
+        let value_name = match region_value_name(group, key) {
+            Some(value_name) => value_name,
+            None => {
+                debugger::error(&format!("save manager's request_region error!\ninvalid region group '{}'", group));
+                return;
+            },
+        };
+        if self.regions.contains_key(&value_name) {
+            return;
+        }
+
+        let load_id = self.next_region_load_id;
+        self.next_region_load_id += 1;
+        let mut region = Region {
+            group: group.into(),
+            key: key.into(),
+            load_id,
+            state: RegionState::Loaded(None),
+            is_dirty: false,
+        };
 
-    match result {
-        Ok(value) => {
-            let save_file_path = "saves/".to_string() + current_save_name + "/" + value_name + ".val";
-            let save_file_path = get_full_asset_path(&save_file_path);
+        // the region was unloaded before its changes were saved
+        if let Some(value) = self.lazy_values_to_save.remove(&value_name) {
+            region.state = RegionState::Loaded(Some(value));
+            region.is_dirty = true;
+            self.regions.insert(value_name, region);
+            self.ready_regions.push((group.into(), key.into()));
+            return;
+        }
 
-            if Path::new(&save_file_path).exists() {
-                if let Err(err) = fs::remove_file(&save_file_path) {
-                    debugger::error(
-                        &format!("save manager's save_game error!\nfailed to remove the old save file!\nerr: {}, path: {}", err, save_file_path)
-                    );
-                }
+        let (save_name, saved_value) = match (&self.current_save_name, self.saved_values.get(&value_name)) {
+            (Some(save_name), Some(saved_value)) => (save_name.clone(), saved_value.clone()),
+            _ => {
+                self.regions.insert(value_name, region);
+                self.ready_regions.push((group.into(), key.into()));
+                return;
+            },
+        };
+
+        region.state = RegionState::Loading(saved_value.version);
+        self.regions.insert(value_name.clone(), region);
+
+        let finished_region_loads = Arc::clone(&self.finished_region_loads);
+        rayon::spawn(move || {
+            let result = read_value_file(&save_name, &saved_value);
+            if let Ok(mut finished_region_loads) = finished_region_loads.lock() {
+                finished_region_loads.push((value_name, load_id, result));
             }
+        });
+    }
 
-            match File::create(&save_file_path) {
-                Ok(mut file) => {
-                    if let Err(err) = file.write_all(&value) {
-                        debugger::error(
-                            &format!("save manager's save_game error!\nfailed to write the file\nerr: {}, path: {}", err, save_file_path)
-                        );
-                    }
-                },
+    /// Takes the finished background loads, returns the regions (group, key) that became ready or failed since the last call.
+    pub fn update_regions(&mut self) -> Vec<(String, String)> {
+        let finished_region_loads = match self.finished_region_loads.lock() {
+            Ok(mut finished_region_loads) => std::mem::take(&mut *finished_region_loads),
+            Err(_) => Vec::new(),
+        };
+        let mut ready_regions = std::mem::take(&mut self.ready_regions);
+
+        for (value_name, load_id, result) in finished_region_loads {
+            let (group, key, saved_version) = match self.regions.get(&value_name) {
+                Some(Region { group, key, load_id: region_load_id, state: RegionState::Loading(saved_version), .. })
+                    if *region_load_id == load_id => (group.clone(), key.clone(), *saved_version),
+                _ => continue,
+            };
+
+            let state = match result.and_then(|value| self.migrate_value(&region_group_value_name(&group), saved_version, value)) {
+                Ok(value) => RegionState::Loaded(Some(value)),
                 Err(err) => {
                     debugger::error(
-                        &format!("save manager's save_game error!\nfailed to open the save file\nerr: {}, path: {}", err, save_file_path)
+                        &format!("save manager's update_regions error!\nfailed to load region '{}' of group '{}'\nerr: {}", key, group, err)
                     );
-                }
+                    RegionState::Failed(err.to_string())
+                },
+            };
+
+            if let Some(region) = self.regions.get_mut(&value_name) {
+                region.state = state;
             }
+            ready_regions.push((group, key));
         }
-        Err(err) => debugger::error(
-            &format!(
-                "save manager's save_game error!\n failed to serialize data!\nerror: {}",
-                err
-            )
-        ),
+
+        ready_regions
     }
-}
 
-fn load_value_from_file(current_save_name: &str, value_name: &str) -> Option<Vec<SystemValue>> {
-    let save_file_path = "saves/".to_string() + current_save_name + "/" + value_name + ".val";
-    let save_file_path = get_full_asset_path(&save_file_path);
+    pub fn is_region_ready(&self, group: &str, key: &str) -> bool {
+        let region = region_value_name(group, key).and_then(|value_name| self.regions.get(&value_name));
+        matches!(region, Some(Region { state: RegionState::Loaded(_), .. }))
+    }
 
-    let mut bytes = Vec::new();
-    match File::open(&save_file_path) {
-        Ok(mut file) => {
-            if let Err(err) = file.read_to_end(&mut bytes) {
-                debugger::error(
-                    &format!("save manager's load_game error!\nfailed to read the save file\nerr: {}, path: {}", err, save_file_path)
-                );
-                return None
-            }
+    /// None if the region isn't ready, failed to load or the save doesn't have it.
+    pub fn get_region(&self, group: &str, key: &str) -> Option<Vec<SystemValue>> {
+        let region = self.regions.get(&region_value_name(group, key)?)?;
+        match &region.state {
+            RegionState::Loaded(value) => value.clone(),
+            RegionState::Loading(_) | RegionState::Failed(_) => None,
         }
-        Err(err) => {
+    }
+
+    /// Some if the saved region failed to load. Unlike a region that isn't in the save, it shouldn't be generated again.
+    pub fn get_region_error(&self, group: &str, key: &str) -> Option<String> {
+        let region = self.regions.get(&region_value_name(group, key)?)?;
+        match &region.state {
+            RegionState::Failed(err) => Some(err.clone()),
+            _ => None,
+        }
+    }
+
+    /// Replaces the value of the region (loading it is not required) and marks it to be written with the next save.
+    pub fn set_region(&mut self, group: &str, key: &str, value: Vec<SystemValue>) {
+        let value_name = match region_value_name(group, key) {
+            Some(value_name) => value_name,
+            None => {
+                debugger::error(&format!("save manager's set_region error!\ninvalid region group '{}'", group));
+                return;
+            },
+        };
+        // otherwise the next save would overwrite the data that couldn't be read
+        if let Some(Region { state: RegionState::Failed(err), .. }) = self.regions.get(&value_name) {
+            debugger::error(&format!(
+                "save manager's set_region error!\nregion '{}' of group '{}' failed to load, it's saved data can't be replaced\nerr: {}",
+                key, group, err
+            ));
+            return;
+        }
+
+        let load_id = self.next_region_load_id;
+        self.next_region_load_id += 1;
+        self.lazy_values_to_save.remove(&value_name);
+        self.regions.insert(value_name, Region {
+            group: group.into(),
+            key: key.into(),
+            load_id,
+            state: RegionState::Loaded(Some(value)),
+            is_dirty: true,
+        });
+    }
+
+    /// Frees the region. The unsaved changes are kept until the next save.
+    pub fn unload_region(&mut self, group: &str, key: &str) {
+        let region = match region_value_name(group, key) {
+            Some(value_name) => self.regions.remove(&value_name).map(|region| (value_name, region)),
+            None => None,
+        };
+
+        if let Some((value_name, Region { state: RegionState::Loaded(Some(value)), is_dirty: true, .. })) = region {
+            self.lazy_values_to_save.insert(value_name, value);
+        }
+    }
+
+    /// Keys of the requested (loading or ready) regions of the group.
+    pub fn requested_regions(&self, group: &str) -> Vec<String> {
+        self.regions
+            .values()
+            .filter(|region| region.group == group)
+            .map(|region| region.key.clone())
+            .collect()
+    }
+
+    // regions of the previous save are dropped, the loads that are still running are ignored
+    fn reset_regions(&mut self) {
+        self.regions.clear();
+        self.ready_regions.clear();
+        self.lazy_values_to_save.retain(|value_name, _| !value_name.starts_with(REGION_VALUE_PREFIX));
+    }
+
+    // versions of the regions are registered for their group
+    fn lazy_value_version(&self, value_name: &str) -> u32 {
+        match region_group_of_value(value_name) {
+            Some(group) => self.get_save_value_version(&region_group_value_name(group)),
+            None => self.get_save_value_version(value_name),
+        }
+    }
+
+    fn write_generation(
+        &self,
+        save_name: &str,
+        generation: u64,
+        global_values: &HashMap<String, Vec<SystemValue>>,
+    ) -> Result<SaveManifest, SaveError> {
+        let mut manifest = SaveManifest {
+            generation,
+            values: HashMap::new(),
+        };
+
+        for value_name in &self.save_system_values {
+            match global_values.get(value_name) {
+                Some(value) => {
+                    let version = self.get_save_value_version(value_name);
+                    let saved_value = write_value_file(save_name, value_name, generation, version, value, false)?;
+                    manifest.values.insert(value_name.clone(), saved_value);
+                },
+                // written below
+                None if self.pending_values.contains_key(value_name) => (),
+                None => debugger::warn(
+                    &format!(
+                        "saves manager's save_game warning!\nfailed to get global system value '{}'",
+                        value_name
+                    )
+                ),
+            }
+        }
+
+        for (value_name, pending_value) in &self.pending_values {
+            if !manifest.values.contains_key(value_name) {
+                let saved_value = write_value_file(save_name, value_name, generation, pending_value.version, &pending_value.value, false)?;
+                manifest.values.insert(value_name.clone(), saved_value);
+            }
+        }
+
+        for (value_name, value) in global_values {
+            if value_name.starts_with(OBJECT_TREES_VALUE_PREFIX) {
+                let saved_value = write_value_file(save_name, value_name, generation, 0, value, false)?;
+                manifest.values.insert(value_name.clone(), saved_value);
+            }
+        }
+
+        for (value_name, value) in &self.lazy_values_to_save {
+            let version = self.lazy_value_version(value_name);
+            let saved_value = write_value_file(save_name, value_name, generation, version, value, true)?;
+            manifest.values.insert(value_name.clone(), saved_value);
+        }
+
+        for (value_name, region) in &self.regions {
+            if let Region { state: RegionState::Loaded(Some(value)), is_dirty: true, .. } = region {
+                let version = self.lazy_value_version(value_name);
+                let saved_value = write_value_file(save_name, value_name, generation, version, value, true)?;
+                manifest.values.insert(value_name.clone(), saved_value);
+            }
+        }
+
+        for (player_id, values) in &self.players {
+            let value_name = player_value_name(player_id);
+            let saved_value = write_value_file(save_name, &value_name, generation, 0, &player_value(values), true)?;
+            manifest.values.insert(value_name, saved_value);
+        }
+
+        // lazy values that weren't changed stay in the files of the older generations
+        for (value_name, saved_value) in &self.saved_values {
+            if saved_value.is_lazy && !manifest.values.contains_key(value_name) {
+                manifest.values.insert(value_name.clone(), saved_value.clone());
+            }
+        }
+
+        write_manifest(save_name, &manifest)?;
+        Ok(manifest)
+    }
+
+    // format 0 and 1 saves: a list of the values and a file for every value
+    fn load_old_format_save(&mut self, save_name: &str) -> Result<HashMap<String, Vec<SystemValue>>, SaveError> {
+        let (format_version, header) = match read_save_header(save_name) {
+            Ok(header) => header,
+            Err(err) => {
+                debugger::error(
+                    &format!("save manager's error!\nrefusing to load save '{}'\nerr: {}", save_name, err)
+                );
+                return Err(err);
+            }
+        };
+
+        let save_value_names_file_path = save_file_path(save_name, "save_values.val");
+
+        match fs::read(&save_value_names_file_path) {
+            Ok(save_values_names) => {
+                match serde_bare::from_slice::<Vec<String>>(&save_values_names) {
+                    Ok(save_value_names) => {
+                        let mut saved_values = HashMap::new();
+                        for file_name in save_dir_files(save_name) {
+                            let value_name = match file_name.strip_suffix(".val") {
+                                Some(value_name) if value_name != "save_values" && value_name != SAVE_HEADER_FILE_NAME => value_name,
+                                _ => continue,
+                            };
+                            saved_values.insert(value_name.to_string(), SavedValue {
+                                version: header.values_versions.get(value_name).copied().unwrap_or(0),
+                                file: file_name.clone(),
+                                checksum: None,
+                                size: 0,
+                                is_lazy: !save_value_names.iter().any(|name| name == value_name),
+                            });
+                        }
+
+                        let mut loaded_values = HashMap::new();
+                        for value_name in save_value_names {
+                            if let Some(value) = load_value_from_file(save_name, &value_name) {
+                                loaded_values.insert(value_name, value);
+                            }
+                        }
+
+                        self.finish_loading(save_name, format_version, 0, saved_values, loaded_values)
+                    },
+                    Err(err) => {
+                        debugger::error(
+                            &format!(
+                                "save manager's error!\nfailed to deserialize save values list!\nfile path: {}\nerr: {}",
+                                save_value_names_file_path, err
+                            )
+                        );
+                        self.current_save_name = Some(save_name.into());
+                        Err(SaveError::DeserializeError(err))
+                    },
+                }
+            },
+            Err(err) => {
+                debugger::error(
+                    &format!(
+                        "save manager's error!\nfailed to read {}\nerr: {}",
+                        save_value_names_file_path, err
+                    )
+                );
+                self.current_save_name = Some(save_name.into());
+                Err(SaveError::IoError(err))
+            },
+        }
+    }
+
+    fn finish_loading(
+        &mut self,
+        save_name: &str,
+        format_version: u32,
+        generation: u64,
+        saved_values: HashMap<String, SavedValue>,
+        loaded_values: HashMap<String, Vec<SystemValue>>,
+    ) -> Result<HashMap<String, Vec<SystemValue>>, SaveError> {
+        let mut global_values: HashMap<String, Vec<SystemValue>> = HashMap::new();
+        let mut pending_values = HashMap::new();
+        let loaded_values = migrate_format(format_version, loaded_values);
+
+        for (value_name, value) in loaded_values {
+            let saved_version = saved_values.get(&value_name).map(|saved_value| saved_value.version).unwrap_or(0);
+            let is_version_registered = self.values_versions.contains_key(&value_name);
+            match self.migrate_value(&value_name, saved_version, value.clone()) {
+                Ok(value) => {
+                    global_values.insert(value_name, value);
+                },
+                // the system that owns the value didn't register it yet, take_migrated_value finishes it
+                Err(SaveError::NewerValue { .. }) if !is_version_registered => {
+                    pending_values.insert(value_name, PendingValue { version: saved_version, value });
+                },
+                Err(SaveError::MigrationNotRegistered { .. }) => {
+                    pending_values.insert(value_name, PendingValue { version: saved_version, value });
+                },
+                Err(err @ SaveError::NewerValue { .. }) => {
+                    debugger::error(
+                        &format!("save manager's error!\nrefusing to load save '{}'\nerr: {}", save_name, err)
+                    );
+                    return Err(err);
+                },
+                Err(err) => debugger::error(
+                    &format!("save manager's error!\nvalue '{}' is skipped\nerr: {}", value_name, err)
+                ),
+            }
+        }
+
+        self.current_save_name = Some(save_name.into());
+        self.current_generation = generation;
+        self.saved_values = saved_values;
+        self.pending_values = pending_values;
+        self.metadata = read_metadata(save_name).unwrap_or(SaveMetadata {
+            created_at: unix_time(),
+            ..Default::default()
+        });
+        self.session_started_at = Some(Instant::now());
+        self.reload_players();
+        self.reset_regions();
+        Ok(global_values)
+    }
+
+    fn migrate_value(&self, value_name: &str, saved_version: u32, mut value: Vec<SystemValue>) -> Result<Vec<SystemValue>, SaveError> {
+        let current_version = self.get_save_value_version(value_name);
+        if saved_version > current_version {
+            return Err(SaveError::NewerValue {
+                value_name: value_name.into(),
+                save_version: saved_version,
+                supported_version: current_version,
+            });
+        }
+
+        for from_version in saved_version..current_version {
+            let migration = match self.migrations.get(value_name) {
+                Some(migration) => migration,
+                None => return Err(SaveError::MigrationNotRegistered {
+                    value_name: value_name.into(),
+                    from_version,
+                }),
+            };
+
+            value = match migration(from_version, value) {
+                Ok(value) => value,
+                Err(err) => return Err(SaveError::MigrationError {
+                    value_name: value_name.into(),
+                    from_version,
+                    err,
+                }),
+            };
+        }
+
+        Ok(value)
+    }
+}
+
+// Upgrades the values of saves made with an older format. Every step is from the version to the next one.
+fn migrate_format(format_version: u32, values: HashMap<String, Vec<SystemValue>>) -> HashMap<String, Vec<SystemValue>> {
+    for from_version in format_version..SAVE_FORMAT_VERSION {
+        match from_version {
+            // version 1 only added the header, the values are the same
+            0 => (),
+            // version 2 only changed how the files are written
+            1 => (),
+            _ => debugger::warn(
+                &format!("save manager's warning!\nno migration from save format {}", from_version)
+            ),
+        }
+    }
+
+    values
+}
+
+// Returns the format version and the header of a format 1 save. Saves without a header are of version 0.
+fn read_save_header(save_name: &str) -> Result<(u32, SaveHeader), SaveError> {
+    let header_file_path = save_file_path(save_name, &(SAVE_HEADER_FILE_NAME.to_string() + ".val"));
+
+    let bytes = match fs::read(&header_file_path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok((0, SaveHeader::default())),
+        Err(err) => return Err(SaveError::IoError(err)),
+    };
+
+    let (format_version, body) = read_versioned_bytes(&bytes, &header_file_path)?;
+    match serde_bare::from_slice::<SaveHeader>(body) {
+        Ok(header) => Ok((format_version, header)),
+        Err(err) => Err(SaveError::DeserializeError(err)),
+    }
+}
+
+// Checks the magic and the format version, returns the version and the rest of the bytes.
+fn read_versioned_bytes<'a>(bytes: &'a [u8], file_path: &str) -> Result<(u32, &'a [u8]), SaveError> {
+    if bytes.len() < 8 || bytes[0..4] != SAVE_HEADER_MAGIC {
+        return Err(SaveError::InvalidHeader(format!("wrong magic in {}", file_path)));
+    }
+
+    let format_version = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+    if format_version > SAVE_FORMAT_VERSION {
+        return Err(SaveError::NewerFormat {
+            save_version: format_version,
+            supported_version: SAVE_FORMAT_VERSION,
+        });
+    }
+
+    Ok((format_version, &bytes[8..]))
+}
+
+// Reads the manifest and all of the values of the generation, checking their checksums.
+// Only the values that aren't lazy are deserialized.
+fn load_generation(
+    save_name: &str,
+    generation: u64,
+) -> Result<(u32, SaveManifest, HashMap<String, Vec<SystemValue>>), SaveError> {
+    let (format_version, manifest) = read_manifest(save_name, generation)?;
+
+    let mut values = HashMap::new();
+    for (value_name, saved_value) in &manifest.values {
+        let bytes = read_checked_file(save_name, saved_value)?;
+        if !saved_value.is_lazy {
+            match serde_bare::from_slice::<Vec<SystemValue>>(&bytes) {
+                Ok(value) => {
+                    values.insert(value_name.clone(), value);
+                },
+                Err(err) => return Err(SaveError::DeserializeError(err)),
+            }
+        }
+    }
+
+    Ok((format_version, manifest, values))
+}
+
+fn read_manifest(save_name: &str, generation: u64) -> Result<(u32, SaveManifest), SaveError> {
+    let manifest_file_path = save_file_path(save_name, &manifest_file_name(generation));
+    let bytes = fs::read(&manifest_file_path).map_err(SaveError::IoError)?;
+
+    let (format_version, body) = read_versioned_bytes(&bytes, &manifest_file_path)?;
+    if body.len() < 8 {
+        return Err(SaveError::Corrupted(format!("manifest {} is truncated", manifest_file_path)));
+    }
+
+    let checksum = u64::from_le_bytes(body[0..8].try_into().expect("the length is checked above"));
+    let body = &body[8..];
+    if fnv1a(body) != checksum {
+        return Err(SaveError::Corrupted(format!("wrong checksum of manifest {}", manifest_file_path)));
+    }
+
+    match serde_bare::from_slice::<SaveManifest>(body) {
+        Ok(manifest) => Ok((format_version, manifest)),
+        Err(err) => Err(SaveError::DeserializeError(err)),
+    }
+}
+
+fn write_manifest(save_name: &str, manifest: &SaveManifest) -> Result<(), SaveError> {
+    let body = serde_bare::to_vec(manifest).map_err(SaveError::SerializeError)?;
+
+    let mut bytes = SAVE_HEADER_MAGIC.to_vec();
+    bytes.extend_from_slice(&SAVE_FORMAT_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&fnv1a(&body).to_le_bytes());
+    bytes.extend_from_slice(&body);
+
+    let manifest_file_path = save_file_path(save_name, &manifest_file_name(manifest.generation));
+    write_file_atomically(&manifest_file_path, &bytes).map_err(SaveError::IoError)?;
+
+    // the renames are durable only after the directory itself is synced
+    if let Ok(save_dir) = File::open(save_file_path(save_name, "")) {
+        let _ = save_dir.sync_all();
+    }
+
+    Ok(())
+}
+
+fn write_value_file(
+    save_name: &str,
+    value_name: &str,
+    generation: u64,
+    version: u32,
+    value: &[SystemValue],
+    is_lazy: bool,
+) -> Result<SavedValue, SaveError> {
+    let bytes = serde_bare::to_vec(value).map_err(SaveError::SerializeError)?;
+    let file = value_file_name(value_name, generation);
+    write_file_atomically(&save_file_path(save_name, &file), &bytes).map_err(SaveError::IoError)?;
+
+    Ok(SavedValue {
+        version,
+        file,
+        checksum: Some(fnv1a(&bytes)),
+        size: bytes.len() as u64,
+        is_lazy,
+    })
+}
+
+fn read_value_file(save_name: &str, saved_value: &SavedValue) -> Result<Vec<SystemValue>, SaveError> {
+    let bytes = read_checked_file(save_name, saved_value)?;
+    serde_bare::from_slice(&bytes).map_err(SaveError::DeserializeError)
+}
+
+fn read_checked_file(save_name: &str, saved_value: &SavedValue) -> Result<Vec<u8>, SaveError> {
+    let file_path = save_file_path(save_name, &saved_value.file);
+    let bytes = fs::read(&file_path).map_err(SaveError::IoError)?;
+
+    if let Some(checksum) = saved_value.checksum {
+        if bytes.len() as u64 != saved_value.size {
+            return Err(SaveError::Corrupted(format!(
+                "{} is {} bytes long, expected {}",
+                file_path, bytes.len(), saved_value.size
+            )));
+        }
+        if fnv1a(&bytes) != checksum {
+            return Err(SaveError::Corrupted(format!("wrong checksum of {}", file_path)));
+        }
+    }
+
+    Ok(bytes)
+}
+
+// The file is written next to the old one and renamed over it, so it's never half written.
+fn write_file_atomically(file_path: &str, bytes: &[u8]) -> Result<(), io::Error> {
+    let temp_file_path = file_path.to_string() + ".tmp";
+    let mut file = File::create(&temp_file_path)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    fs::rename(&temp_file_path, file_path)
+}
+
+// Keeps the current generation and SAVE_BACKUP_GENERATIONS older ones, removes the rest
+// and every value file that isn't used by the kept manifests.
+fn remove_old_generations(save_name: &str) {
+    let generations = manifest_generations(save_name);
+    let (kept_generations, old_generations) = generations.split_at(generations.len().min(SAVE_BACKUP_GENERATIONS + 1));
+
+    for generation in old_generations {
+        let manifest_file_path = save_file_path(save_name, &manifest_file_name(*generation));
+        if let Err(err) = fs::remove_file(&manifest_file_path) {
+            debugger::warn(
+                &format!("save manager's warning!\nfailed to remove an old manifest\nerr: {}, path: {}", err, manifest_file_path)
+            );
+        }
+    }
+
+    let mut used_files = HashSet::new();
+    for generation in kept_generations {
+        if let Ok((_, manifest)) = read_manifest(save_name, *generation) {
+            used_files.extend(manifest.values.into_values().map(|saved_value| saved_value.file));
+        }
+    }
+
+    for file_name in save_dir_files(save_name) {
+        let is_save_file = file_name.ends_with(".val") || file_name.ends_with(".tmp");
+        if is_save_file && !used_files.contains(&file_name) {
+            let _ = fs::remove_file(save_file_path(save_name, &file_name));
+        }
+    }
+}
+
+// Generations of the save that have a manifest, the newest first.
+fn manifest_generations(save_name: &str) -> Vec<u64> {
+    let mut generations: Vec<u64> = save_dir_files(save_name)
+        .iter()
+        .filter_map(|file_name| {
+            file_name
+                .strip_prefix(MANIFEST_FILE_PREFIX)?
+                .strip_suffix(MANIFEST_FILE_EXTENSION)?
+                .parse()
+                .ok()
+        })
+        .collect();
+    generations.sort_unstable_by(|a, b| b.cmp(a));
+    generations
+}
+
+fn player_value_name(player_id: &str) -> String {
+    PLAYER_VALUE_PREFIX.to_string() + player_id
+}
+
+// The values of a player are stored as a single map, so they can be written like any other value.
+fn player_value(values: &HashMap<String, Vec<SystemValue>>) -> Vec<SystemValue> {
+    let map = values
+        .iter()
+        .map(|(value_name, value)| (value_name.clone(), SystemValue::Vec(value.clone())))
+        .collect();
+    vec![SystemValue::Map(map)]
+}
+
+fn player_values_from_value(value: Vec<SystemValue>) -> Result<HashMap<String, Vec<SystemValue>>, SaveError> {
+    let map = match value.into_iter().next() {
+        Some(SystemValue::Map(map)) => map,
+        _ => return Err(SaveError::Corrupted("values of a player are not a map".into())),
+    };
+
+    map.into_iter()
+        .map(|(value_name, value)| match value {
+            SystemValue::Vec(value) => Ok((value_name, value)),
+            _ => Err(SaveError::Corrupted(format!("player value '{}' is not a list", value_name))),
+        })
+        .collect()
+}
+
+fn read_metadata(save_name: &str) -> Option<SaveMetadata> {
+    let bytes = fs::read(save_file_path(save_name, METADATA_FILE_NAME)).ok()?;
+    serde_bare::from_slice(&bytes).ok()
+}
+
+fn write_metadata(save_name: &str, metadata: &SaveMetadata) -> Result<(), io::Error> {
+    let bytes = match serde_bare::to_vec(metadata) {
+        Ok(bytes) => bytes,
+        Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidData, err.to_string())),
+    };
+    write_file_atomically(&save_file_path(save_name, METADATA_FILE_NAME), &bytes)
+}
+
+// magic | version | metadata length | metadata | checksum of the compressed values | compressed values
+fn write_archive(archive_path: &str, metadata: &SaveMetadata, values: &HashMap<String, ArchivedValue>) -> Result<(), SaveError> {
+    let metadata_bytes = serde_bare::to_vec(metadata).map_err(SaveError::SerializeError)?;
+    let values_bytes = serde_bare::to_vec(values).map_err(SaveError::SerializeError)?;
+
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&values_bytes).map_err(SaveError::IoError)?;
+    let compressed_values = encoder.finish().map_err(SaveError::IoError)?;
+
+    let mut bytes = SAVE_ARCHIVE_MAGIC.to_vec();
+    bytes.extend_from_slice(&SAVE_ARCHIVE_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(metadata_bytes.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&metadata_bytes);
+    bytes.extend_from_slice(&fnv1a(&compressed_values).to_le_bytes());
+    bytes.extend_from_slice(&compressed_values);
+
+    write_file_atomically(archive_path, &bytes).map_err(SaveError::IoError)
+}
+
+// Returns the metadata and the rest of the archive after it.
+fn read_archive_header<'a>(bytes: &'a [u8], archive_path: &str) -> Result<(SaveMetadata, &'a [u8]), SaveError> {
+    if bytes.len() < 12 || bytes[0..4] != SAVE_ARCHIVE_MAGIC {
+        return Err(SaveError::InvalidHeader(format!("{} is not a save archive", archive_path)));
+    }
+
+    let version = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+    if version > SAVE_ARCHIVE_VERSION {
+        return Err(SaveError::NewerFormat {
+            save_version: version,
+            supported_version: SAVE_ARCHIVE_VERSION,
+        });
+    }
+
+    let metadata_length = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize;
+    let metadata_bytes = match bytes.get(12..12 + metadata_length) {
+        Some(metadata_bytes) => metadata_bytes,
+        None => return Err(SaveError::Corrupted(format!("archive {} is truncated", archive_path))),
+    };
+    let metadata = serde_bare::from_slice(metadata_bytes).map_err(SaveError::DeserializeError)?;
+
+    Ok((metadata, &bytes[12 + metadata_length..]))
+}
+
+fn read_archive_metadata(archive_path: &str) -> Result<SaveMetadata, SaveError> {
+    let bytes = fs::read(archive_path).map_err(SaveError::IoError)?;
+    let (metadata, _) = read_archive_header(&bytes, archive_path)?;
+    Ok(metadata)
+}
+
+fn read_archive(archive_path: &str) -> Result<(SaveMetadata, HashMap<String, ArchivedValue>), SaveError> {
+    let bytes = fs::read(archive_path).map_err(SaveError::IoError)?;
+    let (metadata, rest) = read_archive_header(&bytes, archive_path)?;
+
+    if rest.len() < 8 {
+        return Err(SaveError::Corrupted(format!("archive {} is truncated", archive_path)));
+    }
+    let checksum = u64::from_le_bytes(rest[0..8].try_into().expect("the length is checked above"));
+    let compressed_values = &rest[8..];
+    if fnv1a(compressed_values) != checksum {
+        return Err(SaveError::Corrupted(format!("wrong checksum of archive {}", archive_path)));
+    }
+
+    let mut values_bytes = Vec::new();
+    DeflateDecoder::new(compressed_values)
+        .read_to_end(&mut values_bytes)
+        .map_err(SaveError::IoError)?;
+    let values = serde_bare::from_slice(&values_bytes).map_err(SaveError::DeserializeError)?;
+
+    Ok((metadata, values))
+}
+
+/// Human-readable copy of a save that is used by the save inspection CLI.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SaveDump {
+    pub format_version: u32,
+    pub generation: u64,
+    pub values: BTreeMap<String, DumpedValue>,
+    /// Values of the players by player id.
+    pub players: BTreeMap<String, BTreeMap<String, Vec<SystemValue>>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DumpedValue {
+    pub version: u32,
+    pub is_lazy: bool,
+    pub value: Vec<SystemValue>,
+}
+
+#[derive(Debug, Default)]
+pub struct SaveValidationReport {
+    pub checked_values: usize,
+    /// Everything that can't be read, one line per problem.
+    pub problems: Vec<String>,
+}
+
+/// Reads the newest readable generation of the save as it's stored, without migrations.
+pub fn dump_save(save_name: &str) -> Result<SaveDump, SaveError> {
+    validate_save_name(save_name)?;
+
+    let generations = manifest_generations(save_name);
+    if generations.is_empty() {
+        // format 0 and 1 saves have no versions and lazy values are not listed anywhere
+        let values = SavesManager::default().load_old_format_save(save_name)?;
+        let (format_version, _) = read_save_header(save_name)?;
+        return Ok(SaveDump {
+            format_version,
+            generation: 0,
+            values: values
+                .into_iter()
+                .map(|(value_name, value)| (value_name, DumpedValue { version: 0, is_lazy: false, value }))
+                .collect(),
+            players: BTreeMap::new(),
+        });
+    }
+
+    let mut last_err = None;
+    for generation in generations {
+        let result = read_manifest(save_name, generation).and_then(|(format_version, manifest)| {
+            let mut values = BTreeMap::new();
+            let mut players: BTreeMap<String, BTreeMap<String, Vec<SystemValue>>> = BTreeMap::new();
+            for (value_name, saved_value) in manifest.values {
+                let value = read_value_file(save_name, &saved_value)?;
+                if let Some(player_id) = value_name.strip_prefix(PLAYER_VALUE_PREFIX) {
+                    players.insert(player_id.to_string(), player_values_from_value(value)?.into_iter().collect());
+                    continue;
+                }
+                values.insert(value_name, DumpedValue {
+                    version: saved_value.version,
+                    is_lazy: saved_value.is_lazy,
+                    value,
+                });
+            }
+            Ok((format_version, values, players))
+        });
+
+        match result {
+            Ok((format_version, values, players)) => {
+                return Ok(SaveDump {
+                    format_version,
+                    generation,
+                    values,
+                    players,
+                })
+            },
+            Err(err) => last_err = Some(err),
+        }
+    }
+
+    Err(last_err.unwrap_or(SaveError::Corrupted("no generations".into())))
+}
+
+/// Writes the dump as a new generation of the save (the save is created if it doesn't exist).
+pub fn import_save_dump(save_name: &str, dump: SaveDump) -> Result<(), SaveError> {
+    validate_save_name(save_name)?;
+    if dump.format_version > SAVE_FORMAT_VERSION {
+        return Err(SaveError::NewerFormat {
+            save_version: dump.format_version,
+            supported_version: SAVE_FORMAT_VERSION,
+        });
+    }
+
+    fs::create_dir_all(save_file_path(save_name, "")).map_err(SaveError::IoError)?;
+    let generation = manifest_generations(save_name).first().copied().unwrap_or(0) + 1;
+    let mut manifest = SaveManifest {
+        generation,
+        values: HashMap::new(),
+    };
+    for (value_name, dumped_value) in dump.values {
+        let saved_value = write_value_file(
+            save_name,
+            &value_name,
+            generation,
+            dumped_value.version,
+            &dumped_value.value,
+            dumped_value.is_lazy,
+        )?;
+        manifest.values.insert(value_name, saved_value);
+    }
+    for (player_id, values) in dump.players {
+        let value_name = player_value_name(&player_id);
+        let values: HashMap<String, Vec<SystemValue>> = values.into_iter().collect();
+        let value = player_value(&values);
+        let saved_value = write_value_file(save_name, &value_name, generation, 0, &value, true)?;
+        manifest.values.insert(value_name, saved_value);
+    }
+    write_manifest(save_name, &manifest)?;
+
+    if read_metadata(save_name).is_none() {
+        let metadata = SaveMetadata {
+            created_at: unix_time(),
+            last_played_at: unix_time(),
+            engine_version: env!("CARGO_PKG_VERSION").into(),
+            format_version: SAVE_FORMAT_VERSION,
+            ..Default::default()
+        };
+        write_metadata(save_name, &metadata).map_err(SaveError::IoError)?;
+    }
+    remove_old_generations(save_name);
+
+    Ok(())
+}
+
+/// Reads every generation and value of the save and reports the ones that can't be read.
+pub fn validate_save(save_name: &str) -> Result<SaveValidationReport, SaveError> {
+    validate_save_name(save_name)?;
+    if !Path::new(&save_file_path(save_name, "")).exists() {
+        return Err(SaveError::SaveNotFound(save_name.into()));
+    }
+
+    let mut report = SaveValidationReport::default();
+    let generations = manifest_generations(save_name);
+    if generations.is_empty() {
+        match read_save_header(save_name) {
+            Ok(_) => report.problems.push("the save has the old format (0 or 1), only its header was checked".into()),
+            Err(err) => report.problems.push(format!("invalid save header: {}", err)),
+        }
+    }
+
+    for generation in generations {
+        let manifest = match read_manifest(save_name, generation) {
+            Ok((_, manifest)) => manifest,
+            Err(err) => {
+                report.problems.push(format!("generation {}: unreadable manifest: {}", generation, err));
+                continue;
+            },
+        };
+
+        let mut value_names: Vec<&String> = manifest.values.keys().collect();
+        value_names.sort();
+        for value_name in value_names {
+            report.checked_values += 1;
+            let result = read_value_file(save_name, &manifest.values[value_name]).and_then(|value| {
+                match value_name.starts_with(PLAYER_VALUE_PREFIX) {
+                    true => player_values_from_value(value).map(|_| ()),
+                    false => Ok(()),
+                }
+            });
+            if let Err(err) = result {
+                report.problems.push(format!("generation {}: value '{}': {}", generation, value_name, err));
+            }
+        }
+    }
+
+    if Path::new(&save_file_path(save_name, METADATA_FILE_NAME)).exists() && read_metadata(save_name).is_none() {
+        report.problems.push("metadata: unreadable".into());
+    }
+
+    Ok(report)
+}
+
+/// Save names are used as directory and file names, so they can't contain paths.
+pub fn validate_save_name(save_name: &str) -> Result<(), SaveError> {
+    if save_name.is_empty() || save_name.trim() != save_name {
+        return Err(SaveError::InvalidSaveName(format!("'{}' is empty or starts/ends with a whitespace", save_name)));
+    }
+    if save_name.len() > 128 {
+        return Err(SaveError::InvalidSaveName(format!("'{}' is longer than 128 bytes", save_name)));
+    }
+    if save_name.starts_with('.') {
+        return Err(SaveError::InvalidSaveName(format!("'{}' starts with a dot", save_name)));
+    }
+    if let Some(character) = save_name
+        .chars()
+        .find(|character| character.is_control() || matches!(character, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|'))
+    {
+        return Err(SaveError::InvalidSaveName(format!("'{}' contains '{}'", save_name, character.escape_default())));
+    }
+    if save_name.ends_with(SAVE_ARCHIVE_EXTENSION) {
+        return Err(SaveError::InvalidSaveName(format!("'{}' ends with {}", save_name, SAVE_ARCHIVE_EXTENSION)));
+    }
+
+    Ok(())
+}
+
+// value names can have any characters (region names have ':'), the file names of the values
+// keep only the ones that are valid on every platform and percent-encode the rest
+fn escape_file_name(name: &str) -> String {
+    let mut file_name = String::with_capacity(name.len());
+    for byte in name.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' | b'-' | b'.' => file_name.push(byte as char),
+            _ => file_name.push_str(&format!("%{:02x}", byte)),
+        }
+    }
+    file_name
+}
+
+fn value_file_name(value_name: &str, generation: u64) -> String {
+    let mut file_name = escape_file_name(value_name);
+    if file_name.len() > MAX_VALUE_FILE_NAME_LENGTH {
+        // escaped names are ASCII, '~' is never in them
+        file_name.truncate(MAX_VALUE_FILE_NAME_LENGTH);
+        file_name += &format!("~{:016x}", fnv1a(value_name.as_bytes()));
+    }
+    format!("{}.{}.val", file_name, generation)
+}
+
+fn region_value_name(group: &str, key: &str) -> Option<String> {
+    match group.contains(':') || group.is_empty() {
+        true => None,
+        false => Some(format!("{}{}:{}", REGION_VALUE_PREFIX, group, key)),
+    }
+}
+
+fn region_group_value_name(group: &str) -> String {
+    format!("{}{}", REGION_VALUE_PREFIX, group)
+}
+
+fn region_group_of_value(value_name: &str) -> Option<&str> {
+    let (group, _) = value_name.strip_prefix(REGION_VALUE_PREFIX)?.split_once(':')?;
+    Some(group)
+}
+
+fn save_archive_path(save_name: &str) -> String {
+    saves_dir_path() + save_name + SAVE_ARCHIVE_EXTENSION
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+fn manifest_file_name(generation: u64) -> String {
+    format!("{}{}{}", MANIFEST_FILE_PREFIX, generation, MANIFEST_FILE_EXTENSION)
+}
+
+fn save_file_path(save_name: &str, file_name: &str) -> String {
+    saves_dir_path() + save_name + "/" + file_name
+}
+
+#[cfg(not(test))]
+fn saves_dir_path() -> String {
+    get_full_asset_path("saves/")
+}
+
+// tests never touch the saves of the game
+#[cfg(test)]
+fn saves_dir_path() -> String {
+    let path = std::env::temp_dir().join(format!("saves_tests_{}", std::process::id()));
+    path.to_string_lossy().to_string() + "/"
+}
+
+fn save_dir_files(save_name: &str) -> Vec<String> {
+    match fs::read_dir(save_file_path(save_name, "")) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect(),
+        Err(_) => vec![],
+    }
+}
+
+fn load_value_from_file(current_save_name: &str, value_name: &str) -> Option<Vec<SystemValue>> {
+    let save_file_path = save_file_path(current_save_name, &(value_name.to_string() + ".val"));
+
+    let mut bytes = Vec::new();
+    match File::open(&save_file_path) {
+        Ok(mut file) => {
+            if let Err(err) = file.read_to_end(&mut bytes) {
+                debugger::error(
+                    &format!("save manager's load_game error!\nfailed to read the save file\nerr: {}, path: {}", err, save_file_path)
+                );
+                return None
+            }
+        }
+        Err(err) => {
             debugger::error(
                 &format!("save manager's load_game error!\nfailed to open the save file\nerr: {}, path: {}", err, save_file_path)
             );
@@ -258,3 +1872,194 @@ fn load_value_from_file(current_save_name: &str, value_name: &str) -> Option<Vec
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // every test writes into it's own save in the temporary directory, so they can run in parallel
+    struct TestSave(String);
+
+    impl TestSave {
+        fn new(test_name: &str) -> TestSave {
+            let save_name = format!("test_{}", test_name);
+            let _ = fs::remove_dir_all(save_file_path(&save_name, ""));
+            fs::create_dir_all(save_file_path(&save_name, "")).unwrap();
+            TestSave(save_name)
+        }
+
+        fn write_generation(&self, generation: u64, number: i32) {
+            let value = vec![SystemValue::Int(number)];
+            let saved_value = write_value_file(&self.0, "value", generation, 0, &value, false).unwrap();
+            let manifest = SaveManifest {
+                generation,
+                values: HashMap::from([("value".to_string(), saved_value)]),
+            };
+            write_manifest(&self.0, &manifest).unwrap();
+        }
+    }
+
+    impl Drop for TestSave {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(save_file_path(&self.0, ""));
+        }
+    }
+
+    fn loaded_number(values: &HashMap<String, Vec<SystemValue>>) -> Option<i32> {
+        match values.get("value")?.as_slice() {
+            [SystemValue::Int(number)] => Some(*number),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn manifest_checksum_is_checked() {
+        let save = TestSave::new("manifest_checksum");
+        save.write_generation(1, 5);
+        assert!(read_manifest(&save.0, 1).is_ok());
+
+        let manifest_file_path = save_file_path(&save.0, &manifest_file_name(1));
+        let mut bytes = fs::read(&manifest_file_path).unwrap();
+        *bytes.last_mut().unwrap() ^= 0xff;
+        fs::write(&manifest_file_path, bytes).unwrap();
+        assert!(matches!(read_manifest(&save.0, 1), Err(SaveError::Corrupted(_))));
+    }
+
+    #[test]
+    fn corrupted_generation_falls_back_to_older_one() {
+        let save = TestSave::new("generation_fallback");
+        save.write_generation(1, 1);
+        save.write_generation(2, 2);
+        assert_eq!(manifest_generations(&save.0), vec![2, 1]);
+
+        let mut saves_manager = SavesManager::default();
+        let values = saves_manager.load_save(&save.0).unwrap();
+        assert_eq!(loaded_number(&values), Some(2));
+
+        // the value file of the newest generation no longer matches it's checksum
+        fs::write(save_file_path(&save.0, &value_file_name("value", 2)), b"broken").unwrap();
+        let values = saves_manager.load_save(&save.0).unwrap();
+        assert_eq!(loaded_number(&values), Some(1));
+        // the next save must not reuse the number of the corrupted generation
+        assert_eq!(saves_manager.current_generation, 2);
+    }
+
+    #[test]
+    fn long_value_names_fit_in_file_names() {
+        let player_id = "a".repeat(crate::managers::networking::auth::MAX_PLAYER_ID_BYTES);
+        let file_name = value_file_name(&player_value_name(&player_id), u64::MAX);
+        assert!(file_name.len() <= 255, "'{}' is too long", file_name);
+
+        let other_file_name = value_file_name(&player_value_name(&(player_id.clone() + "b")), u64::MAX);
+        assert_ne!(file_name, other_file_name);
+        assert_eq!(value_file_name("objects:world", 3), "objects%3aworld.3.val");
+    }
+
+    #[test]
+    fn player_values_survive_encoding() {
+        let values = HashMap::from([
+            ("coins".to_string(), vec![SystemValue::Int(10)]),
+            ("inventory".to_string(), vec![SystemValue::String("sword".into()), SystemValue::Nil]),
+        ]);
+        let decoded = player_values_from_value(player_value(&values)).unwrap();
+
+        assert_eq!(decoded.len(), 2);
+        assert!(matches!(decoded["coins"].as_slice(), [SystemValue::Int(10)]));
+        assert!(matches!(decoded["inventory"].as_slice(), [SystemValue::String(item), SystemValue::Nil] if item == "sword"));
+        assert!(player_values_from_value(vec![SystemValue::Int(1)]).is_err());
+    }
+
+    #[test]
+    fn save_names_are_validated() {
+        assert!(validate_save_name("slot 1").is_ok());
+        assert!(validate_save_name("Сохранение").is_ok());
+
+        for save_name in ["", " slot", "slot ", ".hidden", "..", "a/b", "a\\b", "a:b", "a\nb", "slot.gsave"] {
+            assert!(validate_save_name(save_name).is_err(), "'{}' must be rejected", save_name);
+        }
+        assert!(validate_save_name(&"a".repeat(129)).is_err());
+    }
+
+    #[test]
+    fn escaped_file_names_stay_in_the_directory() {
+        assert_eq!(escape_file_name("player_stats-2.v"), "player_stats-2.v");
+        assert_eq!(escape_file_name("objects:world"), "objects%3aworld");
+        assert_eq!(escape_file_name("../../etc/passwd"), "..%2f..%2fetc%2fpasswd");
+        assert_eq!(escape_file_name("a\\b"), "a%5cb");
+        assert!(!escape_file_name("ä/").contains('/'));
+    }
+
+    #[test]
+    fn migrations_run_for_every_version() {
+        let mut saves_manager = SavesManager::default();
+        saves_manager.register_save_value_with_version("value", 3);
+        saves_manager.register_save_migration("value", Box::new(|from_version: u32, mut value: Vec<SystemValue>| {
+            value.push(SystemValue::UInt(from_version));
+            Ok(value)
+        }));
+
+        let value = saves_manager.migrate_value("value", 1, vec![]).unwrap();
+        assert!(matches!(value.as_slice(), [SystemValue::UInt(1), SystemValue::UInt(2)]));
+
+        let value = saves_manager.migrate_value("value", 3, vec![]).unwrap();
+        assert!(value.is_empty());
+    }
+
+    #[test]
+    fn migration_errors_are_reported() {
+        let mut saves_manager = SavesManager::default();
+        saves_manager.register_save_value_with_version("value", 2);
+        assert!(matches!(
+            saves_manager.migrate_value("value", 3, vec![]),
+            Err(SaveError::NewerValue { save_version: 3, supported_version: 2, .. })
+        ));
+        assert!(matches!(
+            saves_manager.migrate_value("value", 0, vec![]),
+            Err(SaveError::MigrationNotRegistered { from_version: 0, .. })
+        ));
+
+        saves_manager.register_save_migration("value", Box::new(|from_version: u32, value: Vec<SystemValue>| match from_version {
+            0 => Ok(value),
+            _ => Err("unsupported".to_string()),
+        }));
+        assert!(matches!(
+            saves_manager.migrate_value("value", 0, vec![]),
+            Err(SaveError::MigrationError { from_version: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn values_wait_for_their_migrations() {
+        let mut saves_manager = SavesManager::default();
+        saves_manager.pending_values.insert("value".into(), PendingValue { version: 1, value: vec![] });
+        saves_manager.register_save_value_with_version("value", 2);
+        assert!(saves_manager.take_migrated_value("value").is_none());
+
+        saves_manager.register_save_migration("value", Box::new(|_: u32, mut value: Vec<SystemValue>| {
+            value.push(SystemValue::Bool(true));
+            Ok(value)
+        }));
+        let value = saves_manager.take_migrated_value("value").unwrap();
+        assert!(matches!(value.as_slice(), [SystemValue::Bool(true)]));
+        assert!(saves_manager.pending_values.is_empty());
+    }
+
+    #[test]
+    fn deleting_current_save_forgets_its_values() {
+        let save = TestSave::new("delete_current");
+        save.write_generation(1, 1);
+
+        let mut saves_manager = SavesManager::default();
+        saves_manager.load_save(&save.0).unwrap();
+        saves_manager.load_player("player").unwrap();
+        assert!(saves_manager.set_player_value("player", "health", vec![SystemValue::Int(10)]));
+        saves_manager.save_lazy_value("lazy", vec![SystemValue::Int(1)]);
+
+        saves_manager.delete_save(&save.0).unwrap();
+        assert!(!saves_manager.save_exists(&save.0));
+        assert!(saves_manager.is_player_loaded("player"));
+        assert!(saves_manager.get_player_value("player", "health").is_none());
+        assert!(saves_manager.load_lazy_value("lazy").is_none());
+        assert!(matches!(saves_manager.save_player("player"), Err(SaveError::NoCurrentSave)));
+    }
+}
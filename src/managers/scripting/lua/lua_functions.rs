@@ -1,18 +1,18 @@
 use std::{collections::HashMap, fs::read_to_string};
 
-use super::{ObjectHandle, SYSTEMS_LUA_VMS};
+use super::{lua_vm_ref, sandbox, scheduler, ObjectHandle, EVENT_HANDLERS_REGISTRY_NAME, RPC_CALLBACKS_REGISTRY_NAME, RPC_HANDLERS_REGISTRY_NAME, SAVE_MIGRATIONS_REGISTRY_NAME, SYSTEMS_LUA_VMS};
 use crate::{
     assets::{
         self,
         shader_asset::{ShaderAsset, ShaderAssetPath},
     }, managers::{
-        self, debugger, networking::{self, Message, MessageContents, MessageReceiver, MessageReliability, SyncObjectMessage}, physics::{BodyColliderType, CollisionGroups}, render::RenderLayer, scripting::lua::{get_framework_pointer, LuaSpline}, systems::{self, SystemValue}
+        self, debugger, events, networking::{self, conditioner::ConditionerSettings, interest::ObjectRelevancy, rpc::{self, RpcArgType}, spawn::{self, SpawnColliderShape, SpawnObjectKind}, stats::TrafficCounter, Message, MessageContents, MessageReceiver, MessageReliability, SyncObjectMessage}, physics::{BodyColliderType, CollisionGroups}, render::RenderLayer, scripting::lua::{get_framework_pointer, LuaQuat, LuaSpline, LuaVec2, LuaVec3}, systems::{self, SystemValue}
     }, math_utils::{self, look_at_rotation, PerlinNoise}, objects::{
         Object, Transform
     }, systems::System
 };
-use glam::Vec3;
-use mlua::Lua;
+use glam::{Quat, Vec2, Vec3};
+use mlua::{Lua, Table};
 use splines::Spline;
 
 macro_rules! add_function {
@@ -106,6 +106,93 @@ pub fn add_lua_vm_to_list(system_id: String, lua: Lua) {
         });
         add_function!("new_perlin_noise", new_perlin_noise, lua, &system_id);
 
+        // vectors
+        let new_vec2 = lua.create_function(move |_, (x, y): (f32, f32)| {
+            Ok(LuaVec2(Vec2::new(x, y)))
+        });
+        add_function!("new_vec2", new_vec2, lua, &system_id);
+
+        let new_vec3 = lua.create_function(move |_, (x, y, z): (f32, f32, f32)| {
+            Ok(LuaVec3(Vec3::new(x, y, z)))
+        });
+        add_function!("new_vec3", new_vec3, lua, &system_id);
+
+        let new_quat = lua.create_function(move |_, (x, y, z, w): (f32, f32, f32, f32)| {
+            Ok(LuaQuat(Quat::from_xyzw(x, y, z, w)))
+        });
+        add_function!("new_quat", new_quat, lua, &system_id);
+
+        let new_quat_from_euler = lua.create_function(move |_, (x, y, z): (f32, f32, f32)| {
+            Ok(LuaQuat(Quat::from_euler(glam::EulerRot::XYZ, x.to_radians(), y.to_radians(), z.to_radians())))
+        });
+        add_function!("new_quat_from_euler", new_quat_from_euler, lua, &system_id);
+
+
+        // coroutines and timers
+        if let Err(err) = scheduler::add_wait_functions(lua) {
+            debugger::error(&format!("failed to add the wait functions as lua globals in system {}\nerror: {}", system_id, err));
+        }
+
+        // function(framework, ...) is started in the scheduler's update of this frame, returns the id for cancel_scheduled
+        let system_id_for_functions = system_id.clone();
+        let start_coroutine = lua.create_function(move |lua, (function, args): (mlua::Function, mlua::Variadic<mlua::Value>)| {
+            match scheduler::start_coroutine(lua, &system_id_for_functions, function, args) {
+                Ok(task_id) => Ok(Some(task_id)),
+                Err(err) => {
+                    debugger::error(&format!("lua error(system {}): start_coroutine failed!\nerr: {}", system_id_for_functions, err));
+                    Ok(None)
+                }
+            }
+        });
+        add_function!("start_coroutine", start_coroutine, lua, system_id);
+
+        // callback(framework) is called every `seconds` until it returns false
+        let system_id_for_functions = system_id.clone();
+        let every = lua.create_function(move |lua, (seconds, callback): (f32, mlua::Function)| {
+            match scheduler::start_timer(lua, &system_id_for_functions, seconds, callback) {
+                Ok(task_id) => Ok(Some(task_id)),
+                Err(err) => {
+                    debugger::error(&format!("lua error(system {}): every failed!\nerr: {}", system_id_for_functions, err));
+                    Ok(None)
+                }
+            }
+        });
+        add_function!("every", every, lua, system_id);
+
+        let system_id_for_functions = system_id.clone();
+        let cancel_scheduled = lua.create_function(move |lua, task_id: u32| {
+            scheduler::cancel(lua, &system_id_for_functions, task_id);
+            Ok(())
+        });
+        add_function!("cancel_scheduled", cancel_scheduled, lua, system_id);
+
+        // event bus
+        // handler is function(framework, sender_system_id_or_nil, ...), the engine events have no sender
+        let system_id_for_functions = system_id.clone();
+        let subscribe_event = lua.create_function(move |lua, (event_name, handler): (String, mlua::Function)| {
+            let handlers = named_registry_table(lua, EVENT_HANDLERS_REGISTRY_NAME)?;
+            handlers.set(event_name.as_str(), handler)?;
+            events::subscribe(&system_id_for_functions, &event_name);
+            Ok(())
+        });
+        add_function!("subscribe_event", subscribe_event, lua, system_id);
+
+        let system_id_for_functions = system_id.clone();
+        let unsubscribe_event = lua.create_function(move |lua, event_name: String| {
+            let handlers = named_registry_table(lua, EVENT_HANDLERS_REGISTRY_NAME)?;
+            handlers.set(event_name.as_str(), mlua::Value::Nil)?;
+            events::unsubscribe(&system_id_for_functions, &event_name);
+            Ok(())
+        });
+        add_function!("unsubscribe_event", unsubscribe_event, lua, system_id);
+
+        // the subscribed systems get it at the start of the next update
+        let system_id_for_functions = system_id.clone();
+        let emit_event = lua.create_function(move |_, (event_name, payload): (String, mlua::Variadic<SystemValue>)| {
+            events::emit(Some(&system_id_for_functions), &event_name, payload.into_iter().collect());
+            Ok(())
+        });
+        add_function!("emit_event", emit_event, lua, system_id);
 
         // setting/crearing current parent
         let system_id_for_functions = system_id.clone();
@@ -827,7 +914,7 @@ pub fn add_lua_vm_to_list(system_id: String, lua: Lua) {
         add_function!("get_object_scale", get_object_scale, lua, system_id);
 
         let system_id_for_functions = system_id.clone();
-        let send_custom_message = lua.create_function_mut(move |_, (is_reliable, message_id, contents, receiver, client_id): (bool, String, Vec<SystemValue>, Option<String>, Option<u64>)| {
+        let send_custom_message = lua.create_function_mut(move |_, (is_reliable, message_id, contents, receiver, client_id, near_position, near_radius): (bool, String, Vec<SystemValue>, Option<String>, Option<u64>, Option<[f32; 3]>, Option<f32>)| {
             match managers::systems::get_system_mut_with_id(&system_id_for_functions) {
                 Some(system) => {
                     let reliability = match is_reliable {
@@ -857,8 +944,17 @@ pub fn add_lua_vm_to_list(system_id: String, lua: Lua) {
                                             },
                                         }
                                     },
+                                    "EverybodyNear" => {
+                                        match near_position {
+                                            Some(position) => MessageReceiver::EverybodyNear(position.into(), near_radius.unwrap_or(0.0)),
+                                            None => {
+                                                debugger::error("lua send_custom_message error: message receiver set to 'EverybodyNear', but position is nil");
+                                                MessageReceiver::OneClient(0)
+                                            },
+                                        }
+                                    },
                                     _ => {
-                                        debugger::error("lua send_custom_message error: receiver arg is wrong! Possible values: 'Everybody', 'OneClient', 'EverybodyExcept'");
+                                        debugger::error("lua send_custom_message error: receiver arg is wrong! Possible values: 'Everybody', 'OneClient', 'EverybodyExcept', 'EverybodyNear'");
                                         MessageReceiver::OneClient(0)
                                     },
                                 };
@@ -866,7 +962,7 @@ pub fn add_lua_vm_to_list(system_id: String, lua: Lua) {
                                 let _ = system.send_message(reliability, message);
                             },
                             None => {
-                                debugger::error("lua send_custom_message error: receiver arg is nil! Possible values: 'Everybody', 'OneClient', 'EverybodyExcept'");
+                                debugger::error("lua send_custom_message error: receiver arg is nil! Possible values: 'Everybody', 'OneClient', 'EverybodyExcept', 'EverybodyNear'");
                             },
                         };
 
@@ -884,8 +980,8 @@ pub fn add_lua_vm_to_list(system_id: String, lua: Lua) {
 
         let system_id_for_functions = system_id.clone();
         let send_sync_object_message = lua.create_function_mut(move 
-            |_, (is_reliable, message_id, object_name, pos, rot, scale, receiver, client_id): 
-            (bool, String, String, [f32; 3], [f32; 3], [f32; 3], Option<String>, Option<u64>)| {
+            |_, (is_reliable, message_id, object_name, pos, rot, scale, receiver, client_id, near_position, near_radius): 
+            (bool, String, String, [f32; 3], [f32; 3], [f32; 3], Option<String>, Option<u64>, Option<[f32; 3]>, Option<f32>)| {
                 let contents = MessageContents::SyncObject(SyncObjectMessage {
                     object_name,
                     transform: Transform {
@@ -923,8 +1019,17 @@ pub fn add_lua_vm_to_list(system_id: String, lua: Lua) {
                                                 },
                                             }
                                         },
+                                        "EverybodyNear" => {
+                                            match near_position {
+                                                Some(position) => MessageReceiver::EverybodyNear(position.into(), near_radius.unwrap_or(0.0)),
+                                                None => {
+                                                    debugger::error("lua send_sync_object_message error: message receiver set to 'EverybodyNear', but position is nil");
+                                                    MessageReceiver::OneClient(0)
+                                                },
+                                            }
+                                        },
                                         _ => {
-                                            debugger::error("lua send_sync_object_message error: receiver arg is wrong! Possible values: 'Everybody', 'OneClient', 'EverybodyExcept'");
+                                            debugger::error("lua send_sync_object_message error: receiver arg is wrong! Possible values: 'Everybody', 'OneClient', 'EverybodyExcept', 'EverybodyNear'");
                                             MessageReceiver::OneClient(0)
                                         },
                                     };
@@ -932,7 +1037,7 @@ pub fn add_lua_vm_to_list(system_id: String, lua: Lua) {
                                     let _ = system.send_message(reliability, message);
                                 },
                                 None => {
-                                    debugger::error("lua send_sync_object_message error: receiver arg is nil! Possible values: 'Everybody', 'OneClient', 'EverybodyExcept'");
+                                    debugger::error("lua send_sync_object_message error: receiver arg is nil! Possible values: 'Everybody', 'OneClient', 'EverybodyExcept', 'EverybodyNear'");
                                 },
                             };
 
@@ -948,6 +1053,120 @@ pub fn add_lua_vm_to_list(system_id: String, lua: Lua) {
             });
         add_function!("send_sync_object_message", send_sync_object_message, lua, system_id);
 
+        let system_id_for_functions = system_id.clone();
+        let replicate_object = lua.create_function_mut(move |_, name: String| {
+            networking::replication::replicate_object(&system_id_for_functions, &name);
+            Ok(())
+        });
+        add_function!("replicate_object", replicate_object, lua, system_id);
+
+        let system_id_for_functions = system_id.clone();
+        let stop_replicating_object = lua.create_function_mut(move |_, name: String| {
+            networking::replication::stop_replicating_object(&system_id_for_functions, &name);
+            Ok(())
+        });
+        add_function!("stop_replicating_object", stop_replicating_object, lua, system_id);
+
+        let system_id_for_functions = system_id.clone();
+        let is_object_replicated = lua.create_function_mut(move |_, name: String| {
+            Ok(networking::replication::is_object_replicated(&system_id_for_functions, &name))
+        });
+        add_function!("is_object_replicated", is_object_replicated, lua, system_id);
+
+        let set_client_interest = lua.create_function_mut(|_, (client_id, position, radius): (u64, [f32; 3], f32)| {
+            networking::interest::set_client_interest(client_id, position.into(), radius);
+            Ok(())
+        });
+        add_function!("set_client_interest", set_client_interest, lua, system_id);
+
+        // the area of interest follows the object of this system
+        let system_id_for_functions = system_id.clone();
+        let attach_client_interest = lua.create_function_mut(move |_, (client_id, object_name, radius): (u64, String, f32)| {
+            networking::interest::attach_client_interest(client_id, &system_id_for_functions, &object_name, radius);
+            Ok(())
+        });
+        add_function!("attach_client_interest", attach_client_interest, lua, system_id);
+
+        let remove_client_interest = lua.create_function_mut(|_, client_id: u64| {
+            networking::interest::remove_client_interest(client_id);
+            Ok(())
+        });
+        add_function!("remove_client_interest", remove_client_interest, lua, system_id);
+
+        let system_id_for_functions = system_id.clone();
+        let set_object_relevancy_radius = lua.create_function_mut(move |_, (name, radius): (String, f32)| {
+            networking::interest::set_object_relevancy(&system_id_for_functions, &name, ObjectRelevancy::Radius(radius.max(0.0)));
+            Ok(())
+        });
+        add_function!("set_object_relevancy_radius", set_object_relevancy_radius, lua, system_id);
+
+        let system_id_for_functions = system_id.clone();
+        let set_object_always_relevant = lua.create_function_mut(move |_, name: String| {
+            networking::interest::set_object_relevancy(&system_id_for_functions, &name, ObjectRelevancy::Always);
+            Ok(())
+        });
+        add_function!("set_object_always_relevant", set_object_always_relevant, lua, system_id);
+
+        // kind: "Empty", "Model", "SoundEmitter", "Trigger", "CharacterController", "Ray", "NavObstacle", "DynamicNavObject"
+        // params is a table with the arguments of the kind (the same as in new_* functions) and optional position, rotation and scale
+        // returns the network id or nil if the object wasn't spawned
+        let system_id_for_functions = system_id.clone();
+        let spawn_object = lua.create_function_mut(move |lua, (name, kind, params): (String, String, Option<Table>)| {
+            let params = match params {
+                Some(params) => params,
+                None => lua.create_table()?,
+            };
+            let kind = match spawn_kind_from_lua(&kind, &params) {
+                Ok(kind) => kind,
+                Err(err) => {
+                    debugger::error(&format!(
+                        "lua error(system {}): spawn_object '{}' failed!\n{}",
+                        system_id_for_functions, name, err
+                    ));
+                    return Ok(None);
+                }
+            };
+            let transform = Transform {
+                position: params.get::<_, Option<[f32; 3]>>("position")?.unwrap_or([0.0; 3]).into(),
+                rotation: params.get::<_, Option<[f32; 3]>>("rotation")?.unwrap_or([0.0; 3]).into(),
+                scale: params.get::<_, Option<[f32; 3]>>("scale")?.unwrap_or([1.0; 3]).into(),
+            };
+            let parent: Option<String> = lua.globals().get("current_parent")?;
+
+            let framework_ptr = get_framework_pointer();
+            let framework = &mut *framework_ptr;
+            match spawn::spawn_object(framework, &system_id_for_functions, &name, parent.as_deref(), kind, transform) {
+                Ok(network_id) => Ok(Some(network_id)),
+                Err(err) => {
+                    debugger::error(&format!(
+                        "lua error(system {}): spawn_object '{}' failed!\nerr: {}",
+                        system_id_for_functions, name, err
+                    ));
+                    Ok(None)
+                }
+            }
+        });
+        add_function!("spawn_object", spawn_object, lua, system_id);
+
+        let system_id_for_functions = system_id.clone();
+        let get_network_id = lua.create_function_mut(move |_, name: String| {
+            Ok(spawn::get_network_id(&system_id_for_functions, &name))
+        });
+        add_function!("get_network_id", get_network_id, lua, system_id);
+
+        // returns the name of the object of this system with the network id, or nil
+        let system_id_for_functions = system_id.clone();
+        let find_object_by_network_id = lua.create_function_mut(move |_, network_id: u64| {
+            match spawn::find_object_by_network_id(network_id) {
+                Some((object_system_id, object_name)) if object_system_id == system_id_for_functions => Ok(Some(ObjectHandle {
+                    system_id: object_system_id,
+                    name: object_name,
+                })),
+                _ => Ok(None),
+            }
+        });
+        add_function!("find_object_by_network_id", find_object_by_network_id, lua, system_id);
+
 
         let get_network_events = lua.create_function_mut(
             move |_, _: ()| {
@@ -975,6 +1194,24 @@ pub fn add_lua_vm_to_list(system_id: String, lua: Lua) {
                         networking::NetworkEvent::Disconnected(_) => {
                             let mut ev = HashMap::new();
                             ev.insert("type", "Disconnected".into());
+                            if let Some(reason) = networking::get_disconnect_reason() {
+                                ev.insert("reason", reason);
+                            }
+                            events.push(ev);
+                        },
+                        networking::NetworkEvent::ClientRejected(id, reason) => {
+                            let mut ev = HashMap::new();
+                            ev.insert("type", "ClientRejected".into());
+                            ev.insert("id", id.to_string());
+                            ev.insert("reason", reason.name().into());
+                            ev.insert("description", reason.to_string());
+                            events.push(ev);
+                        },
+                        networking::NetworkEvent::Rejected(reason) => {
+                            let mut ev = HashMap::new();
+                            ev.insert("type", "Rejected".into());
+                            ev.insert("reason", reason.name().into());
+                            ev.insert("description", reason.to_string());
                             events.push(ev);
                         },
                     }
@@ -983,9 +1220,269 @@ pub fn add_lua_vm_to_list(system_id: String, lua: Lua) {
             }
         );
         add_function!("get_network_events", get_network_events, lua, system_id);
+
+        let get_disconnect_reason = lua.create_function_mut(|_, _: ()| {
+            Ok(networking::get_disconnect_reason())
+        });
+        add_function!("get_disconnect_reason", get_disconnect_reason, lua, system_id);
+
+        // arg_types are "String"/"Int"/"UInt"/"Float"/"Bool"/"Spline"/"Vec"/"Map"/"Int64"/"Vec2"/"Vec3"/"Quat"/"Bytes"/"Nil"/"Any"
+        // handler is function(framework, caller_client_id_or_nil, ...) and can return values that are sent back to the caller
+        let system_id_for_functions = system_id.clone();
+        let register_rpc = lua.create_function_mut(move |lua, (name, arg_types, handler): (String, Vec<String>, mlua::Function)| {
+            let mut rpc_arg_types = Vec::new();
+            for arg_type in arg_types {
+                match RpcArgType::from_name(&arg_type) {
+                    Some(arg_type) => rpc_arg_types.push(arg_type),
+                    None => {
+                        debugger::error(&format!(
+                            "lua error(system {}): register_rpc failed! unknown argument type '{}' in rpc '{}'\nPossible values: 'String', 'Int', 'UInt', 'Float', 'Bool', 'Spline', 'Vec', 'Map', 'Int64', 'Vec2', 'Vec3', 'Quat', 'Bytes', 'Nil', 'Any'",
+                            system_id_for_functions, arg_type, name
+                        ));
+                        return Ok(());
+                    }
+                }
+            }
+
+            let handlers = named_registry_table(lua, RPC_HANDLERS_REGISTRY_NAME)?;
+            handlers.set(name.as_str(), handler)?;
+            rpc::register_rpc(&system_id_for_functions, &name, rpc_arg_types);
+            Ok(())
+        });
+        add_function!("register_rpc", register_rpc, lua, system_id);
+
+        // target: nil (the server for clients, everybody for the server), "Server", "Everybody", client id,
+        // {"EverybodyExcept", client id} or {"EverybodyNear", {x, y, z}, radius}
+        // callback is function(framework, ok, results_table_or_error)
+        let system_id_for_functions = system_id.clone();
+        let call_rpc = lua.create_function_mut(move |lua, (target_system_id, name, args, target, callback): (String, String, Option<Vec<SystemValue>>, mlua::Value, Option<mlua::Function>)| {
+            let receiver = match target {
+                mlua::Value::Nil => MessageReceiver::Everybody,
+                mlua::Value::String(target) => match target.to_str()? {
+                    "Everybody" | "Server" => MessageReceiver::Everybody,
+                    target => {
+                        debugger::error(&format!(
+                            "lua error(system {}): rpc '{}' failed! unknown target '{}'",
+                            system_id_for_functions, name, target
+                        ));
+                        return Ok(None);
+                    }
+                },
+                mlua::Value::Integer(client_id) => MessageReceiver::OneClient(client_id as u64),
+                mlua::Value::Table(target) => match target.get::<_, String>(1).unwrap_or_default().as_str() {
+                    "EverybodyExcept" if target.get::<_, u64>(2).is_ok() => MessageReceiver::EverybodyExcept(target.get(2)?),
+                    "EverybodyNear" if target.get::<_, [f32; 3]>(2).is_ok() => MessageReceiver::EverybodyNear(
+                        target.get::<_, [f32; 3]>(2)?.into(),
+                        target.get::<_, Option<f32>>(3)?.unwrap_or(0.0),
+                    ),
+                    _ => {
+                        debugger::error(&format!(
+                            "lua error(system {}): rpc '{}' failed! target table should be {{\"EverybodyExcept\", client_id}} or {{\"EverybodyNear\", {{x, y, z}}, radius}}",
+                            system_id_for_functions, name
+                        ));
+                        return Ok(None);
+                    }
+                },
+                _ => {
+                    debugger::error(&format!(
+                        "lua error(system {}): rpc '{}' failed! wrong target type: {}",
+                        system_id_for_functions, name, target.type_name()
+                    ));
+                    return Ok(None);
+                }
+            };
+
+            let caller_system_id = match callback {
+                Some(_) => Some(system_id_for_functions.as_str()),
+                None => None,
+            };
+            match rpc::call(caller_system_id, &target_system_id, &name, args.unwrap_or_default(), receiver) {
+                Ok(Some(call_id)) => {
+                    if let Some(callback) = callback {
+                        let callbacks = named_registry_table(lua, RPC_CALLBACKS_REGISTRY_NAME)?;
+                        callbacks.set(call_id, callback)?;
+                    }
+                    Ok(Some(call_id))
+                }
+                Ok(None) => Ok(None),
+                Err(_) => Ok(None),
+            }
+        });
+        add_function!("rpc", call_rpc, lua, system_id);
+
+        // migration is function(from_version, value) and returns the value upgraded to from_version + 1,
+        // the current version of the value is set with framework:register_save_value(name, version)
+        let system_id_for_functions = system_id.clone();
+        let register_save_migration = lua.create_function_mut(move |lua, (value_name, migration): (String, mlua::Function)| {
+            let migrations = named_registry_table(lua, SAVE_MIGRATIONS_REGISTRY_NAME)?;
+            migrations.set(value_name.as_str(), migration)?;
+
+            let migration_system_id = system_id_for_functions.clone();
+            let migration_value_name = value_name.clone();
+            let framework = get_framework_pointer();
+            framework.register_save_migration(&value_name, Box::new(move |from_version, value| {
+                let lua = match lua_vm_ref(migration_system_id.clone()) {
+                    Some(lua) => lua,
+                    None => return Err(format!("lua vm of system '{}' not found", migration_system_id)),
+                };
+                let migration: Option<mlua::Function> = match lua.named_registry_value::<Option<Table>>(SAVE_MIGRATIONS_REGISTRY_NAME) {
+                    Ok(Some(migrations)) => migrations.get(migration_value_name.as_str()).unwrap_or(None),
+                    _ => None,
+                };
+                match migration {
+                    Some(migration) => sandbox::call_callback::<_, Vec<SystemValue>>(lua, &migration, (from_version, value))
+                        .map_err(|err| err.to_string()),
+                    None => Err(format!("system '{}' has no migration for this value", migration_system_id)),
+                }
+            }));
+            Ok(())
+        });
+        add_function!("register_save_migration", register_save_migration, lua, system_id);
+
+        // persistent objects are saved with their children by save_game and recreated by load_save
+        let system_id_for_functions = system_id.clone();
+        let set_object_persistent = lua.create_function_mut(move |_, (name, is_persistent): (String, Option<bool>)| {
+            let framework = get_framework_pointer();
+            Ok(framework.set_object_persistent(&system_id_for_functions, &name, is_persistent.unwrap_or(true)).is_ok())
+        });
+        add_function!("set_object_persistent", set_object_persistent, lua, system_id);
+
+        // returns {connections = {{client_id, rtt_ms, packet_loss, ...}}, channels = {name = counters}, systems = {id = counters}}
+        let get_network_stats = lua.create_function(|lua, _: ()| {
+            let connections = lua.create_table()?;
+            for (client_id, stats) in networking::stats::get_connections_stats() {
+                let connection = lua.create_table()?;
+                connection.set("client_id", client_id)?;
+                connection.set("rtt_ms", stats.rtt_ms)?;
+                connection.set("packet_loss", stats.packet_loss)?;
+                connection.set("bytes_sent_per_second", stats.bytes_sent_per_second)?;
+                connection.set("bytes_received_per_second", stats.bytes_received_per_second)?;
+                connections.push(connection)?;
+            }
+
+            let channels = lua.create_table()?;
+            for (channel, counter) in networking::stats::get_channels_traffic() {
+                channels.set(channel, traffic_counter_table(lua, &counter)?)?;
+            }
+
+            let systems = lua.create_table()?;
+            for (system_id, counter) in networking::stats::get_systems_traffic() {
+                systems.set(system_id, traffic_counter_table(lua, &counter)?)?;
+            }
+
+            let stats = lua.create_table()?;
+            stats.set("connections", connections)?;
+            stats.set("channels", channels)?;
+            stats.set("systems", systems)?;
+            Ok(stats)
+        });
+        add_function!("get_network_stats", get_network_stats, lua, system_id);
+
+        let reset_network_stats = lua.create_function(|_, _: ()| {
+            networking::stats::reset_traffic_counters();
+            Ok(())
+        });
+        add_function!("reset_network_stats", reset_network_stats, lua, system_id);
+
+        let set_network_conditioner = lua.create_function(|_, (latency_ms, jitter_ms, loss, duplication): (f32, Option<f32>, Option<f32>, Option<f32>)| {
+            networking::conditioner::set_conditioner(Some(ConditionerSettings {
+                latency_ms,
+                jitter_ms: jitter_ms.unwrap_or(0.0),
+                loss: loss.unwrap_or(0.0),
+                duplication: duplication.unwrap_or(0.0),
+            }));
+            Ok(())
+        });
+        add_function!("set_network_conditioner", set_network_conditioner, lua, system_id);
+
+        let disable_network_conditioner = lua.create_function(|_, _: ()| {
+            networking::conditioner::set_conditioner(None);
+            Ok(())
+        });
+        add_function!("disable_network_conditioner", disable_network_conditioner, lua, system_id);
     }
 }
 
+fn traffic_counter_table<'lua>(lua: &'lua Lua, counter: &TrafficCounter) -> mlua::Result<Table<'lua>> {
+    let table = lua.create_table()?;
+    table.set("bytes_sent", counter.bytes_sent)?;
+    table.set("bytes_received", counter.bytes_received)?;
+    table.set("messages_sent", counter.messages_sent)?;
+    table.set("messages_received", counter.messages_received)?;
+    Ok(table)
+}
+
+// table that is stored in the lua registry and is created on the first use
+pub(super) fn named_registry_table<'lua>(lua: &'lua Lua, name: &str) -> mlua::Result<Table<'lua>> {
+    match lua.named_registry_value::<Option<Table>>(name)? {
+        Some(table) => Ok(table),
+        None => {
+            let table = lua.create_table()?;
+            lua.set_named_registry_value(name, table.clone())?;
+            Ok(table)
+        }
+    }
+}
+
+fn spawn_kind_from_lua(kind: &str, params: &Table) -> Result<SpawnObjectKind, String> {
+    let lua_err = |err: mlua::Error| format!("wrong params: {}", err);
+    let collider_shape = |params: &Table| -> Result<SpawnColliderShape, String> {
+        let shape: String = params.get("shape").map_err(lua_err)?;
+        let [size_x, size_y, size_z]: [f32; 3] = params.get("size").map_err(lua_err)?;
+        match shape.as_str() {
+            "Cuboid" => Ok(SpawnColliderShape::Cuboid(size_x, size_y, size_z)),
+            "Capsule" => Ok(SpawnColliderShape::Capsule(size_x, size_y)),
+            "Cylinder" => Ok(SpawnColliderShape::Cylinder(size_x, size_y)),
+            "Ball" => Ok(SpawnColliderShape::Ball(size_x)),
+            _ => Err(format!("unknown shape '{}'! Possible values: 'Cuboid', 'Capsule', 'Cylinder', 'Ball'", shape)),
+        }
+    };
+
+    let kind = match kind {
+        "Empty" => SpawnObjectKind::Empty,
+        "Model" => SpawnObjectKind::Model {
+            model_asset_id: params.get("model_asset").map_err(lua_err)?,
+            texture_asset_id: params.get("texture_asset").map_err(lua_err)?,
+            shader_asset_id: params.get("shader_asset").map_err(lua_err)?,
+            is_transparent: params.get::<_, Option<bool>>("is_transparent").map_err(lua_err)?.unwrap_or(false),
+            layer: params.get("layer").map_err(lua_err)?,
+        },
+        "SoundEmitter" => SpawnObjectKind::SoundEmitter {
+            sound_asset_id: params.get("sound_asset").map_err(lua_err)?,
+            should_loop: params.get::<_, Option<bool>>("should_loop").map_err(lua_err)?.unwrap_or(false),
+            is_positional: params.get::<_, Option<bool>>("is_positional").map_err(lua_err)?.unwrap_or(false),
+            max_distance: params.get::<_, Option<f32>>("max_distance").map_err(lua_err)?.unwrap_or(0.0),
+        },
+        "Trigger" => SpawnObjectKind::Trigger {
+            shape: collider_shape(params)?,
+            membership_groups: params.get("membership").map_err(lua_err)?,
+            mask: params.get("mask").map_err(lua_err)?,
+        },
+        "CharacterController" => SpawnObjectKind::CharacterController {
+            shape: collider_shape(params)?,
+            membership_groups: params.get("membership").map_err(lua_err)?,
+            mask: params.get("mask").map_err(lua_err)?,
+        },
+        "Ray" => SpawnObjectKind::Ray {
+            direction: params.get::<_, [f32; 3]>("direction").map_err(lua_err)?.into(),
+            mask: params.get("mask").map_err(lua_err)?,
+        },
+        "NavObstacle" => SpawnObjectKind::NavObstacle {
+            size_x: params.get("size_x").map_err(lua_err)?,
+            size_z: params.get("size_z").map_err(lua_err)?,
+        },
+        "DynamicNavObject" => SpawnObjectKind::DynamicNavObject {
+            radius: params.get("radius").map_err(lua_err)?,
+        },
+        _ => return Err(format!(
+            "unknown kind '{}'! Possible values: 'Empty', 'Model', 'SoundEmitter', 'Trigger', 'CharacterController', 'Ray', 'NavObstacle', 'DynamicNavObject'",
+            kind
+        )),
+    };
+
+    Ok(kind)
+}
+
 fn add_to_system_or_parent(lua: &Lua, system: &mut Box<dyn System>, object: Box<dyn Object>) {
     if let Ok(current_parent) = lua.globals().get::<&str, Option<String>>("current_parent") {
         if let Some(current_parent) = current_parent {
@@ -1,23 +1,39 @@
 pub mod lua_functions;
+mod modules;
+pub mod sandbox;
+mod scheduler;
 use crate::{
     framework::{self, DebugMode, Framework}, managers::{
-        assets, debugger, networking::{Message, MessageContents}, physics::{BodyColliderType, BodyType, CollisionGroups, RenderColliderType}, render::RenderUniformValue, scripting::lua::lua_functions::add_lua_vm_to_list, systems::{self, CallList, SystemValue}
-    }, math_utils::{self, PerlinNoise}, objects::{character_controller::CharacterController, master_instanced_model_object::MasterInstancedModelObject, model_object::ModelObject, particle_system::ParticleSystem, ray::Ray, sound_emitter::SoundEmitter, trigger::Trigger}, systems::System
+        assets, debugger, events::{self, Event}, networking::{self, interpolation::{self, InterpolationSettings}, rpc::RpcContext, Message, MessageContents}, physics::{BodyColliderType, BodyType, CollisionGroups, RenderColliderType}, render::RenderUniformValue, scripting::lua::lua_functions::add_lua_vm_to_list, systems::{self, CallList, SystemValue}
+    }, math_utils::{self, PerlinNoise}, objects::{character_controller::{CharacterController, PredictionMode}, master_instanced_model_object::MasterInstancedModelObject, model_object::ModelObject, particle_system::ParticleSystem, ray::Ray, sound_emitter::SoundEmitter, trigger::Trigger}, systems::System
 };
 use crate::objects::Object;
-use glam::{Vec2, Vec3};
-use mlua::{Error, FromLua, FromLuaMulti, Function, IntoLua, Lua, LuaOptions, StdLib, UserData};
+use glam::{Quat, Vec2, Vec3};
+use mlua::{Error, FromLua, FromLuaMulti, Function, IntoLua, Lua, LuaOptions, StdLib, Table, UserData, Variadic};
 use once_cell::sync::Lazy;
 use splines::Spline;
-use std::{collections::HashMap, fs};
+use std::{collections::{BTreeMap, HashMap, HashSet, VecDeque}, fs, time::{Duration, Instant, SystemTime}};
 
+pub(crate) const RPC_HANDLERS_REGISTRY_NAME: &str = "rpc_handlers";
+pub(crate) const RPC_CALLBACKS_REGISTRY_NAME: &str = "rpc_callbacks";
+pub(crate) const SAVE_MIGRATIONS_REGISTRY_NAME: &str = "save_migrations";
+pub(crate) const EVENT_HANDLERS_REGISTRY_NAME: &str = "event_handlers";
 static mut SYSTEMS_LUA_VMS: Lazy<HashMap<String, Lua>> = Lazy::new(|| HashMap::new()); // String is system's id and Lua is it's vm
+static mut HOT_RELOAD_ENABLED: bool = false;
+static mut RELOAD_REQUESTS: Lazy<HashSet<String>> = Lazy::new(|| HashSet::new());
+const HOT_RELOAD_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+// the state that is passed to on_reload doesn't go deeper than this (tables can reference themselves)
+const RELOAD_STATE_MAX_DEPTH: usize = 16;
 
 #[derive(Debug)]
 pub struct LuaSystem {
     pub is_destroyed: bool,
     pub id: String,
     pub objects: Vec<Box<dyn Object>>,
+    script_path: String,
+    // the script and the modules it requires with their modification times when they were loaded, used by the hot reload
+    watched_files: Vec<(String, Option<SystemTime>)>,
+    last_reload_check: Instant,
 }
 
 impl LuaSystem {
@@ -35,7 +51,16 @@ impl LuaSystem {
                     }
                 };
 
-                let load_result = lua.load(script).exec();
+                networking::handshake::register_system_content(id, script.as_bytes());
+                if let Err(err) = sandbox::add_sandbox(&lua, id) {
+                    debugger::error(&format!("lua system creation error!\nfailed to add the sandbox\nerror: {}", err));
+                    return Err(LuaSystemError::ScriptLoadingError);
+                }
+                if let Err(err) = modules::add_require(&lua, id) {
+                    debugger::error(&format!("lua system creation error!\nfailed to add require\nerror: {}", err));
+                    return Err(LuaSystemError::ScriptLoadingError);
+                }
+                let load_result = lua.load(script).set_name(format!("@{}", script_path)).exec();
 
                 match load_result {
                     Ok(_) => {
@@ -43,6 +68,9 @@ impl LuaSystem {
                             is_destroyed: false,
                             id: id.into(),
                             objects: Vec::new(),
+                            script_path: script_path.into(),
+                            watched_files: watched_files(script_path, &lua),
+                            last_reload_check: Instant::now(),
                         };
 
                         add_lua_vm_to_list(id.into(), lua);
@@ -66,6 +94,269 @@ impl LuaSystem {
     }
 }
 
+impl LuaSystem {
+    /// Runs the script again in a new VM. The objects of the system are kept and the script's
+    /// `on_reload(framework, old_state)` gets the plain data (not functions or objects) from the globals of the old VM.
+    /// If anything fails, the old VM keeps running.
+    /// start() isn't called again, so the RPCs, save migrations and event subscriptions that were registered in it
+    /// are dropped with the old VM. The top level of the script or on_reload has to register them again.
+    pub fn reload(&mut self, framework: &mut Framework) -> Result<(), LuaSystemError> {
+        let script = match fs::read_to_string(assets::get_full_asset_path(&self.script_path)) {
+            Ok(script) => script,
+            Err(err) => {
+                debugger::error(&format!("lua system reload error!\nsystem: {}\nerror: {}", self.id, err));
+                return Err(LuaSystemError::ScriptLoadingError);
+            }
+        };
+
+        let lua: Lua = match Lua::new_with(StdLib::ALL_SAFE, LuaOptions::default()) {
+            Ok(lua) => lua,
+            Err(err) => {
+                debugger::error(&format!("lua system reload error!\nsystem: {}\nlua creation error\nerror: {}", self.id, err));
+                return Err(LuaSystemError::ScriptLoadingError);
+            }
+        };
+
+        if let Err(err) = sandbox::add_sandbox(&lua, &self.id) {
+            debugger::error(&format!("lua system reload error!\nsystem: {}\nfailed to add the sandbox\nerror: {}", self.id, err));
+            return Err(LuaSystemError::ScriptLoadingError);
+        }
+
+        if let Err(err) = modules::add_require(&lua, &self.id) {
+            debugger::error(&format!("lua system reload error!\nsystem: {}\nfailed to add require\nerror: {}", self.id, err));
+            return Err(LuaSystemError::ScriptLoadingError);
+        }
+
+        // the modules that the new script requires are added to the new content, the old one is put back if the reload fails
+        let old_content = networking::handshake::take_system_content(&self.id);
+        networking::handshake::register_system_content(&self.id, script.as_bytes());
+        if let Err(err) = lua.load(script.as_str()).set_name(format!("@{}", self.script_path)).exec() {
+            debugger::error(&format!(
+                "lua system reload error!\nsystem: {}, the old script keeps running\nlua execution error\nerror: {}",
+                self.id, err
+            ));
+            networking::handshake::restore_system_content(&self.id, old_content);
+            return Err(LuaSystemError::ScriptLoadingError);
+        }
+
+        let old_lua = match unsafe { SYSTEMS_LUA_VMS.remove(&self.id) } {
+            Some(old_lua) => old_lua,
+            None => {
+                debugger::error(&format!("lua system reload error!\nsystem: {}\ncan't get lua vm reference", self.id));
+                networking::handshake::restore_system_content(&self.id, old_content);
+                return Err(LuaSystemError::ScriptLoadingError);
+            }
+        };
+        let old_state = lua_globals_state(&old_lua);
+        let new_watched_files = watched_files(&self.script_path, &lua);
+        // the coroutines belong to the old VM, they are put back only if the reload fails
+        let old_scheduler = scheduler::take_scheduler(&self.id);
+        let old_subscriptions = events::take_subscriptions(&self.id);
+        add_lua_vm_to_list(self.id.clone(), lua);
+
+        let on_reload_result = match lua_vm_ref(self.id.clone()) {
+            Some(lua) => match lua.globals().get::<_, Option<Function>>("on_reload") {
+                // on_reload is optional
+                Ok(Some(on_reload)) => lua.scope(|scope| {
+                    sandbox::start_callback(lua);
+                    let framework_userdata = scope.create_userdata_ref_mut(framework)?;
+                    on_reload.call::<_, ()>((framework_userdata, old_state))
+                }),
+                _ => Ok(()),
+            },
+            None => Ok(()),
+        };
+
+        if let Err(err) = on_reload_result {
+            debugger::error(&format!(
+                "lua system reload error!\nsystem: {}, the old script keeps running\non_reload error\nerror: {}",
+                self.id, err
+            ));
+            unsafe { SYSTEMS_LUA_VMS.insert(self.id.clone(), old_lua) };
+            scheduler::restore_scheduler(&self.id, old_scheduler);
+            events::restore_subscriptions(&self.id, old_subscriptions);
+            networking::handshake::restore_system_content(&self.id, old_content);
+            return Err(LuaSystemError::ScriptLoadingError);
+        }
+
+        // the handlers of the old VM are gone, so everything that the new one didn't register again is unregistered
+        if let Some(lua) = lua_vm_ref(self.id.clone()) {
+            let new_rpcs = registry_keys(lua, RPC_HANDLERS_REGISTRY_NAME);
+            for name in registry_keys(&old_lua, RPC_HANDLERS_REGISTRY_NAME).difference(&new_rpcs) {
+                networking::rpc::unregister_rpc(&self.id, name);
+            }
+
+            let new_migrations = registry_keys(lua, SAVE_MIGRATIONS_REGISTRY_NAME);
+            for value_name in registry_keys(&old_lua, SAVE_MIGRATIONS_REGISTRY_NAME).difference(&new_migrations) {
+                framework.unregister_save_migration(value_name);
+            }
+        }
+
+        self.watched_files = new_watched_files;
+        println!("lua system '{}' is reloaded from {}", self.id, self.script_path);
+        Ok(())
+    }
+
+    // reloads the script if it was requested or the file was changed (when the hot reload is enabled)
+    fn reload_if_needed(&mut self, framework: &mut Framework) {
+        let mut should_reload = unsafe { RELOAD_REQUESTS.remove(&self.id) };
+
+        if unsafe { HOT_RELOAD_ENABLED } && self.last_reload_check.elapsed() >= HOT_RELOAD_CHECK_INTERVAL {
+            self.last_reload_check = Instant::now();
+            for (file_path, modified) in &mut self.watched_files {
+                let current_modified = file_modified_time(file_path);
+                if current_modified.is_some() && current_modified != *modified {
+                    // a broken script isn't loaded again until it's changed
+                    *modified = current_modified;
+                    should_reload = true;
+                }
+            }
+        }
+
+        if should_reload {
+            let _ = self.reload(framework);
+        }
+    }
+}
+
+/// Makes the Lua systems reload their scripts when the files change.
+pub fn set_hot_reload_enabled(is_enabled: bool) {
+    unsafe { HOT_RELOAD_ENABLED = is_enabled }
+}
+
+/// The Lua system reloads its script on the next update.
+pub fn request_reload(system_id: &str) {
+    unsafe { RELOAD_REQUESTS.insert(system_id.into()) };
+}
+
+pub fn is_lua_system(system_id: &str) -> bool {
+    unsafe { SYSTEMS_LUA_VMS.contains_key(system_id) }
+}
+
+// keys of a table in the registry of the VM, e.g. the names of the registered RPC handlers
+fn registry_keys(lua: &Lua, registry_name: &str) -> HashSet<String> {
+    match lua.named_registry_value::<Option<Table>>(registry_name) {
+        Ok(Some(table)) => table
+            .pairs::<String, mlua::Value>()
+            .filter_map(|pair| pair.ok().map(|(key, _)| key))
+            .collect(),
+        _ => HashSet::new(),
+    }
+}
+
+fn watched_files(script_path: &str, lua: &Lua) -> Vec<(String, Option<SystemTime>)> {
+    let mut file_paths = vec![script_path.to_string()];
+    file_paths.extend(modules::loaded_module_paths(lua));
+    file_paths
+        .into_iter()
+        .map(|file_path| {
+            let modified = file_modified_time(&file_path);
+            (file_path, modified)
+        })
+        .collect()
+}
+
+fn file_modified_time(file_path: &str) -> Option<SystemTime> {
+    fs::metadata(assets::get_full_asset_path(file_path))
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+// plain data globals of the VM, the standard library, functions and objects are skipped
+fn lua_globals_state(lua: &Lua) -> SystemValue {
+    let mut state = BTreeMap::new();
+    for pair in lua.globals().pairs::<String, mlua::Value>() {
+        if let Ok((name, value)) = pair {
+            if name == "_G" || name == "package" {
+                continue;
+            }
+            if let Some(value) = lua_value_state(value, 0) {
+                state.insert(name, value);
+            }
+        }
+    }
+    SystemValue::Map(state)
+}
+
+fn lua_value_state(value: mlua::Value, depth: usize) -> Option<SystemValue> {
+    match value {
+        mlua::Value::Boolean(value) => Some(SystemValue::Bool(value)),
+        mlua::Value::Integer(value) => Some(match i32::try_from(value) {
+            Ok(value) => SystemValue::Int(value),
+            Err(_) => SystemValue::Int64(value),
+        }),
+        mlua::Value::Number(value) => Some(SystemValue::Float(value as f32)),
+        mlua::Value::String(value) => Some(match value.to_str() {
+            Ok(string) => SystemValue::String(string.into()),
+            Err(_) => SystemValue::Bytes(value.as_bytes().to_vec()),
+        }),
+        // borrowing, not taking, the old VM has to keep its values if the reload fails
+        mlua::Value::UserData(value) => {
+            if let Ok(vec) = value.borrow::<LuaVec2>() {
+                return Some(SystemValue::Vec2(vec.0));
+            }
+            if let Ok(vec) = value.borrow::<LuaVec3>() {
+                return Some(SystemValue::Vec3(vec.0));
+            }
+            if let Ok(quat) = value.borrow::<LuaQuat>() {
+                return Some(SystemValue::Quat(quat.0));
+            }
+            if let Ok(spline) = value.borrow::<LuaSpline>() {
+                return Some(SystemValue::Spline(spline.0.clone()));
+            }
+            None
+        },
+        mlua::Value::Table(table) => {
+            if depth >= RELOAD_STATE_MAX_DEPTH {
+                return None;
+            }
+
+            let mut entries = Vec::new();
+            let mut is_empty = true;
+            for pair in table.pairs::<mlua::Value, mlua::Value>() {
+                let (key, value) = match pair {
+                    Ok(pair) => pair,
+                    Err(_) => continue,
+                };
+                is_empty = false;
+                if let Some(value) = lua_value_state(value, depth + 1) {
+                    entries.push((key, value));
+                }
+            }
+            // tables of functions (like the standard library) have nothing to keep
+            if entries.is_empty() && !is_empty {
+                return None;
+            }
+
+            // arrays stay arrays, everything else becomes a map with string keys
+            let mut indices: Vec<i64> = entries.iter().filter_map(|(key, _)| key.as_i64()).collect();
+            indices.sort_unstable();
+            let is_array = indices.len() == entries.len() && indices.iter().enumerate().all(|(idx, key)| *key == idx as i64 + 1);
+            if is_array && !entries.is_empty() {
+                entries.sort_by_key(|(key, _)| key.as_i64());
+                return Some(SystemValue::Vec(entries.into_iter().map(|(_, value)| value).collect()));
+            }
+
+            let mut map = BTreeMap::new();
+            for (key, value) in entries {
+                let key = match key {
+                    mlua::Value::String(key) => match key.to_str() {
+                        Ok(key) => key.to_string(),
+                        Err(_) => continue,
+                    },
+                    mlua::Value::Integer(key) => key.to_string(),
+                    mlua::Value::Number(key) => key.to_string(),
+                    mlua::Value::Boolean(key) => key.to_string(),
+                    _ => continue,
+                };
+                map.insert(key, value);
+            }
+            Some(SystemValue::Map(map))
+        },
+        _ => None,
+    }
+}
+
 impl System for LuaSystem {
     fn client_start(&mut self, framework: &mut Framework) {
         let lua_option = lua_vm_ref(self.system_id().into());
@@ -88,22 +379,24 @@ impl System for LuaSystem {
     }
 
     fn client_update(&mut self, framework: &mut Framework) {
+        self.reload_if_needed(framework);
         let lua_option = lua_vm_ref(self.system_id().into());
         match lua_option {
             Some(lua) => {
                 let _ = call_lua_function(self.system_id(), &lua, "client_update", Some(framework));
-                let _ = lua.gc_collect();
-                let _ = lua.gc_collect();
+                sandbox::collect_garbage(lua, self.system_id());
             }
             None => debugger::error("lua system client_update function error\ncan't get lua vm reference"),
         }
     }
 
     fn server_update(&mut self, framework: &mut Framework) {
+        self.reload_if_needed(framework);
         let lua_option = lua_vm_ref(self.system_id().into());
         match lua_option {
             Some(lua) => {
                 let _ = call_lua_function(self.system_id(), &lua, "server_update", Some(framework));
+                sandbox::collect_garbage(lua, self.system_id());
             }
             None => debugger::error("lua system server_update function error\ncan't get lua vm reference"),
         }
@@ -186,6 +479,7 @@ impl System for LuaSystem {
         let lua_option = lua_vm_ref(self.system_id().into());
         match lua_option {
             Some(lua) => {
+                sandbox::start_callback(lua);
                 let scope = lua.scope(|scope| {
                     let framework_ptr: *mut Framework = unsafe { framework::FRAMEWORK_POINTER } as *mut Framework;
                     let framework = unsafe { &mut *framework_ptr };
@@ -225,8 +519,10 @@ impl System for LuaSystem {
 
                 match func {
                     Ok(func) => {
-                        let call_result: Result<SystemValue, mlua::Error> = Function::call(&func, value_name);
+                        let call_result: Result<SystemValue, mlua::Error> = sandbox::call_callback(lua, &func, value_name);
                         return match call_result {
+                            // nil means that the system doesn't have this value
+                            Ok(SystemValue::Nil) => None,
                             Ok(value) => Some(value),
                             Err(err) => {
                                 debugger::error(
@@ -244,6 +540,159 @@ impl System for LuaSystem {
         }
         None
     }
+
+    fn before_save(&mut self, framework: &mut Framework) {
+        let lua = match lua_vm_ref(self.system_id().into()) {
+            Some(lua) => lua,
+            None => {
+                debugger::error("lua system before_save function error\ncan't get lua vm reference");
+                return;
+            }
+        };
+
+        // before_save is optional
+        if let Ok(Some(_)) = lua.globals().get::<_, Option<Function>>("before_save") {
+            call_lua_function(self.system_id(), lua, "before_save", Some(framework));
+        }
+    }
+
+    fn update_coroutines(&mut self, framework: &mut Framework) {
+        match lua_vm_ref(self.system_id().into()) {
+            Some(lua) => scheduler::update(self.system_id(), lua, framework),
+            None => debugger::error("lua system update_coroutines function error\ncan't get lua vm reference"),
+        }
+    }
+
+    fn destroy_system(&mut self) {
+        self.set_destroyed(true);
+        scheduler::cancel_all(&self.id);
+        events::unsubscribe_all(&self.id);
+    }
+
+    fn handle_event(&mut self, framework: &mut Framework, event: &Event) {
+        let lua = match lua_vm_ref(self.system_id().into()) {
+            Some(lua) => lua,
+            None => {
+                debugger::error("lua system handle_event function error\ncan't get lua vm reference");
+                return;
+            }
+        };
+
+        let handler: Option<Function> = match lua.named_registry_value::<Option<Table>>(EVENT_HANDLERS_REGISTRY_NAME) {
+            Ok(Some(handlers)) => handlers.get(event.name.as_str()).unwrap_or(None),
+            _ => None,
+        };
+        let handler = match handler {
+            Some(handler) => handler,
+            None => return,
+        };
+
+        sandbox::start_callback(lua);
+        let scope_result = lua.scope(|scope| {
+            let framework_userdata = scope.create_userdata_ref_mut(framework)?;
+            handler.call::<_, ()>((framework_userdata, event.sender.clone(), Variadic::from_iter(event.payload.clone())))
+        });
+
+        if let Err(err) = scope_result {
+            debugger::error(&format!("lua error when handling event '{}' in system {}\nerror: {}", event.name, self.system_id(), err));
+        }
+    }
+
+    fn region_loaded(&mut self, framework: &mut Framework, group: &str, key: &str) {
+        let lua = match lua_vm_ref(self.system_id().into()) {
+            Some(lua) => lua,
+            None => {
+                debugger::error("lua system region_loaded function error\ncan't get lua vm reference");
+                return;
+            }
+        };
+
+        // on_region_loaded is optional
+        let callback = match lua.globals().get::<_, Option<Function>>("on_region_loaded") {
+            Ok(Some(callback)) => callback,
+            _ => return,
+        };
+
+        sandbox::start_callback(lua);
+        let scope_result = lua.scope(|scope| {
+            let framework_userdata = scope.create_userdata_ref_mut(framework)?;
+            callback.call::<_, ()>((framework_userdata, group, key))
+        });
+
+        if let Err(err) = scope_result {
+            debugger::error(&format!("lua error when calling 'on_region_loaded' in system {}\nerror: {}", self.system_id(), err));
+        }
+    }
+
+    fn handle_rpc(&mut self, framework: &mut Framework, context: RpcContext, args: Vec<SystemValue>) -> Result<Vec<SystemValue>, String> {
+        let lua = match lua_vm_ref(self.system_id().into()) {
+            Some(lua) => lua,
+            None => {
+                debugger::error("lua system handle_rpc function error\ncan't get lua vm reference");
+                return Err("can't get lua vm reference".into());
+            }
+        };
+
+        let handler: Option<Function> = match lua.named_registry_value::<Option<Table>>(RPC_HANDLERS_REGISTRY_NAME) {
+            Ok(Some(handlers)) => handlers.get(context.name.as_str()).unwrap_or(None),
+            _ => None,
+        };
+        let handler = match handler {
+            Some(handler) => handler,
+            None => return Err(format!("system '{}' doesn't have a handler for rpc '{}'", self.system_id(), context.name)),
+        };
+
+        sandbox::start_callback(lua);
+        let scope_result = lua.scope(|scope| {
+            let framework_userdata = scope.create_userdata_ref_mut(framework)?;
+            let results: Variadic<SystemValue> = handler.call((framework_userdata, context.caller, Variadic::from_iter(args)))?;
+            Ok(results.into_iter().collect())
+        });
+
+        match scope_result {
+            Ok(results) => Ok(results),
+            Err(err) => {
+                debugger::error(&format!("lua error when calling rpc handler '{}' in system {}\nerror: {}", context.name, self.system_id(), err));
+                Err(err.to_string())
+            }
+        }
+    }
+
+    fn rpc_response(&mut self, framework: &mut Framework, call_id: u32, result: Result<Vec<SystemValue>, String>) {
+        let lua = match lua_vm_ref(self.system_id().into()) {
+            Some(lua) => lua,
+            None => {
+                debugger::error("lua system rpc_response function error\ncan't get lua vm reference");
+                return;
+            }
+        };
+
+        let callback: Option<Function> = match lua.named_registry_value::<Option<Table>>(RPC_CALLBACKS_REGISTRY_NAME) {
+            Ok(Some(callbacks)) => {
+                let callback = callbacks.get(call_id).unwrap_or(None);
+                let _ = callbacks.set(call_id, mlua::Value::Nil);
+                callback
+            }
+            _ => None,
+        };
+        let callback = match callback {
+            Some(callback) => callback,
+            None => return,
+        };
+
+        sandbox::start_callback(lua);
+        let scope_result = lua.scope(|scope| {
+            let framework_userdata = scope.create_userdata_ref_mut(framework)?;
+            match result {
+                Ok(results) => callback.call::<_, ()>((framework_userdata, true, results)),
+                Err(err) => callback.call::<_, ()>((framework_userdata, false, err)),
+            }
+        });
+
+        if let Err(err) = scope_result {
+            debugger::error(&format!("lua error when calling rpc callback {} in system {}\nerror: {}", call_id, self.system_id(), err));
+        }
+    }
 }
 
 fn lua_vm_ref<'a>(system_id: String) -> Option<&'a Lua> {
@@ -255,6 +704,7 @@ fn lua_vm_ref_mut<'a>(system_id: String) -> Option<&'a mut Lua> {
 }
 
 fn call_lua_function(system_id: &str, lua: &Lua, function_name: &str, framework: Option<&mut Framework>) {
+    sandbox::start_callback(lua);
     let _ = lua.scope(|scope| {
         let framework_userdata = match framework {
             Some(framework) => Some(scope.create_userdata_ref_mut(framework)),
@@ -1589,6 +2039,117 @@ impl UserData for ObjectHandle {
 
             Ok(HashMap::new())
         });
+
+        // delay, max_extrapolation and snap_distance are optional, defaults are used if they're nil
+        methods.add_method("set_interpolated", |_, this, (interpolated, delay, max_extrapolation, snap_distance): (bool, Option<f32>, Option<f32>, Option<f32>)| {
+            match systems::get_system_mut_with_id(&this.system_id) {
+                Some(system) => match system.find_object_mut(&this.name) {
+                    Some(object) => {
+                        let settings = match interpolated {
+                            true => {
+                                let mut settings = InterpolationSettings::default();
+                                if let Some(delay) = delay {
+                                    settings.delay = delay;
+                                }
+                                if let Some(max_extrapolation) = max_extrapolation {
+                                    settings.max_extrapolation = max_extrapolation;
+                                }
+                                if let Some(snap_distance) = snap_distance {
+                                    settings.snap_distance = snap_distance;
+                                }
+                                Some(settings)
+                            },
+                            false => None,
+                        };
+                        interpolation::set_interpolation(*object.object_id(), settings);
+                    }
+                    None => {
+                        debugger::error(
+                            &format!("lua error: set_interpolated failed! failed to get object {} in system {}", this.name, this.system_id));
+                    },
+                },
+                None => debugger::error(&format!(
+                        "lua error: set_interpolated failed! failed to get system {} to find object {}",
+                        this.system_id, this.name
+                )),
+            }
+
+            Ok(())
+        });
+
+        methods.add_method("is_interpolated", |_, this, _: ()| {
+            match systems::get_system_mut_with_id(&this.system_id) {
+                Some(system) => match system.find_object_mut(&this.name) {
+                    Some(object) => return Ok(interpolation::is_interpolated(*object.object_id())),
+                    None => {
+                        debugger::error(
+                            &format!("lua error: is_interpolated failed! failed to get object {} in system {}", this.name, this.system_id));
+                    },
+                },
+                None => debugger::error(&format!(
+                        "lua error: is_interpolated failed! failed to get system {} to find object {}",
+                        this.system_id, this.name
+                )),
+            }
+
+            Ok(false)
+        });
+
+        // mode = "None"/"Predicted"/"Authoritative", owner is the client id and is needed only for "Authoritative"
+        // max_speed is the max distance per second that the owner's inputs can move an "Authoritative" controller
+        methods.add_method("set_prediction_mode", |_, this, (mode, owner, max_speed): (String, Option<u64>, Option<f32>)| {
+            let prediction_mode = match mode.as_str() {
+                "None" => PredictionMode::None,
+                "Predicted" => PredictionMode::Predicted {
+                    next_sequence: 0,
+                    pending_inputs: VecDeque::new(),
+                },
+                "Authoritative" => match owner {
+                    Some(owner) => PredictionMode::Authoritative {
+                        owner,
+                        max_speed: max_speed.unwrap_or(networking::prediction::DEFAULT_MAX_INPUT_SPEED).max(0.0),
+                        last_sequence: None,
+                    },
+                    None => {
+                        debugger::error(&format!(
+                            "lua error(system {}): set_prediction_mode failed in object: {}. \"Authoritative\" mode requires the owner's client id",
+                            this.system_id, this.name));
+                        return Ok(());
+                    }
+                },
+                _ => {
+                    debugger::error(&format!(
+                        "lua error(system {}): set_prediction_mode failed in object: {}. unknown mode {}",
+                        this.system_id, this.name, mode));
+                    return Ok(());
+                }
+            };
+
+            match systems::get_system_mut_with_id(&this.system_id) {
+                Some(system) => match system.find_object_mut(&this.name) {
+                    Some(object) => {
+                        match object.downcast_mut::<CharacterController>() {
+                            Some(object) => object.set_prediction_mode(prediction_mode),
+                            None => {
+                                debugger::error(
+                                    &format!("lua error(system {}): set_prediction_mode failed in object: {}. this object is not CharacterController!",
+                                    this.system_id, this.name));
+                            },
+                        }
+                    }
+                    None => {
+                        debugger::error(
+                            &format!("lua error: set_prediction_mode failed! failed to get object {} in system {}", this.name, this.system_id));
+                    },
+                },
+                None => debugger::error(&format!(
+                        "lua error: set_prediction_mode failed! failed to get system {} to find object {}",
+                        this.system_id, this.name
+                )),
+            }
+
+            Ok(())
+        });
         // i could've used a macro
     }
 }
@@ -1650,6 +2211,14 @@ impl UserData for Message {
             match this.contents {
                 MessageContents::SyncObject(_) => Ok("SyncObject"),
                 MessageContents::Custom(_) => Ok("Custom"),
+                MessageContents::Snapshot(_) => Ok("Snapshot"),
+                MessageContents::SnapshotAck(_) => Ok("SnapshotAck"),
+                MessageContents::ControllerInput(_) => Ok("ControllerInput"),
+                MessageContents::ControllerState(_) => Ok("ControllerState"),
+                MessageContents::RpcCall(_) => Ok("RpcCall"),
+                MessageContents::RpcResponse(_) => Ok("RpcResponse"),
+                MessageContents::SpawnObject(_) => Ok("SpawnObject"),
+                MessageContents::DespawnObject(_) => Ok("DespawnObject"),
             }
         });
 
@@ -1658,7 +2227,7 @@ impl UserData for Message {
                 MessageContents::SyncObject(message) => {
                     Ok(Some(message.object_name.to_string()))
                 },
-                MessageContents::Custom(_) => {
+                _ => {
                     debugger::error(&"lua error: sync_object_name in Message failed! the contents_type != 'SyncObject'");
                     Ok(None)
                 },
@@ -1680,7 +2249,7 @@ impl UserData for Message {
                     let pos_rot_scale = [position, rotation, scale];
                     Ok(Some(pos_rot_scale))
                 },
-                MessageContents::Custom(_) => {
+                _ => {
                     debugger::error(&"lua error: sync_object_pos_rot_scale in Message failed! the contents_type != 'SyncObject'");
                     Ok(None)
                 },
@@ -1689,13 +2258,13 @@ impl UserData for Message {
 
         methods.add_method("custom_contents", |_, this, _: ()| {
             match &this.contents {
-                MessageContents::SyncObject(_) => {
-                    debugger::error(&"lua error: custom_contents in Message failed! the contents_type != 'Custom'");
-                    Ok(None)
-                },
                 MessageContents::Custom(contents) => {
                     Ok(Some(contents.to_owned()))
                 },
+                _ => {
+                    debugger::error(&"lua error: custom_contents in Message failed! the contents_type != 'Custom'");
+                    Ok(None)
+                },
             }
         });
 
@@ -1726,11 +2295,18 @@ impl UserData for Message {
 
 impl<'lua> FromLua<'lua> for SystemValue {
     fn from_lua(value: mlua::prelude::LuaValue<'lua>, _: &'lua Lua) -> mlua::prelude::LuaResult<Self> {
+        if value.is_nil() {
+            return Ok(SystemValue::Nil);
+        }
         if let Some(value) = value.as_f32() {
             return Ok(SystemValue::Float(value));
         } 
         if let Some(value) = value.as_string() {
-            return Ok(SystemValue::String(String::from(value.to_str().unwrap())));
+            // lua strings are byte strings, the ones that aren't utf-8 are binary data
+            return match value.to_str() {
+                Ok(string) => Ok(SystemValue::String(String::from(string))),
+                Err(_) => Ok(SystemValue::Bytes(value.as_bytes().to_vec())),
+            };
         } 
         if let Some(value) = value.as_u32() {
             return Ok(SystemValue::UInt(value));
@@ -1738,10 +2314,22 @@ impl<'lua> FromLua<'lua> for SystemValue {
         if let Some(value) = value.as_i32() {
             return Ok(SystemValue::Int(value));
         }
+        if let Some(value) = value.as_i64() {
+            return Ok(SystemValue::Int64(value));
+        }
         if let Some(value) = value.as_boolean() {
             return Ok(SystemValue::Bool(value));
         }
         if let Some(value) = value.as_userdata() {
+            if let Ok(vec) = value.borrow::<LuaVec2>() {
+                return Ok(SystemValue::Vec2(vec.0));
+            }
+            if let Ok(vec) = value.borrow::<LuaVec3>() {
+                return Ok(SystemValue::Vec3(vec.0));
+            }
+            if let Ok(quat) = value.borrow::<LuaQuat>() {
+                return Ok(SystemValue::Quat(quat.0));
+            }
             match value.take::<LuaSpline>() {
                 Ok(value) => return Ok(SystemValue::Spline(value.0)),
                 Err(err) => {
@@ -1754,15 +2342,27 @@ impl<'lua> FromLua<'lua> for SystemValue {
             }
         }
         if let Some(value) = value.as_table() {
-            let mut vec: Vec<SystemValue> = Vec::new();
-            if let Err(err) = value.for_each(|_: SystemValue, value: SystemValue| {
-                vec.push(value);
+            // tables with only string keys are maps, the values of all other tables are put into a Vec
+            let mut pairs: Vec<(mlua::Value, SystemValue)> = Vec::new();
+            if let Err(err) = value.for_each(|key: mlua::Value, value: SystemValue| {
+                pairs.push((key, value));
                 Ok(())
             }) {
                 debugger::error(&format!("Failed to convert a Table to a Vec. Err: {}", err))
             };
 
-            return Ok(SystemValue::Vec(vec))
+            let is_map = !pairs.is_empty() && pairs.iter().all(|(key, _)| key.is_string());
+            if is_map {
+                let mut map = BTreeMap::new();
+                for (key, value) in pairs {
+                    if let mlua::Value::String(key) = key {
+                        map.insert(key.to_str()?.to_string(), value);
+                    }
+                }
+                return Ok(SystemValue::Map(map))
+            }
+
+            return Ok(SystemValue::Vec(pairs.into_iter().map(|(_, value)| value).collect()))
         }
         Err(Error::FromLuaConversionError { from: value.type_name(), to: "SystemValue::Vec", message: None })
     }
@@ -1850,6 +2450,13 @@ impl<'lua> IntoLua<'lua> for SystemValue {
             SystemValue::Bool(value) => Ok(value.into_lua(lua)?),
             SystemValue::Vec(value) => Ok(value.into_lua(lua)?),
             SystemValue::Spline(value) => Ok(LuaSpline(value).into_lua(lua)?),
+            SystemValue::Map(value) => Ok(value.into_lua(lua)?),
+            SystemValue::Int64(value) => Ok(value.into_lua(lua)?),
+            SystemValue::Vec2(value) => Ok(LuaVec2(value).into_lua(lua)?),
+            SystemValue::Vec3(value) => Ok(LuaVec3(value).into_lua(lua)?),
+            SystemValue::Quat(value) => Ok(LuaQuat(value).into_lua(lua)?),
+            SystemValue::Bytes(value) => Ok(mlua::Value::String(lua.create_string(&value)?)),
+            SystemValue::Nil => Ok(mlua::Value::Nil),
         }
     }
 }
@@ -1921,8 +2528,11 @@ impl UserData for Framework {
         );
 
         methods.add_method_mut("register_save_value",
-            |_, framework, system_value_name: String| {
-                framework.register_save_value(&system_value_name);
+            |_, framework, (system_value_name, version): (String, Option<u32>)| {
+                match version {
+                    Some(version) => framework.register_save_value_with_version(&system_value_name, version),
+                    None => framework.register_save_value(&system_value_name),
+                }
                 Ok(())
             }
         );
@@ -1943,7 +2553,7 @@ impl UserData for Framework {
 
         methods.add_method_mut("save_game",
             |_, framework, _: ()| {
-                framework.save_game();
+                let _ = framework.save_game();
                 Ok(())
             }
         );
@@ -1960,6 +2570,175 @@ impl UserData for Framework {
             }
         );
 
+        methods.add_method_mut("request_region",
+            |_, framework, (group, key): (String, String)| {
+                Ok(framework.request_region(&group, &key))
+            }
+        );
+
+        methods.add_method("is_region_ready",
+            |_, framework, (group, key): (String, String)| {
+                Ok(framework.is_region_ready(&group, &key))
+            }
+        );
+
+        methods.add_method("get_region",
+            |_, framework, (group, key): (String, String)| {
+                Ok(framework.get_region(&group, &key))
+            }
+        );
+
+        methods.add_method("get_region_error",
+            |_, framework, (group, key): (String, String)| {
+                Ok(framework.get_region_error(&group, &key))
+            }
+        );
+
+        methods.add_method_mut("set_region",
+            |_, framework, (group, key, value): (String, String, Vec<SystemValue>)| {
+                Ok(framework.set_region(&group, &key, value))
+            }
+        );
+
+        methods.add_method_mut("unload_region",
+            |_, framework, (group, key): (String, String)| {
+                Ok(framework.unload_region(&group, &key))
+            }
+        );
+
+        methods.add_method("requested_regions",
+            |_, framework, group: String| {
+                Ok(framework.requested_regions(&group))
+            }
+        );
+
+        methods.add_method("get_player_id",
+            |_, framework, client_id: String| {
+                Ok(client_id.parse().ok().and_then(|client_id| framework.get_player_id(client_id)))
+            }
+        );
+
+        // the value is written to the save when the player disconnects and on save_game
+        methods.add_method_mut("save_player_value",
+            |_, framework, (client_id, value_name, value): (String, String, Vec<SystemValue>)| {
+                match client_id.parse() {
+                    Ok(client_id) => Ok(framework.set_player_value(client_id, &value_name, value).is_ok()),
+                    Err(_) => {
+                        debugger::error(&format!("lua error: save_player_value failed!\ninvalid client id '{}'", client_id));
+                        Ok(false)
+                    },
+                }
+            }
+        );
+
+        methods.add_method("load_player_value",
+            |_, framework, (client_id, value_name): (String, String)| {
+                Ok(client_id.parse().ok().and_then(|client_id| framework.get_player_value(client_id, &value_name)))
+            }
+        );
+
+        methods.add_method("list_saves",
+            |lua, framework, _: ()| {
+                let saves = lua.create_table()?;
+                for (idx, save) in framework.list_saves().into_iter().enumerate() {
+                    let save_table = lua.create_table()?;
+                    save_table.set("name", save.name)?;
+                    save_table.set("is_archive", save.is_archive)?;
+                    save_table.set("created_at", save.metadata.created_at)?;
+                    save_table.set("last_played_at", save.metadata.last_played_at)?;
+                    save_table.set("playtime_seconds", save.metadata.playtime_seconds)?;
+                    save_table.set("engine_version", save.metadata.engine_version)?;
+                    save_table.set("has_thumbnail", save.metadata.thumbnail.is_some())?;
+                    saves.set(idx + 1, save_table)?;
+                }
+                Ok(saves)
+            }
+        );
+
+        methods.add_method("save_exists",
+            |_, framework, save_name: String| {
+                Ok(framework.save_exists(&save_name))
+            }
+        );
+
+        methods.add_method_mut("rename_save",
+            |_, framework, (save_name, new_save_name): (String, String)| {
+                match framework.rename_save(&save_name, &new_save_name) {
+                    Ok(_) => Ok(true),
+                    Err(err) => {
+                        debugger::error(&format!("lua error: rename_save failed!\nerr: {}", err));
+                        Ok(false)
+                    },
+                }
+            }
+        );
+
+        methods.add_method_mut("copy_save",
+            |_, framework, (save_name, new_save_name): (String, String)| {
+                match framework.copy_save(&save_name, &new_save_name) {
+                    Ok(_) => Ok(true),
+                    Err(err) => {
+                        debugger::error(&format!("lua error: copy_save failed!\nerr: {}", err));
+                        Ok(false)
+                    },
+                }
+            }
+        );
+
+        methods.add_method_mut("delete_save",
+            |_, framework, save_name: String| {
+                match framework.delete_save(&save_name) {
+                    Ok(_) => Ok(true),
+                    Err(err) => {
+                        debugger::error(&format!("lua error: delete_save failed!\nerr: {}", err));
+                        Ok(false)
+                    },
+                }
+            }
+        );
+
+        methods.add_method("export_save",
+            |_, framework, (save_name, archive_path): (String, Option<String>)| {
+                let archive_path = archive_path.map(|path| assets::get_full_asset_path(&path));
+                match framework.export_save_archive(&save_name, archive_path.as_deref()) {
+                    Ok(_) => Ok(true),
+                    Err(err) => {
+                        debugger::error(&format!("lua error: export_save failed!\nerr: {}", err));
+                        Ok(false)
+                    },
+                }
+            }
+        );
+
+        methods.add_method_mut("import_save",
+            |_, framework, (archive_path, save_name): (String, String)| {
+                match framework.import_save_archive(&assets::get_full_asset_path(&archive_path), &save_name) {
+                    Ok(_) => Ok(true),
+                    Err(err) => {
+                        debugger::error(&format!("lua error: import_save failed!\nerr: {}", err));
+                        Ok(false)
+                    },
+                }
+            }
+        );
+
+        methods.add_method_mut("set_save_thumbnail",
+            |_, framework, thumbnail_path: Option<String>| {
+                let thumbnail = match thumbnail_path {
+                    Some(thumbnail_path) => match fs::read(assets::get_full_asset_path(&thumbnail_path)) {
+                        Ok(thumbnail) => Some(thumbnail),
+                        Err(err) => {
+                            debugger::error(&format!("lua error: set_save_thumbnail failed to read {}!\nerr: {}", thumbnail_path, err));
+                            return Ok(());
+                        },
+                    },
+                    None => None,
+                };
+                framework.set_save_thumbnail(thumbnail);
+                Ok(())
+            }
+        );
+
         methods.add_method_mut("new_bind_keyboard",
             |_, framework, (name, keys): (String, Vec<String>)| {
                 let keys: Vec<&str> = keys.iter().map(|key| key.as_str()).collect();
@@ -2500,6 +3279,68 @@ impl UserData for LuaSpline {
     }
 }
 
+#[derive(Clone, Copy)]
+pub(crate) struct LuaVec2(pub Vec2);
+
+impl UserData for LuaVec2 {
+    fn add_fields<'lua, F: mlua::UserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_field_method_get("x", |_, vec| Ok(vec.0.x));
+        fields.add_field_method_get("y", |_, vec| Ok(vec.0.y));
+        fields.add_field_method_set("x", |_, vec, x: f32| Ok(vec.0.x = x));
+        fields.add_field_method_set("y", |_, vec, y: f32| Ok(vec.0.y = y));
+    }
+
+    fn add_methods<'lua, M: mlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_meta_method(mlua::MetaMethod::ToString, |_, vec, _: ()| {
+            Ok(format!("vec2({}, {})", vec.0.x, vec.0.y))
+        });
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct LuaVec3(pub Vec3);
+
+impl UserData for LuaVec3 {
+    fn add_fields<'lua, F: mlua::UserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_field_method_get("x", |_, vec| Ok(vec.0.x));
+        fields.add_field_method_get("y", |_, vec| Ok(vec.0.y));
+        fields.add_field_method_get("z", |_, vec| Ok(vec.0.z));
+        fields.add_field_method_set("x", |_, vec, x: f32| Ok(vec.0.x = x));
+        fields.add_field_method_set("y", |_, vec, y: f32| Ok(vec.0.y = y));
+        fields.add_field_method_set("z", |_, vec, z: f32| Ok(vec.0.z = z));
+    }
+
+    fn add_methods<'lua, M: mlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_meta_method(mlua::MetaMethod::ToString, |_, vec, _: ()| {
+            Ok(format!("vec3({}, {}, {})", vec.0.x, vec.0.y, vec.0.z))
+        });
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct LuaQuat(pub Quat);
+
+impl UserData for LuaQuat {
+    fn add_fields<'lua, F: mlua::UserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_field_method_get("x", |_, quat| Ok(quat.0.x));
+        fields.add_field_method_get("y", |_, quat| Ok(quat.0.y));
+        fields.add_field_method_get("z", |_, quat| Ok(quat.0.z));
+        fields.add_field_method_get("w", |_, quat| Ok(quat.0.w));
+    }
+
+    fn add_methods<'lua, M: mlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
+        // euler angles in degrees, the same way the objects' rotations are set
+        methods.add_method("to_euler", |_, quat, _: ()| {
+            let (x, y, z) = quat.0.to_euler(glam::EulerRot::XYZ);
+            Ok((x.to_degrees(), y.to_degrees(), z.to_degrees()))
+        });
+
+        methods.add_meta_method(mlua::MetaMethod::ToString, |_, quat, _: ()| {
+            Ok(format!("quat({}, {}, {}, {})", quat.0.x, quat.0.y, quat.0.z, quat.0.w))
+        });
+    }
+}
+
 impl UserData for PerlinNoise {
     fn add_fields<'lua, F: mlua::UserDataFields<'lua, Self>>(fields: &mut F) {}
 
@@ -0,0 +1,145 @@
+use std::fs;
+
+use mlua::{Lua, Table, Value};
+
+use super::lua_functions::named_registry_table;
+use crate::managers::{assets, debugger, networking};
+
+const MODULES_REGISTRY_NAME: &str = "modules";
+// names of the modules that are being loaded, in the order they were required
+const LOADING_MODULES_REGISTRY_NAME: &str = "loading_modules";
+/// Module `lib.inventory` is the file `scripts/lua/lib/inventory.lua`.
+const MODULES_DIRECTORY: &str = "scripts/lua/";
+const SAFE_OS_FUNCTIONS: [&str; 4] = ["clock", "date", "difftime", "time"];
+
+/// Replaces the standard `require` with one that only loads the modules from the asset directory
+/// and removes the rest of the standard functions that can read or run files outside of it.
+/// Modules are cached per VM, so every system gets its own copy of the module.
+pub(crate) fn add_require(lua: &Lua, system_id: &str) -> mlua::Result<()> {
+    // the standard searchers look for the files and native libraries anywhere on the disk
+    if let Some(package) = lua.globals().get::<_, Option<Table>>("package")? {
+        package.set("path", "")?;
+        package.set("cpath", "")?;
+        package.set("loadlib", Value::Nil)?;
+    }
+
+    let globals = lua.globals();
+    globals.set("dofile", Value::Nil)?;
+    globals.set("loadfile", Value::Nil)?;
+    globals.set("io", Value::Nil)?;
+    // only the time functions of os are kept, the rest of it can run commands and remove files
+    if let Some(os) = globals.get::<_, Option<Table>>("os")? {
+        let safe_os = lua.create_table()?;
+        for name in SAFE_OS_FUNCTIONS {
+            safe_os.set(name, os.get::<_, Value>(name)?)?;
+        }
+        globals.set("os", safe_os)?;
+    }
+
+    let system_id = system_id.to_string();
+    let require = lua.create_function(move |lua, name: String| {
+        match require_module(lua, &system_id, &name) {
+            Ok(module) => Ok(module),
+            Err(err) => {
+                debugger::error(&format!("lua require error!\nsystem: {}, module: '{}'\nerror: {}", system_id, name, err));
+                Err(err)
+            }
+        }
+    })?;
+    lua.globals().set("require", require)
+}
+
+/// Asset paths of the modules that were loaded by the VM.
+pub(crate) fn loaded_module_paths(lua: &Lua) -> Vec<String> {
+    let modules = match lua.named_registry_value::<Option<Table>>(MODULES_REGISTRY_NAME) {
+        Ok(Some(modules)) => modules,
+        _ => return Vec::new(),
+    };
+
+    modules
+        .pairs::<String, Value>()
+        .filter_map(|pair| pair.ok())
+        .filter_map(|(name, _)| module_path(&name))
+        .collect()
+}
+
+fn require_module<'lua>(lua: &'lua Lua, system_id: &str, name: &str) -> mlua::Result<Value<'lua>> {
+    let modules = named_registry_table(lua, MODULES_REGISTRY_NAME)?;
+    let module: Value = modules.get(name)?;
+    if !module.is_nil() {
+        return Ok(module);
+    }
+
+    let loading_modules = named_registry_table(lua, LOADING_MODULES_REGISTRY_NAME)?;
+    let loading_names: Vec<String> = loading_modules.clone().sequence_values().collect::<mlua::Result<_>>()?;
+    if loading_names.iter().any(|loading_name| loading_name == name) {
+        return Err(mlua::Error::RuntimeError(format!(
+            "cyclic require: {} -> {}",
+            loading_names.join(" -> "),
+            name
+        )));
+    }
+
+    let path = match module_path(name) {
+        Some(path) => path,
+        None => {
+            return Err(mlua::Error::RuntimeError(format!(
+                "invalid module name '{}', use names like 'lib.inventory' for {}lib/inventory.lua",
+                name, MODULES_DIRECTORY
+            )))
+        }
+    };
+    let source = match fs::read_to_string(assets::get_full_asset_path(&path)) {
+        Ok(source) => source,
+        Err(err) => return Err(mlua::Error::RuntimeError(format!("failed to read {}: {}", path, err))),
+    };
+    // the modules are a part of the system's script, so clients with different ones are rejected too
+    networking::handshake::add_system_content(system_id, &path, source.as_bytes());
+
+    // the chunk is named after the file, so the errors look like 'scripts/lua/lib/inventory.lua:12: ...'
+    let loading_idx = loading_modules.raw_len() + 1;
+    loading_modules.raw_set(loading_idx, name)?;
+    let result = lua.load(&source).set_name(format!("@{}", path)).eval::<Value>();
+    loading_modules.raw_set(loading_idx, Value::Nil)?;
+
+    let module = match result? {
+        // like the standard require, modules that don't return anything are stored as true
+        Value::Nil => Value::Boolean(true),
+        module => module,
+    };
+    modules.set(name, module.clone())?;
+    Ok(module)
+}
+
+// module names are dot separated identifiers, so they can't leave the modules directory
+fn module_path(name: &str) -> Option<String> {
+    let is_valid = !name.is_empty() && name.split('.').all(|part| {
+        !part.is_empty() && part.chars().all(|char| char.is_ascii_alphanumeric() || char == '_')
+    });
+
+    match is_valid {
+        true => Some(format!("{}{}.lua", MODULES_DIRECTORY, name.replace('.', "/"))),
+        false => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dotted_names_become_paths() {
+        assert_eq!(module_path("utils").unwrap(), format!("{}utils.lua", MODULES_DIRECTORY));
+        assert_eq!(module_path("ui.menu_2").unwrap(), format!("{}ui/menu_2.lua", MODULES_DIRECTORY));
+    }
+
+    #[test]
+    fn names_cannot_leave_the_modules_directory() {
+        assert!(module_path("").is_none());
+        assert!(module_path("..secret").is_none());
+        assert!(module_path("ui.").is_none());
+        assert!(module_path("../secret").is_none());
+        assert!(module_path("ui/menu").is_none());
+        assert!(module_path("C:\\secret").is_none());
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use mlua::{FromLuaMulti, Function, HookTriggers, IntoLuaMulti, Lua};
+use once_cell::sync::Lazy;
+
+use crate::managers::debugger;
+
+static mut DEFAULT_LIMITS: LuaLimits = LuaLimits {
+    instruction_budget: 50_000_000,
+    memory_limit: 256 * 1024 * 1024,
+};
+static mut SYSTEMS_LIMITS: Lazy<HashMap<String, LuaLimits>> = Lazy::new(|| HashMap::new()); // String is system's id
+// the hook is called every this many instructions, so the budget is checked with this precision
+const INSTRUCTION_HOOK_INTERVAL: u32 = 1000;
+// a full collection is done when the VM uses more than this part of its memory limit
+const FULL_COLLECTION_MEMORY_RATIO: f64 = 0.75;
+
+/// Limits of a Lua system's VM, 0 disables the limit.
+#[derive(Debug, Clone, Copy)]
+pub struct LuaLimits {
+    /// How many Lua instructions one callback (update, event handler, coroutine resume, etc.) can run
+    /// before it's stopped with an error.
+    pub instruction_budget: u64,
+    /// Bytes the VM can allocate, the allocations over it fail with a memory error.
+    pub memory_limit: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SandboxStats {
+    pub used_memory: usize,
+    pub peak_memory: usize,
+    /// The most instructions a single callback has run (with the precision of the hook interval).
+    pub peak_instructions: u64,
+    /// Callbacks that were stopped because they ran out of the instruction budget.
+    pub aborted_callbacks: u32,
+    pub full_collections: u32,
+}
+
+// stored in the VM's app data, so the hook can get it
+struct SandboxState {
+    system_id: String,
+    limits: LuaLimits,
+    instructions: u64,
+    is_aborted: bool,
+    stats: SandboxStats,
+}
+
+/// Limits for the systems that don't have their own ones, used by the VMs created after the call.
+pub fn set_default_limits(limits: LuaLimits) {
+    unsafe { DEFAULT_LIMITS = limits };
+}
+
+pub fn default_limits() -> LuaLimits {
+    unsafe { DEFAULT_LIMITS }
+}
+
+/// Overrides the default limits for the system, if the system is already running the new limits
+/// are used starting from the next callback.
+pub fn set_system_limits(system_id: &str, limits: LuaLimits) {
+    unsafe { SYSTEMS_LIMITS.insert(system_id.into(), limits) };
+
+    if let Some(lua) = super::lua_vm_ref(system_id.into()) {
+        if let Err(err) = apply_limits(lua, system_id, limits) {
+            debugger::error(&format!("lua sandbox error!\nsystem: {}\nfailed to set the limits\nerror: {}", system_id, err));
+        }
+    }
+}
+
+pub fn system_limits(system_id: &str) -> LuaLimits {
+    unsafe {
+        match SYSTEMS_LIMITS.get(system_id) {
+            Some(limits) => *limits,
+            None => DEFAULT_LIMITS,
+        }
+    }
+}
+
+/// Memory and instruction stats of the system's VM.
+pub fn stats(system_id: &str) -> Option<SandboxStats> {
+    let lua = super::lua_vm_ref(system_id.into())?;
+    let state = lua.app_data_ref::<SandboxState>()?;
+
+    Some(SandboxStats {
+        used_memory: lua.used_memory(),
+        ..state.stats
+    })
+}
+
+/// Sets the memory limit and the instruction hook of a new VM, has to be called before the script is executed.
+pub(crate) fn add_sandbox(lua: &Lua, system_id: &str) -> mlua::Result<()> {
+    apply_limits(lua, system_id, system_limits(system_id))?;
+
+    lua.set_hook(HookTriggers::new().every_nth_instruction(INSTRUCTION_HOOK_INTERVAL), |lua, _| {
+        let mut state = match lua.app_data_mut::<SandboxState>() {
+            Some(state) => state,
+            None => return Ok(()),
+        };
+
+        state.instructions += INSTRUCTION_HOOK_INTERVAL as u64;
+        state.stats.peak_instructions = state.stats.peak_instructions.max(state.instructions);
+
+        let budget = state.limits.instruction_budget;
+        if budget == 0 || state.instructions <= budget {
+            return Ok(());
+        }
+
+        // the hook keeps failing until the callback returns, so pcall can't keep a runaway loop going
+        if !state.is_aborted {
+            state.is_aborted = true;
+            state.stats.aborted_callbacks += 1;
+            debugger::error(&format!(
+                "lua sandbox error!\nsystem: {}, the callback ran more than {} instructions and is stopped",
+                state.system_id, budget
+            ));
+        }
+        Err(mlua::Error::RuntimeError(format!("instruction budget of {} is exceeded", budget)))
+    });
+
+    Ok(())
+}
+
+/// Resets the instruction count, called before every call from Rust into the VM.
+pub(crate) fn start_callback(lua: &Lua) {
+    if let Some(mut state) = lua.app_data_mut::<SandboxState>() {
+        state.instructions = 0;
+        state.is_aborted = false;
+    }
+}
+
+/// Calls the function as a new callback with it's own instruction budget. The calls that can happen while
+/// another callback of the VM is running (e.g. a save migration registered in start) don't use up it's budget,
+/// it's instruction count is put back after the call.
+pub(crate) fn call_callback<'lua, A, R>(lua: &'lua Lua, function: &Function<'lua>, args: A) -> mlua::Result<R>
+where
+    A: IntoLuaMulti<'lua>,
+    R: FromLuaMulti<'lua>,
+{
+    let outer_state = lua
+        .app_data_ref::<SandboxState>()
+        .map(|state| (state.instructions, state.is_aborted));
+
+    start_callback(lua);
+    let result = function.call(args);
+
+    if let (Some((instructions, is_aborted)), Some(mut state)) = (outer_state, lua.app_data_mut::<SandboxState>()) {
+        state.instructions = instructions;
+        state.is_aborted = is_aborted;
+    }
+    result
+}
+
+/// Called once a frame. Does a small incremental step and does a full collection only if the VM
+/// gets close to its memory limit.
+pub(crate) fn collect_garbage(lua: &Lua, system_id: &str) {
+    let used_memory = lua.used_memory();
+    // the state isn't borrowed during the collection, the finalizers can run Lua code that calls the hook
+    let is_full_collection = match lua.app_data_mut::<SandboxState>() {
+        Some(mut state) => {
+            state.stats.peak_memory = state.stats.peak_memory.max(used_memory);
+            let memory_limit = state.limits.memory_limit;
+            let is_full_collection = memory_limit > 0 && used_memory as f64 > memory_limit as f64 * FULL_COLLECTION_MEMORY_RATIO;
+            if is_full_collection {
+                state.stats.full_collections += 1;
+            }
+            is_full_collection
+        },
+        None => return,
+    };
+
+    let result = match is_full_collection {
+        true => lua.gc_collect(),
+        false => lua.gc_step().map(|_| ()),
+    };
+
+    if let Err(err) = result {
+        debugger::error(&format!("lua sandbox error!\nsystem: {}\ngarbage collection failed\nerror: {}", system_id, err));
+    }
+}
+
+fn apply_limits(lua: &Lua, system_id: &str, limits: LuaLimits) -> mlua::Result<()> {
+    lua.set_memory_limit(limits.memory_limit)?;
+
+    match lua.app_data_mut::<SandboxState>() {
+        Some(mut state) => state.limits = limits,
+        None => {
+            lua.set_app_data(SandboxState {
+                system_id: system_id.into(),
+                limits,
+                instructions: 0,
+                is_aborted: false,
+                stats: SandboxStats::default(),
+            });
+        },
+    }
+
+    Ok(())
+}
@@ -0,0 +1,286 @@
+use std::collections::{HashMap, HashSet};
+
+use mlua::{AnyUserData, FromLua, Function, Lua, MultiValue, RegistryKey, Table, Thread, ThreadStatus, Value, Variadic};
+use once_cell::sync::Lazy;
+
+use super::sandbox;
+use crate::{framework::Framework, managers::debugger};
+
+static mut SCHEDULERS: Lazy<HashMap<String, SystemScheduler>> = Lazy::new(|| HashMap::new()); // String is system's id
+static mut NEXT_TASK_ID: u32 = 1;
+
+// wait functions yield to the scheduler, the values they return are the ones the scheduler resumes the coroutine with
+const WAIT_FUNCTIONS: &str = r#"
+local function check_yieldable(function_name)
+    if not coroutine.isyieldable() then
+        error(function_name .. " can only be used inside of a coroutine, start one with start_coroutine", 3)
+    end
+end
+
+function wait(seconds)
+    check_yieldable("wait")
+    return coroutine.yield("seconds", seconds or 0)
+end
+
+function wait_frames(frames)
+    check_yieldable("wait_frames")
+    return coroutine.yield("frames", frames or 1)
+end
+
+function wait_until(condition)
+    check_yieldable("wait_until")
+    return coroutine.yield("until", condition)
+end
+"#;
+
+/// Coroutines and timers of a Lua system.
+#[derive(Default)]
+pub(crate) struct SystemScheduler {
+    tasks: Vec<Task>,
+    // tasks that were cancelled while the scheduler was updating them
+    cancelled: HashSet<u32>,
+}
+
+struct Task {
+    id: u32,
+    kind: TaskKind,
+}
+
+enum TaskKind {
+    Coroutine { thread: RegistryKey, wait: Wait },
+    Timer { callback: RegistryKey, interval: f32, remaining: f32 },
+}
+
+enum Wait {
+    /// Not started yet, the key is the table with the arguments of the coroutine's function.
+    Start(RegistryKey),
+    Seconds(f32),
+    Frames(u32),
+    /// The function is called every frame until it returns true.
+    Until(RegistryKey),
+}
+
+/// Adds wait, wait_frames and wait_until. They return the framework, the one that was used
+/// before the wait can't be used after it.
+pub(crate) fn add_wait_functions(lua: &Lua) -> mlua::Result<()> {
+    lua.load(WAIT_FUNCTIONS).set_name("=wait_functions").exec()
+}
+
+/// The coroutine runs function(framework, ...) starting from this frame's update of the scheduler.
+pub(crate) fn start_coroutine(lua: &Lua, system_id: &str, function: Function, args: Variadic<Value>) -> mlua::Result<u32> {
+    let thread = lua.create_thread(function)?;
+    let args = lua.create_sequence_from(args)?;
+    let task = Task {
+        id: next_task_id(),
+        kind: TaskKind::Coroutine {
+            thread: lua.create_registry_value(thread)?,
+            wait: Wait::Start(lua.create_registry_value(args)?),
+        },
+    };
+
+    Ok(add_task(system_id, task))
+}
+
+/// Calls callback(framework) every `seconds` until it returns false or the timer is cancelled.
+pub(crate) fn start_timer(lua: &Lua, system_id: &str, seconds: f32, callback: Function) -> mlua::Result<u32> {
+    let task = Task {
+        id: next_task_id(),
+        kind: TaskKind::Timer {
+            callback: lua.create_registry_value(callback)?,
+            interval: seconds.max(0.0),
+            remaining: seconds.max(0.0),
+        },
+    };
+
+    Ok(add_task(system_id, task))
+}
+
+/// Cancels a coroutine or a timer. Cancelling the ones that are finished does nothing.
+pub(crate) fn cancel(lua: &Lua, system_id: &str, task_id: u32) {
+    let scheduler = match unsafe { SCHEDULERS.get_mut(system_id) } {
+        Some(scheduler) => scheduler,
+        None => return,
+    };
+
+    match scheduler.tasks.iter().position(|task| task.id == task_id) {
+        Some(idx) => {
+            let task = scheduler.tasks.remove(idx);
+            remove_task(lua, task);
+        },
+        // it may be the task that is being updated right now
+        None => {
+            scheduler.cancelled.insert(task_id);
+        },
+    }
+}
+
+/// Drops all of the coroutines and timers of the system.
+pub(crate) fn cancel_all(system_id: &str) {
+    unsafe { SCHEDULERS.remove(system_id) };
+}
+
+/// Takes the tasks of the system out, so they can be put back with restore_scheduler
+/// (used when the system's VM is replaced).
+pub(crate) fn take_scheduler(system_id: &str) -> Option<SystemScheduler> {
+    unsafe { SCHEDULERS.remove(system_id) }
+}
+
+pub(crate) fn restore_scheduler(system_id: &str, scheduler: Option<SystemScheduler>) {
+    unsafe {
+        match scheduler {
+            Some(scheduler) => SCHEDULERS.insert(system_id.into(), scheduler),
+            None => SCHEDULERS.remove(system_id),
+        };
+    }
+}
+
+/// Resumes the coroutines and calls the timers of the system that are ready.
+pub(crate) fn update(system_id: &str, lua: &Lua, framework: &mut Framework) {
+    let tasks = match unsafe { SCHEDULERS.get_mut(system_id) } {
+        Some(scheduler) if !scheduler.tasks.is_empty() => std::mem::take(&mut scheduler.tasks),
+        _ => return,
+    };
+    let delta_time = framework.delta_time.as_secs_f32();
+
+    let mut kept_tasks = Vec::new();
+    let scope_result = lua.scope(|scope| {
+        let framework_userdata = scope.create_userdata_ref_mut(framework)?;
+        for mut task in tasks {
+            // every coroutine resume and timer call gets its own instruction budget
+            sandbox::start_callback(lua);
+            match update_task(lua, &mut task, &framework_userdata, delta_time) {
+                Ok(true) => kept_tasks.push(task),
+                Ok(false) => remove_task(lua, task),
+                Err(err) => {
+                    debugger::error(&format!(
+                        "lua scheduler error!\nsystem: {}, coroutine/timer {} is stopped\nerror: {}",
+                        system_id, task.id, err
+                    ));
+                    remove_task(lua, task);
+                },
+            }
+        }
+        Ok(())
+    });
+
+    if let Err(err) = scope_result {
+        debugger::error(&format!("lua scheduler error!\nsystem: {}\nerror: {}", system_id, err));
+    }
+
+    let scheduler = match unsafe { SCHEDULERS.get_mut(system_id) } {
+        Some(scheduler) => scheduler,
+        // the system was destroyed during the update
+        None => return,
+    };
+    let (cancelled_tasks, mut kept_tasks): (Vec<Task>, Vec<Task>) =
+        kept_tasks.into_iter().partition(|task| scheduler.cancelled.contains(&task.id));
+    for task in cancelled_tasks {
+        remove_task(lua, task);
+    }
+    // the tasks that were started during the update go after the older ones
+    kept_tasks.append(&mut scheduler.tasks);
+    scheduler.tasks = kept_tasks;
+    scheduler.cancelled.clear();
+}
+
+// returns false if the task is finished
+fn update_task<'lua>(lua: &'lua Lua, task: &mut Task, framework: &AnyUserData<'lua>, delta_time: f32) -> mlua::Result<bool> {
+    let (thread, wait) = match &mut task.kind {
+        TaskKind::Timer { callback, interval, remaining } => {
+            *remaining -= delta_time;
+            if *remaining > 0.0 {
+                return Ok(true);
+            }
+            *remaining = (*remaining + *interval).max(0.0);
+
+            let callback: Function = lua.registry_value(callback)?;
+            let result: Value = callback.call(framework.clone())?;
+            // returning false stops the timer
+            return Ok(!matches!(result, Value::Boolean(false)));
+        },
+        TaskKind::Coroutine { thread, wait } => (thread, wait),
+    };
+
+    let is_ready = match wait {
+        Wait::Start(_) => true,
+        Wait::Seconds(remaining) => {
+            *remaining -= delta_time;
+            *remaining <= 0.0
+        },
+        Wait::Frames(frames) => {
+            *frames = frames.saturating_sub(1);
+            *frames == 0
+        },
+        Wait::Until(condition) => {
+            let condition: Function = lua.registry_value(condition)?;
+            condition.call::<_, bool>(framework.clone())?
+        },
+    };
+    if !is_ready {
+        return Ok(true);
+    }
+
+    let thread: Thread = lua.registry_value(thread)?;
+    let yielded: MultiValue = match wait {
+        Wait::Start(args) => {
+            let args: Table = lua.registry_value(args)?;
+            let args: Vec<Value> = args.sequence_values().collect::<mlua::Result<_>>()?;
+            thread.resume((framework.clone(), Variadic::from_iter(args)))?
+        },
+        _ => thread.resume(framework.clone())?,
+    };
+
+    if thread.status() != ThreadStatus::Resumable {
+        return Ok(false);
+    }
+
+    let mut yielded = yielded.into_iter();
+    let new_wait = match (yielded.next(), yielded.next()) {
+        (Some(Value::String(kind)), value) => match (kind.to_str()?, value) {
+            ("seconds", Some(seconds)) => Wait::Seconds(f32::from_lua(seconds, lua)?),
+            ("frames", Some(frames)) => Wait::Frames(u32::from_lua(frames, lua)?.max(1)),
+            ("until", Some(Value::Function(condition))) => Wait::Until(lua.create_registry_value(condition)?),
+            _ => Wait::Frames(1),
+        },
+        // a plain coroutine.yield() waits for the next frame
+        _ => Wait::Frames(1),
+    };
+    remove_wait(lua, std::mem::replace(wait, new_wait));
+
+    Ok(true)
+}
+
+fn add_task(system_id: &str, task: Task) -> u32 {
+    let task_id = task.id;
+    unsafe { SCHEDULERS.entry(system_id.into()).or_default().tasks.push(task) };
+    task_id
+}
+
+fn next_task_id() -> u32 {
+    unsafe {
+        let task_id = NEXT_TASK_ID;
+        NEXT_TASK_ID = NEXT_TASK_ID.wrapping_add(1).max(1);
+        task_id
+    }
+}
+
+fn remove_task(lua: &Lua, task: Task) {
+    match task.kind {
+        TaskKind::Coroutine { thread, wait } => {
+            let _ = lua.remove_registry_value(thread);
+            remove_wait(lua, wait);
+        },
+        TaskKind::Timer { callback, .. } => {
+            let _ = lua.remove_registry_value(callback);
+        },
+    }
+}
+
+fn remove_wait(lua: &Lua, wait: Wait) {
+    match wait {
+        Wait::Start(key) | Wait::Until(key) => {
+            let _ = lua.remove_registry_value(key);
+        },
+        Wait::Seconds(_) | Wait::Frames(_) => (),
+    }
+}
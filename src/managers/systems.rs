@@ -1,13 +1,14 @@
-use std::{collections::HashMap, time::Instant};
+use std::{collections::{BTreeMap, HashMap}, time::Instant};
 
 use crate::{framework::Framework, objects::ObjectGroup, systems::System};
 use egui_glium::egui_winit::egui::Context;
+use glam::{Quat, Vec2, Vec3};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use splines::Spline;
 
 use super::{
-    debugger, networking,
+    debugger, events::{self, Event}, networking,
 };
 
 static mut SYSTEMS: Vec<Box<dyn System>> = vec![];
@@ -43,12 +44,14 @@ pub fn update(framework: &mut Framework) {
         if networking::is_server() {
             for system in &mut SYSTEMS {
                 system.server_update(framework);
+                system.update_coroutines(framework);
                 system.update_objects(framework);
             }
         } else {
             for system in &mut SYSTEMS {
                 let update_time_intant = Instant::now();
                 system.client_update(framework);
+                system.update_coroutines(framework);
                 let client_update_time = update_time_intant.elapsed();
                 system.update_objects(framework);
                 let client_objects_update_time = update_time_intant.elapsed() - client_update_time;
@@ -61,6 +64,36 @@ pub fn update(framework: &mut Framework) {
     }
 }
 
+pub fn before_save(framework: &mut Framework) {
+    unsafe {
+        for system in &mut SYSTEMS {
+            system.before_save(framework);
+        }
+    }
+}
+
+/// Delivers the queued events: in the order they were emitted, to the subscribed systems in the order they were added.
+pub fn dispatch_events(framework: &mut Framework) {
+    let events: Vec<Event> = events::take_events();
+    unsafe {
+        for event in &events {
+            for system in &mut SYSTEMS {
+                if events::is_subscribed(system.system_id(), &event.name) {
+                    system.handle_event(framework, event);
+                }
+            }
+        }
+    }
+}
+
+pub fn region_loaded(framework: &mut Framework, group: &str, key: &str) {
+    unsafe {
+        for system in &mut SYSTEMS {
+            system.region_loaded(framework, group, key);
+        }
+    }
+}
+
 pub fn get_systems_iter<'a>() -> std::slice::Iter<'a, Box<dyn System>> {
     unsafe { SYSTEMS.iter() }
 }
@@ -193,6 +226,10 @@ pub fn get_object_name_with_id(id: u128) -> Option<String> {
     unsafe { OBJECTS_ID_NAMES.get(&id).cloned() }
 }
 
+pub fn get_object_system_with_id(id: u128) -> Option<String> {
+    unsafe { OBJECTS_ID_SYSTEMS.get(&id).cloned() }
+}
+
 pub fn get_value_in_system(system_id: &str, value_name: String) -> Option<SystemValue> {
     match get_system_mut_with_id(system_id) {
         Some(system) => system.get_value(value_name),
@@ -221,4 +258,154 @@ pub enum SystemValue {
     Bool(bool),
     Spline(Spline<f32, f32>),
     Vec(Vec<SystemValue>),
+    // new variants go to the end, saves and network messages store the index of the variant
+    Map(BTreeMap<String, SystemValue>),
+    Int64(i64),
+    Vec2(Vec2),
+    Vec3(Vec3),
+    Quat(Quat),
+    Bytes(Vec<u8>),
+    Nil,
+}
+
+impl SystemValue {
+    /// Name of the variant, used in the error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            SystemValue::String(_) => "String",
+            SystemValue::Int(_) => "Int",
+            SystemValue::UInt(_) => "UInt",
+            SystemValue::Float(_) => "Float",
+            SystemValue::Bool(_) => "Bool",
+            SystemValue::Spline(_) => "Spline",
+            SystemValue::Vec(_) => "Vec",
+            SystemValue::Map(_) => "Map",
+            SystemValue::Int64(_) => "Int64",
+            SystemValue::Vec2(_) => "Vec2",
+            SystemValue::Vec3(_) => "Vec3",
+            SystemValue::Quat(_) => "Quat",
+            SystemValue::Bytes(_) => "Bytes",
+            SystemValue::Nil => "Nil",
+        }
+    }
+
+    /// Vec3 values and lists of three numbers (the way vectors were stored before Vec3 was added).
+    pub fn as_vec3(&self) -> Option<Vec3> {
+        match self {
+            SystemValue::Vec3(value) => Some(*value),
+            SystemValue::Vec(values) => match values.as_slice() {
+                [x, y, z] => Some(Vec3::new(x.as_f32()?, y.as_f32()?, z.as_f32()?)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn as_vec2(&self) -> Option<Vec2> {
+        match self {
+            SystemValue::Vec2(value) => Some(*value),
+            SystemValue::Vec(values) => match values.as_slice() {
+                [x, y] => Some(Vec2::new(x.as_f32()?, y.as_f32()?)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn as_quat(&self) -> Option<Quat> {
+        match self {
+            SystemValue::Quat(value) => Some(*value),
+            SystemValue::Vec(values) => match values.as_slice() {
+                [x, y, z, w] => Some(Quat::from_xyzw(x.as_f32()?, y.as_f32()?, z.as_f32()?, w.as_f32()?)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn as_f32(&self) -> Option<f32> {
+        match self {
+            SystemValue::Float(value) => Some(*value),
+            SystemValue::Int(value) => Some(*value as f32),
+            SystemValue::UInt(value) => Some(*value as f32),
+            SystemValue::Int64(value) => Some(*value as f32),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            SystemValue::Int(value) => Some(*value as i64),
+            SystemValue::UInt(value) => Some(*value as i64),
+            SystemValue::Int64(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_map(&self) -> Option<&BTreeMap<String, SystemValue>> {
+        match self {
+            SystemValue::Map(map) => Some(map),
+            _ => None,
+        }
+    }
+}
+
+impl From<Vec2> for SystemValue {
+    fn from(value: Vec2) -> Self {
+        SystemValue::Vec2(value)
+    }
+}
+
+impl From<Vec3> for SystemValue {
+    fn from(value: Vec3) -> Self {
+        SystemValue::Vec3(value)
+    }
+}
+
+impl From<Quat> for SystemValue {
+    fn from(value: Quat) -> Self {
+        SystemValue::Quat(value)
+    }
+}
+
+impl From<i64> for SystemValue {
+    fn from(value: i64) -> Self {
+        SystemValue::Int64(value)
+    }
+}
+
+impl From<BTreeMap<String, SystemValue>> for SystemValue {
+    fn from(value: BTreeMap<String, SystemValue>) -> Self {
+        SystemValue::Map(value)
+    }
+}
+
+impl From<Vec<u8>> for SystemValue {
+    fn from(value: Vec<u8>) -> Self {
+        SystemValue::Bytes(value)
+    }
+}
+
+impl TryFrom<SystemValue> for Vec2 {
+    type Error = SystemValue;
+
+    fn try_from(value: SystemValue) -> Result<Self, Self::Error> {
+        value.as_vec2().ok_or(value)
+    }
+}
+
+impl TryFrom<SystemValue> for Vec3 {
+    type Error = SystemValue;
+
+    fn try_from(value: SystemValue) -> Result<Self, Self::Error> {
+        value.as_vec3().ok_or(value)
+    }
+}
+
+impl TryFrom<SystemValue> for Quat {
+    type Error = SystemValue;
+
+    fn try_from(value: SystemValue) -> Result<Self, Self::Error> {
+        value.as_quat().ok_or(value)
+    }
 }
@@ -4,7 +4,7 @@ use ez_al::{EzAl, SoundSource};
 use glam::{Vec2, Vec3};
 use image::GenericImageView;
 use crate::{framework::{DebugMode, Framework}, managers::assets::{AssetManager, SoundAssetId}};
-use super::{assets::get_full_asset_path, debugger, physics::RenderColliderType, systems};
+use super::{assets::get_full_asset_path, debugger, networking::{self, conditioner::ConditionerSettings}, physics::RenderColliderType, scripting::lua, systems};
 
 pub struct ImageToLoad {
     id: String,
@@ -1437,6 +1437,33 @@ pub fn draw_inspector(framework: &mut Framework, ui: &mut Ui, fps: &usize, ui_st
         let system_id = system.system_id();
 
         ui.collapsing(system_id, |ui| {
+            if lua::is_lua_system(system_id) && ui.small_button("reload script").clicked() {
+                lua::request_reload(system_id);
+            }
+            if let Some(stats) = lua::sandbox::stats(system_id) {
+                let limits = lua::sandbox::system_limits(system_id);
+                ui.label(format!(
+                    "lua memory: {:.2} MB (peak {:.2} MB, limit {})",
+                    megabytes(stats.used_memory),
+                    megabytes(stats.peak_memory),
+                    match limits.memory_limit {
+                        0 => "none".to_string(),
+                        memory_limit => format!("{:.2} MB", megabytes(memory_limit)),
+                    }
+                ));
+                ui.label(format!(
+                    "peak instructions per callback: {} (budget {})",
+                    stats.peak_instructions,
+                    match limits.instruction_budget {
+                        0 => "none".to_string(),
+                        instruction_budget => instruction_budget.to_string(),
+                    }
+                ));
+                ui.label(format!(
+                    "aborted callbacks: {}, full gc collections: {}",
+                    stats.aborted_callbacks, stats.full_collections
+                ));
+            }
             for object in system.objects_list() {
                 let object_name = object.name();
                 if ui.small_button(object_name).clicked() {
@@ -1461,6 +1488,10 @@ pub fn draw_inspector(framework: &mut Framework, ui: &mut Ui, fps: &usize, ui_st
 
     ui.separator();
 
+    ui.collapsing("network", |ui| draw_network_stats(ui, ui_state));
+
+    ui.separator();
+
     let selected_object = &mut ui_state.selected_inspector_object;
     if let Some(selected_object) = selected_object {
         match systems::get_system_mut_with_id(&selected_object.current_selected_object_system) {
@@ -1510,6 +1541,13 @@ pub fn draw_inspector(framework: &mut Framework, ui: &mut Ui, fps: &usize, ui_st
                                             systems::SystemValue::Bool(bool) => ui.label(bool.to_string()),
                                             systems::SystemValue::Spline(_) => ui.label("inspecting splines is unsupported :c"),
                                             systems::SystemValue::Vec(_) => ui.label("inspecting vectors is unsupported :c"),
+                                            systems::SystemValue::Map(_) => ui.label("inspecting maps is unsupported :c"),
+                                            systems::SystemValue::Int64(int) => ui.label(int.to_string()),
+                                            systems::SystemValue::Vec2(vec) => ui.label(format!("{} {}", vec.x, vec.y)),
+                                            systems::SystemValue::Vec3(vec) => ui.label(format!("{} {} {}", vec.x, vec.y, vec.z)),
+                                            systems::SystemValue::Quat(quat) => ui.label(format!("{} {} {} {}", quat.x, quat.y, quat.z, quat.w)),
+                                            systems::SystemValue::Bytes(bytes) => ui.label(format!("{} bytes", bytes.len())),
+                                            systems::SystemValue::Nil => ui.label("nil"),
                                         };
                                     }
                                 });
@@ -1738,6 +1776,63 @@ pub fn draw_inspector(framework: &mut Framework, ui: &mut Ui, fps: &usize, ui_st
 pub struct UiState {
     full_debug_checkbox_val: bool,
     selected_inspector_object: Option<SelectedInspectorObject>,
+    network_conditioner: ConditionerSettings,
+}
+
+fn draw_network_stats(ui: &mut Ui, ui_state: &mut UiState) {
+    let connections = networking::stats::get_connections_stats();
+    if connections.is_empty() {
+        ui.label("no connections");
+    }
+    for (client_id, stats) in connections {
+        ui.collapsing(format!("client {}", client_id), |ui| {
+            ui.label(format!("rtt: {:.1} ms", stats.rtt_ms));
+            ui.label(format!("packet loss: {:.1}%", stats.packet_loss * 100.0));
+            ui.label(format!("sent: {:.1} KB/s", stats.bytes_sent_per_second / 1024.0));
+            ui.label(format!("received: {:.1} KB/s", stats.bytes_received_per_second / 1024.0));
+        });
+    }
+
+    ui.collapsing("channels", |ui| {
+        for (channel, counter) in networking::stats::get_channels_traffic() {
+            ui.label(format!(
+                "{}: sent {} B ({} msgs), received {} B ({} msgs)",
+                channel, counter.bytes_sent, counter.messages_sent, counter.bytes_received, counter.messages_received
+            ));
+        }
+    });
+
+    ui.collapsing("systems", |ui| {
+        for (system_id, counter) in networking::stats::get_systems_traffic() {
+            ui.label(format!(
+                "{}: sent {} B ({} msgs), received {} B ({} msgs)",
+                system_id, counter.bytes_sent, counter.messages_sent, counter.bytes_received, counter.messages_received
+            ));
+        }
+    });
+
+    if ui.small_button("reset counters").clicked() {
+        networking::stats::reset_traffic_counters();
+    }
+
+    ui.collapsing("conditioner", |ui| {
+        let mut enabled = networking::conditioner::get_conditioner().is_some();
+        let settings = &mut ui_state.network_conditioner;
+        let old_settings = *settings;
+
+        let enabled_changed = ui.checkbox(&mut enabled, "simulate bad network").changed();
+        ui.add(Slider::new(&mut settings.latency_ms, 0.0..=1000.0).text("latency (ms)"));
+        ui.add(Slider::new(&mut settings.jitter_ms, 0.0..=500.0).text("jitter (ms)"));
+        ui.add(Slider::new(&mut settings.loss, 0.0..=1.0).text("loss"));
+        ui.add(Slider::new(&mut settings.duplication, 0.0..=1.0).text("duplication"));
+
+        if enabled_changed || (enabled && old_settings != *settings) {
+            match enabled {
+                true => networking::conditioner::set_conditioner(Some(*settings)),
+                false => networking::conditioner::set_conditioner(None),
+            }
+        }
+    });
 }
 
 #[derive(Default, Debug)]
@@ -1878,6 +1973,10 @@ pub fn draw_update_time(framework: &Framework, ui: &mut Ui) {
     });
 }
 
+fn megabytes(bytes: usize) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
 fn set_visuals_to_default(visuals: &mut Visuals) {
     visuals.widgets.inactive.fg_stroke = Stroke::new(0.0, Color32::from_rgb(30, 30, 30));
     visuals.widgets.active.fg_stroke = Stroke::new(0.0, Color32::from_rgb(30, 30, 30));
@@ -26,6 +26,17 @@ pub fn rad_vec_to_deg(rad_vec: Vec3) -> Vec3 {
     Vec3::new(x, y, z)
 }
 
+/// FNV-1a hash. Unlike std's DefaultHasher it never changes between Rust versions,
+/// so it can be stored in files and compared between builds.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
 pub fn rotate_vector(direction: Vec3, rotation: Vec3) -> Vec3 {
     let global_rotation = deg_vec_to_rad(rotation);
     let rotation_mat = Mat4::from_euler(glam::EulerRot::XYZ, global_rotation.x, global_rotation.y, global_rotation.z);
@@ -66,3 +77,15 @@ impl PerlinNoise {
         self.noise.get([coordinate as f64, 2.0]) as f32
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fnv1a_matches_reference_values() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x85944171f73967e8);
+    }
+}
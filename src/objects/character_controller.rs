@@ -1,10 +1,11 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 
 use super::{gen_object_id, Object, ObjectGroup, Transform};
 use crate::{
     framework::Framework,
     managers::{
         debugger,
+        networking::prediction::{self, InputCommand, MAX_INPUT_DELTA_TIME},
         physics::{self, BodyColliderType, CollisionGroups, ObjectBodyParameters, PhysicsManager}, systems,
     },
     math_utils::{self, deg_to_rad},
@@ -26,6 +27,7 @@ pub struct CharacterController {
     controller: KinematicCharacterController,
     collider: ColliderHandle,
     movement: Option<CharacterControllerMovement>,
+    prediction: PredictionMode,
     object_properties: HashMap<String, Vec<crate::managers::systems::SystemValue>>
 }
 
@@ -35,6 +37,34 @@ pub struct CharacterControllerMovement {
     pub speed: f32,
 }
 
+// If the position predicted by the client differs from the server's one by more than that, the client rewinds.
+const RECONCILIATION_TOLERANCE: f32 = 0.01;
+const MAX_PENDING_INPUTS: usize = 128;
+
+#[derive(Debug)]
+pub enum PredictionMode {
+    /// Controller is moved only locally.
+    None,
+    /// Client: inputs are applied immediately, sent to the server and re-applied when the server disagrees.
+    Predicted {
+        next_sequence: u32,
+        pending_inputs: VecDeque<PendingInput>,
+    },
+    /// Server: controller is moved only by the inputs of it's owner.
+    Authoritative {
+        owner: u64,
+        /// Max distance (per second) that the owner's inputs can move the controller.
+        max_speed: f32,
+        last_sequence: Option<u32>,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PendingInput {
+    pub command: InputCommand,
+    pub predicted_position: Vec3,
+}
+
 impl CharacterController {
     pub fn new(
         physics: &mut PhysicsManager,
@@ -88,6 +118,7 @@ impl CharacterController {
             collider: collider_handle,
             id,
             movement: None,
+            prediction: PredictionMode::None,
             object_properties: HashMap::new()
         }
     }
@@ -195,68 +226,183 @@ impl CharacterController {
     }
 
     pub fn move_controller_ignoring_rotation(&mut self, framework: &mut Framework, direction: Vec3) {
-        let mut new_position = None;
-        {
-            let collider = framework.physics.collider_set.get(self.collider);
-            if let Some(collider) = collider {
-                let shape = collider.shape();
-                let global_transform = self.global_transform();
-                let global_position = global_transform.position;
+        self.move_locally(framework, direction, "move_controller_ignoring_rotation");
+    }
 
-                let movement = self.controller.move_shape(
-                    framework.delta_time().as_secs_f32(),
-                    &framework.physics.rigid_body_set,
-                    &framework.physics.collider_set,
-                    &framework.physics.query_pipeline,
-                    shape,
-                    &global_position.into(),
-                    direction.into(),
-                    QueryFilter::new().exclude_sensors(),
-                    |_| {},
-                );
+    pub fn move_controller(&mut self, framework: &mut Framework, direction: Vec3) {
+        let global_rotation = self.global_transform().rotation;
+        let direction = math_utils::rotate_vector(direction, global_rotation);
+        self.move_locally(framework, direction, "move_controller");
+    }
 
-                let translation: Vec3;
+    // Every local movement goes through here, so authoritative controllers can't be moved
+    // and the movement of predicted ones is always recorded and sent to the server.
+    fn move_locally(&mut self, framework: &mut Framework, direction: Vec3, function_name: &str) {
+        if self.is_authoritative(function_name) {
+            return;
+        }
 
-                if direction.x == 0.0 && direction.y < 0.0 && direction.z == 0.0 {
-                    translation = Vec3::new(
-                        0.0,
-                        movement.translation.y,
-                        0.0,
-                    );
-                } else {
-                    translation = Vec3::new(
-                        movement.translation.x,
-                        movement.translation.y,
-                        movement.translation.z,
-                    );
-                }
+        let delta_time = framework.delta_time().as_secs_f32();
+        self.move_with_delta_time(framework, direction, delta_time);
+
+        let predicted_position = self.transform.position;
+        if let PredictionMode::Predicted { next_sequence, pending_inputs } = &mut self.prediction {
+            let command = InputCommand {
+                sequence: *next_sequence,
+                direction,
+                delta_time,
+            };
+            *next_sequence = next_sequence.wrapping_add(1);
+
+            pending_inputs.push_back(PendingInput { command, predicted_position });
+            while pending_inputs.len() > MAX_PENDING_INPUTS {
+                pending_inputs.pop_front();
+            }
 
-                let object_position = self.local_transform().position
-                    + translation;
-                self.set_position(framework, object_position, false);
-                new_position = Some(object_position);
-            } else {
-                debugger::error(
-                    "CharacterController's move_controller error!\nfailed to get collider",
-                );
+            let commands = pending_inputs.iter().map(|input| input.command).collect();
+            match systems::get_object_system_with_id(self.id) {
+                Some(system_id) => prediction::send_inputs(&system_id, &self.name, commands),
+                None => debugger::error(&format!(
+                    "CharacterController '{}' error!\nfailed to send inputs, failed to get the system of the object",
+                    self.name
+                )),
             }
         }
+    }
 
-        {
-            if let Some(new_position) = new_position {
-                let collider = framework.physics.collider_set.get_mut(self.collider);
-                if let Some(collider) = collider {
-                    collider.set_position(new_position.into());
+    // Warns and returns true if the controller can be moved only by it's owner's inputs.
+    fn is_authoritative(&self, function_name: &str) -> bool {
+        match self.prediction {
+            PredictionMode::Authoritative { .. } => {
+                debugger::warn(&format!(
+                    "CharacterController '{}' warning!\n{} is ignored, the controller is moved only by it's owner's inputs",
+                    self.name, function_name
+                ));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn set_prediction_mode(&mut self, prediction: PredictionMode) {
+        if let PredictionMode::Authoritative { .. } = prediction {
+            self.movement = None;
+        }
+        self.prediction = prediction;
+    }
+
+    pub fn prediction_mode(&self) -> &PredictionMode {
+        &self.prediction
+    }
+
+    pub fn is_predicted(&self) -> bool {
+        match self.prediction {
+            PredictionMode::Predicted { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Server: replays the inputs sent by the owner of the controller.
+    /// Returns the last applied input sequence and the resulting position.
+    pub(crate) fn apply_remote_inputs(
+        &mut self,
+        framework: &mut Framework,
+        sender: u64,
+        commands: &[InputCommand],
+    ) -> Option<(u32, Vec3)> {
+        let (owner, max_speed, mut last_sequence) = match self.prediction {
+            PredictionMode::Authoritative { owner, max_speed, last_sequence } => (owner, max_speed, last_sequence),
+            _ => {
+                debugger::warn(&format!(
+                    "CharacterController '{}' warning!\ngot inputs from client {}, but the controller is not authoritative",
+                    self.name, sender
+                ));
+                return None;
+            }
+        };
+
+        if owner != sender {
+            debugger::warn(&format!(
+                "CharacterController '{}' warning!\nclient {} sent inputs, but the owner is client {}",
+                self.name, sender, owner
+            ));
+            return None;
+        }
+
+        for command in commands {
+            if let Some(last_sequence) = last_sequence {
+                if command.sequence <= last_sequence {
+                    continue;
                 }
-            } else {
-                debugger::error(
-                    "CharacterController's move_controller error!\nfailed to get collider",
-                );
             }
+
+            let delta_time = match command.delta_time.is_finite() {
+                true => command.delta_time.clamp(0.0, MAX_INPUT_DELTA_TIME),
+                false => 0.0,
+            };
+            // direction is the translation of a single input, so it can't be longer than the max speed allows
+            let direction = match command.direction.is_finite() {
+                true => command.direction.clamp_length_max(max_speed * delta_time),
+                false => Vec3::ZERO,
+            };
+            self.move_with_delta_time(framework, direction, delta_time);
+            last_sequence = Some(command.sequence);
         }
+
+        self.prediction = PredictionMode::Authoritative { owner, max_speed, last_sequence };
+        last_sequence.map(|sequence| (sequence, self.transform.position))
     }
 
-    pub fn move_controller(&mut self, framework: &mut Framework, direction: Vec3) {
+    /// Client: drops the acknowledged inputs and re-applies the rest of them if the server disagrees with the prediction.
+    pub(crate) fn reconcile(&mut self, framework: &mut Framework, last_sequence: u32, position: Vec3) {
+        let pending_inputs = match &mut self.prediction {
+            PredictionMode::Predicted { pending_inputs, .. } => pending_inputs,
+            _ => return,
+        };
+
+        let mut acknowledged_input = None;
+        while let Some(input) = pending_inputs.front() {
+            if input.command.sequence > last_sequence {
+                break;
+            }
+            acknowledged_input = pending_inputs.pop_front();
+        }
+
+        let mispredicted = match acknowledged_input {
+            Some(input) => input.predicted_position.distance(position) > RECONCILIATION_TOLERANCE,
+            // the input was already reconciled (state messages can be reordered)
+            None => false,
+        };
+        if !mispredicted {
+            return;
+        }
+
+        let inputs_to_replay: Vec<PendingInput> = pending_inputs.drain(..).collect();
+        self.set_position(framework, position, false);
+        self.set_collider_position(framework, position);
+
+        let mut replayed_inputs = VecDeque::new();
+        for input in inputs_to_replay {
+            self.move_with_delta_time(framework, input.command.direction, input.command.delta_time);
+            replayed_inputs.push_back(PendingInput {
+                command: input.command,
+                predicted_position: self.transform.position,
+            });
+        }
+
+        if let PredictionMode::Predicted { pending_inputs, .. } = &mut self.prediction {
+            *pending_inputs = replayed_inputs;
+        }
+    }
+
+    fn set_collider_position(&mut self, framework: &mut Framework, position: Vec3) {
+        let collider = framework.physics.collider_set.get_mut(self.collider);
+        if let Some(collider) = collider {
+            collider.set_position(position.into());
+        }
+    }
+
+    fn move_with_delta_time(&mut self, framework: &mut Framework, direction: Vec3, delta_time: f32) {
         let mut new_position = None;
         {
             let collider = framework.physics.collider_set.get(self.collider);
@@ -264,12 +410,9 @@ impl CharacterController {
                 let shape = collider.shape();
                 let global_transform = self.global_transform();
                 let global_position = global_transform.position;
-                let global_rotation = global_transform.rotation;
-
-                let direction = math_utils::rotate_vector(direction, global_rotation);
 
                 let movement = self.controller.move_shape(
-                    framework.delta_time().as_secs_f32(),
+                    delta_time,
                     &framework.physics.rigid_body_set,
                     &framework.physics.collider_set,
                     &framework.physics.query_pipeline,
@@ -307,21 +450,16 @@ impl CharacterController {
             }
         }
 
-        {
-            if let Some(new_position) = new_position {
-                let collider = framework.physics.collider_set.get_mut(self.collider);
-                if let Some(collider) = collider {
-                    collider.set_position(new_position.into());
-                }
-            } else {
-                debugger::error(
-                    "CharacterController's move_controller error!\nfailed to get collider",
-                );
-            }
+        if let Some(new_position) = new_position {
+            self.set_collider_position(framework, new_position);
         }
     }
 
     pub fn walk_to(&mut self, target: Vec3, speed: f32) {
+        if self.is_authoritative("walk_to") {
+            return;
+        }
+
         let movement = CharacterControllerMovement { target, speed };
         self.movement = Some(movement);
     }
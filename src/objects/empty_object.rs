@@ -108,4 +108,8 @@ impl Object for EmptyObject {
     fn object_properties(&self) -> &HashMap<String, Vec<crate::managers::systems::SystemValue>> {
         &self.object_properties
     }
+
+    fn save_state(&self) -> Option<Vec<crate::managers::systems::SystemValue>> {
+        Some(vec![])
+    }
 }
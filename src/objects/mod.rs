@@ -25,6 +25,7 @@ pub mod nav_obstacle;
 pub mod nav_object;
 pub mod navmesh;
 pub mod ray;
+pub mod serialization;
 pub mod sound_emitter;
 pub mod trigger;
 pub mod point_light;
@@ -67,6 +68,12 @@ pub trait Object: std::fmt::Debug + Downcast {
     fn set_object_properties(&mut self, properties: HashMap<String, Vec<SystemValue>>);
     fn object_properties(&self) -> &HashMap<String, Vec<SystemValue>>;
 
+    /// Type-specific state that is needed to recreate the object from a save with the constructor
+    /// registered in `objects::serialization`. Objects that return None are not saved.
+    fn save_state(&self) -> Option<Vec<SystemValue>> {
+        None
+    }
+
     fn call(&mut self, _name: &str, _args: Vec<&str>) -> Option<String> {
         println!("call function is not implemented in this object.");
         None
@@ -130,6 +137,15 @@ pub trait Object: std::fmt::Debug + Downcast {
     }
 
     fn update_transform(&mut self, framework: &mut Framework) {
+        if managers::networking::is_client() {
+            if let Some(transform) = managers::networking::interpolation::sample_transform(*self.object_id()) {
+                self.set_position(framework, transform.position, true);
+                self.set_rotation(framework, transform.rotation, true);
+                self.set_scale(framework, transform.scale, true);
+                return;
+            }
+        }
+
         if let Some(parameters) = self.body_parameters() {
             if let None = parameters.rigid_body_handle {
                 return;
@@ -254,6 +270,7 @@ pub trait Object: std::fmt::Debug + Downcast {
                         framework.physics.remove_collider_by_handle(handle);
                     }
                 }
+                managers::networking::interpolation::remove_object_tree(&**object);
                 self.children_list_mut().remove(idx);
                 return true;
             }
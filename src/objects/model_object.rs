@@ -178,6 +178,30 @@ impl Object for ModelObject {
     fn object_properties(&self) -> &HashMap<String, Vec<crate::managers::systems::SystemValue>> {
         &self.object_properties
     }
+
+    // model, texture, shader, transparency, layer, current animation, looping
+    fn save_state(&self) -> Option<Vec<crate::managers::systems::SystemValue>> {
+        use crate::managers::systems::SystemValue;
+
+        let texture_asset_id = match &self.texture_asset_id {
+            Some(texture_asset_id) => texture_asset_id.get_id().to_string(),
+            None => String::new(),
+        };
+        let layer = match self.layer {
+            RenderLayer::Layer1 => 1,
+            RenderLayer::Layer2 => 2,
+        };
+
+        Some(vec![
+            SystemValue::String(self.model_asset_id.get_id().into()),
+            SystemValue::String(texture_asset_id),
+            SystemValue::String(self.shader.get_id().into()),
+            SystemValue::Bool(self.transparent),
+            SystemValue::UInt(layer),
+            SystemValue::String(self.current_animation().unwrap_or_default()),
+            SystemValue::Bool(self.looping()),
+        ])
+    }
 }
 
 impl ModelObject {
@@ -145,4 +145,20 @@ impl Object for NavObject {
     fn object_properties(&self) -> &HashMap<String, Vec<crate::managers::systems::SystemValue>> {
         &self.object_properties
     }
+
+    // "DynamicCapsule" and radius or "StaticMesh" and model
+    fn save_state(&self) -> Option<Vec<crate::managers::systems::SystemValue>> {
+        use crate::managers::systems::SystemValue;
+
+        match &self.data {
+            NavObjectData::DynamicCapsule(radius) => Some(vec![
+                SystemValue::String("DynamicCapsule".into()),
+                SystemValue::Float(*radius),
+            ]),
+            NavObjectData::StaticMesh(model_asset_id) => Some(vec![
+                SystemValue::String("StaticMesh".into()),
+                SystemValue::String(model_asset_id.get_id().into()),
+            ]),
+        }
+    }
 }
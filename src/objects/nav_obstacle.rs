@@ -51,6 +51,17 @@ impl Object for NavObstacle {
         &self.object_properties
     }
 
+    // size
+    fn save_state(&self) -> Option<Vec<crate::managers::systems::SystemValue>> {
+        use crate::managers::systems::SystemValue;
+
+        Some(vec![
+            SystemValue::Float(self.size.x),
+            SystemValue::Float(self.size.y),
+            SystemValue::Float(self.size.z),
+        ])
+    }
+
     fn start(&mut self) {}
 
     fn update(&mut self, framework: &mut Framework) {
@@ -118,6 +118,19 @@ impl Object for PointLight {
     fn object_properties(&self) -> &HashMap<String, Vec<crate::managers::systems::SystemValue>> {
         &self.object_properties
     }
+
+    // color, attenuation
+    fn save_state(&self) -> Option<Vec<crate::managers::systems::SystemValue>> {
+        use crate::managers::systems::SystemValue;
+
+        Some(vec![
+            SystemValue::Float(self.color.x),
+            SystemValue::Float(self.color.y),
+            SystemValue::Float(self.color.z),
+            SystemValue::Float(self.attenuation.x),
+            SystemValue::Float(self.attenuation.y),
+        ])
+    }
 }
 
 impl PointLight {
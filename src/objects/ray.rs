@@ -57,6 +57,18 @@ impl Object for Ray {
         &self.object_properties
     }
 
+    // direction, mask
+    fn save_state(&self) -> Option<Vec<crate::managers::systems::SystemValue>> {
+        use crate::managers::systems::SystemValue;
+
+        Some(vec![
+            SystemValue::Float(self.direction.x),
+            SystemValue::Float(self.direction.y),
+            SystemValue::Float(self.direction.z),
+            SystemValue::UInt(self.mask.bits()),
+        ])
+    }
+
     fn start(&mut self) {}
 
     fn update(&mut self, _: &mut Framework) {}
@@ -0,0 +1,572 @@
+use std::collections::{HashMap, HashSet};
+
+use glam::{Vec2, Vec3};
+use once_cell::sync::Lazy;
+
+use super::{model_object::ModelObject, point_light::PointLight, Object, Transform};
+use crate::{
+    framework::Framework,
+    managers::{
+        debugger,
+        physics::CollisionGroups,
+        render::RenderLayer,
+        saves::OBJECT_TREES_VALUE_PREFIX,
+        systems::{self, SystemValue},
+    },
+};
+
+/// Creates an object of the registered type from the state returned by it's `Object::save_state`.
+/// Transform, properties, groups and children are restored by the caller.
+pub type ObjectConstructor = fn(&mut Framework, &str, Vec<SystemValue>) -> Result<Box<dyn Object>, String>;
+
+const OBJECT_TREE_FORMAT_VERSION: u32 = 1;
+
+static mut OBJECT_TYPES: Lazy<HashMap<String, ObjectConstructor>> = Lazy::new(|| {
+    let mut object_types: HashMap<String, ObjectConstructor> = HashMap::new();
+    object_types.insert("EmptyObject".into(), new_empty_object);
+    object_types.insert("ModelObject".into(), new_model_object);
+    object_types.insert("PointLight".into(), new_point_light);
+    object_types.insert("NavObstacle".into(), new_nav_obstacle);
+    object_types.insert("NavObject".into(), new_nav_object);
+    object_types.insert("Ray".into(), new_ray);
+    object_types
+});
+// ids of the objects that are saved with their children
+static mut PERSISTENT_OBJECTS: Lazy<HashSet<u128>> = Lazy::new(|| HashSet::new());
+// trees from the loaded save for systems that don't exist yet
+static mut PENDING_OBJECT_TREES: Lazy<HashMap<String, Vec<SystemValue>>> = Lazy::new(|| HashMap::new());
+
+/// Registers (or replaces) the constructor of the objects with this `Object::object_type`.
+pub fn register_object_type(object_type: &str, constructor: ObjectConstructor) {
+    unsafe {
+        OBJECT_TYPES.insert(object_type.into(), constructor);
+    }
+}
+
+/// Persistent objects are saved with all of their children by save_game and recreated by load_save.
+pub fn set_object_persistent(object_id: u128, is_persistent: bool) {
+    unsafe {
+        match is_persistent {
+            true => PERSISTENT_OBJECTS.insert(object_id),
+            false => PERSISTENT_OBJECTS.remove(&object_id),
+        };
+    }
+}
+
+pub fn is_object_persistent(object_id: u128) -> bool {
+    unsafe { PERSISTENT_OBJECTS.contains(&object_id) }
+}
+
+/// Returns the first object in the tree that can't be saved (it's `Object::save_state` returns None).
+pub fn find_unsaveable_object(object: &Box<dyn Object>) -> Option<&Box<dyn Object>> {
+    if object.save_state().is_none() {
+        return Some(object);
+    }
+    object.children_list().iter().find_map(find_unsaveable_object)
+}
+
+/// Builds the save values with the persistent objects of every system.
+pub fn serialize_persistent_objects() -> HashMap<String, Vec<SystemValue>> {
+    let mut object_trees = HashMap::new();
+
+    for system in systems::get_systems_iter() {
+        let mut entries = vec![SystemValue::UInt(OBJECT_TREE_FORMAT_VERSION)];
+        for object in system.objects_list() {
+            collect_persistent_objects(object, None, &mut entries);
+        }
+
+        if entries.len() > 1 {
+            object_trees.insert(OBJECT_TREES_VALUE_PREFIX.to_string() + system.system_id(), entries);
+        }
+    }
+
+    object_trees
+}
+
+/// Replaces the persistent objects of the systems with the ones from the save values.
+/// Trees of the systems that don't exist yet are restored in update() after the system is added.
+pub fn restore_persistent_objects(framework: &mut Framework, object_trees: HashMap<String, Vec<SystemValue>>) {
+    for (value_name, entries) in object_trees {
+        let system_id = match value_name.strip_prefix(OBJECT_TREES_VALUE_PREFIX) {
+            Some(system_id) => system_id.to_string(),
+            None => continue,
+        };
+
+        match systems::get_system_with_id(&system_id) {
+            Some(_) => restore_system_objects(framework, &system_id, entries),
+            None => unsafe {
+                PENDING_OBJECT_TREES.insert(system_id, entries);
+            },
+        }
+    }
+}
+
+pub fn update(framework: &mut Framework) {
+    let system_ids: Vec<String> = unsafe {
+        PENDING_OBJECT_TREES
+            .keys()
+            .filter(|system_id| systems::get_system_with_id(system_id).is_some())
+            .cloned()
+            .collect()
+    };
+
+    for system_id in system_ids {
+        if let Some(entries) = unsafe { PENDING_OBJECT_TREES.remove(&system_id) } {
+            restore_system_objects(framework, &system_id, entries);
+        }
+    }
+}
+
+pub(crate) fn object_deleted(object: &Box<dyn Object>) {
+    unsafe {
+        PERSISTENT_OBJECTS.remove(object.object_id());
+    }
+    for child in object.children_list() {
+        object_deleted(child);
+    }
+}
+
+// entry: [parent name (empty vec for root objects), object]
+fn collect_persistent_objects(object: &Box<dyn Object>, parent: Option<&str>, entries: &mut Vec<SystemValue>) {
+    if is_object_persistent(*object.object_id()) {
+        if let Some(object_value) = serialize_object(object) {
+            let parent = match parent {
+                Some(parent) => vec![SystemValue::String(parent.into())],
+                None => vec![],
+            };
+            entries.push(SystemValue::Vec(vec![SystemValue::Vec(parent), object_value]));
+        }
+        return;
+    }
+
+    for child in object.children_list() {
+        collect_persistent_objects(child, Some(object.name()), entries);
+    }
+}
+
+// [type, name, transform, properties, groups, state, children]
+fn serialize_object(object: &Box<dyn Object>) -> Option<SystemValue> {
+    let state = match object.save_state() {
+        Some(state) => state,
+        None => {
+            debugger::warn(&format!(
+                "object serialization warning!\nobject '{}' of type '{}' can't be saved and is skipped",
+                object.name(), object.object_type()
+            ));
+            return None;
+        }
+    };
+
+    let transform = object.local_transform();
+    let transform = [transform.position, transform.rotation, transform.scale]
+        .iter()
+        .flat_map(|vec| [vec.x, vec.y, vec.z])
+        .map(SystemValue::Float)
+        .collect();
+
+    let properties = object
+        .object_properties()
+        .iter()
+        .map(|(key, value)| SystemValue::Vec(vec![SystemValue::String(key.clone()), SystemValue::Vec(value.clone())]))
+        .collect();
+
+    let groups = systems::get_object_groups_with_id(*object.object_id())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|group| SystemValue::String(group.0))
+        .collect();
+
+    let children = object
+        .children_list()
+        .iter()
+        .filter_map(serialize_object)
+        .collect();
+
+    Some(SystemValue::Vec(vec![
+        SystemValue::String(object.object_type().into()),
+        SystemValue::String(object.name().into()),
+        SystemValue::Vec(transform),
+        SystemValue::Vec(properties),
+        SystemValue::Vec(groups),
+        SystemValue::Vec(state),
+        SystemValue::Vec(children),
+    ]))
+}
+
+fn restore_system_objects(framework: &mut Framework, system_id: &str, entries: Vec<SystemValue>) {
+    let mut entries = entries.into_iter();
+    match entries.next() {
+        Some(SystemValue::UInt(version)) if version <= OBJECT_TREE_FORMAT_VERSION => (),
+        _ => {
+            debugger::error(&format!(
+                "object serialization error!\nunsupported object tree of system '{}', the objects are not restored",
+                system_id
+            ));
+            return;
+        }
+    }
+
+    let system = match systems::get_system_mut_with_id(system_id) {
+        Some(system) => system,
+        None => return,
+    };
+
+    // the loaded objects replace the current persistent ones
+    let mut objects_to_delete = vec![];
+    for object in system.objects_list() {
+        collect_persistent_names(object, &mut objects_to_delete);
+    }
+
+    let entries: Vec<(Option<String>, SystemValue)> = entries.filter_map(parse_entry).collect();
+    for (_, object_value) in &entries {
+        if let Some(name) = object_name(object_value) {
+            if system.find_object(name).is_some() && !objects_to_delete.iter().any(|object| object == name) {
+                objects_to_delete.push(name.into());
+            }
+        }
+    }
+    // objects that can't be saved are not in the save, so they are kept and moved to the restored trees
+    let mut kept_objects = vec![];
+    for name in &objects_to_delete {
+        if let Some(object) = system.find_object_mut(name) {
+            take_unsaveable_children(object, &mut kept_objects);
+        }
+    }
+    for name in objects_to_delete {
+        // could be deleted with it's parent already
+        if system.find_object(&name).is_some() {
+            system.delete_object(framework, &name);
+        }
+    }
+
+    for (parent, object_value) in entries {
+        let object = match deserialize_object(framework, object_value) {
+            Ok(object) => object,
+            Err(err) => {
+                debugger::error(&format!(
+                    "object serialization error!\nfailed to restore an object of system '{}'\nerr: {}",
+                    system_id, err
+                ));
+                continue;
+            }
+        };
+        set_object_persistent(*object.object_id(), true);
+
+        match parent.and_then(|parent| system.find_object_mut(&parent)) {
+            Some(parent_object) => parent_object.add_child(object),
+            None => system.add_object(object),
+        }
+    }
+
+    // kept objects are already started, so they are not added with add_child or add_object
+    for (parent, mut object) in kept_objects {
+        match system.find_object_mut(&parent) {
+            Some(parent_object) => {
+                object.set_parent_transform(parent_object.global_transform());
+                parent_object.children_list_mut().push(object);
+            },
+            None => {
+                object.set_parent_transform(Transform::default());
+                system.objects_list_mut().push(object);
+            },
+        }
+    }
+}
+
+// unsaveable persistent objects weren't saved, so they are not replaced
+fn collect_persistent_names(object: &Box<dyn Object>, names: &mut Vec<String>) {
+    if is_object_persistent(*object.object_id()) {
+        if object.save_state().is_some() {
+            names.push(object.name().into());
+        }
+        return;
+    }
+
+    for child in object.children_list() {
+        collect_persistent_names(child, names);
+    }
+}
+
+// moves the objects that can't be saved out of the tree, with the names of their parents
+fn take_unsaveable_children(object: &mut Box<dyn Object>, taken: &mut Vec<(String, Box<dyn Object>)>) {
+    let parent_name = object.name().to_string();
+    for mut child in std::mem::take(object.children_list_mut()) {
+        if child.save_state().is_none() {
+            taken.push((parent_name.clone(), child));
+        } else {
+            take_unsaveable_children(&mut child, taken);
+            object.children_list_mut().push(child);
+        }
+    }
+}
+
+fn parse_entry(entry: SystemValue) -> Option<(Option<String>, SystemValue)> {
+    let mut entry = match entry {
+        SystemValue::Vec(entry) if entry.len() == 2 => entry.into_iter(),
+        _ => return None,
+    };
+
+    let parent = match entry.next()? {
+        SystemValue::Vec(parent) => match parent.into_iter().next() {
+            Some(SystemValue::String(parent)) => Some(parent),
+            _ => None,
+        },
+        _ => return None,
+    };
+
+    Some((parent, entry.next()?))
+}
+
+fn object_name(object_value: &SystemValue) -> Option<&str> {
+    match object_value {
+        SystemValue::Vec(fields) => match fields.get(1) {
+            Some(SystemValue::String(name)) => Some(name),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn deserialize_object(framework: &mut Framework, object_value: SystemValue) -> Result<Box<dyn Object>, String> {
+    let fields = match object_value {
+        SystemValue::Vec(fields) if fields.len() == 7 => fields,
+        _ => return Err("invalid object".into()),
+    };
+    let mut fields = fields.into_iter();
+    let object_type = string_field(fields.next(), "type")?;
+    let name = string_field(fields.next(), "name")?;
+    let transform = vec_field(fields.next(), "transform")?;
+    let properties = vec_field(fields.next(), "properties")?;
+    let groups = vec_field(fields.next(), "groups")?;
+    let state = vec_field(fields.next(), "state")?;
+    let children = vec_field(fields.next(), "children")?;
+
+    let constructor = match unsafe { OBJECT_TYPES.get(&object_type) } {
+        Some(constructor) => *constructor,
+        None => return Err(format!("object type '{}' of object '{}' is not registered", object_type, name)),
+    };
+    let mut object = constructor(framework, &name, state)
+        .map_err(|err| format!("failed to create object '{}' of type '{}': {}", name, object_type, err))?;
+
+    let transform = floats(&transform);
+    if transform.len() != 9 {
+        return Err(format!("invalid transform of object '{}'", name));
+    }
+    let transform = Transform {
+        position: Vec3::new(transform[0], transform[1], transform[2]),
+        rotation: Vec3::new(transform[3], transform[4], transform[5]),
+        scale: Vec3::new(transform[6], transform[7], transform[8]),
+    };
+    object.set_position(framework, transform.position, true);
+    object.set_rotation(framework, transform.rotation, true);
+    object.set_scale(framework, transform.scale, true);
+
+    let mut object_properties = HashMap::new();
+    for property in properties {
+        if let SystemValue::Vec(property) = property {
+            let mut property = property.into_iter();
+            if let (Some(SystemValue::String(key)), Some(SystemValue::Vec(value))) = (property.next(), property.next()) {
+                object_properties.insert(key, value);
+            }
+        }
+    }
+    object.set_object_properties(object_properties);
+
+    for group in groups {
+        if let SystemValue::String(group) = group {
+            object.add_to_group(&group);
+        }
+    }
+
+    for child in children {
+        match deserialize_object(framework, child) {
+            Ok(child) => object.add_child(child),
+            Err(err) => debugger::error(&format!(
+                "object serialization error!\nfailed to restore a child of object '{}'\nerr: {}",
+                name, err
+            )),
+        }
+    }
+
+    Ok(object)
+}
+
+fn string_field(field: Option<SystemValue>, field_name: &str) -> Result<String, String> {
+    match field {
+        Some(SystemValue::String(value)) => Ok(value),
+        _ => Err(format!("invalid {} of object", field_name)),
+    }
+}
+
+fn vec_field(field: Option<SystemValue>, field_name: &str) -> Result<Vec<SystemValue>, String> {
+    match field {
+        Some(SystemValue::Vec(value)) => Ok(value),
+        _ => Err(format!("invalid {} of object", field_name)),
+    }
+}
+
+fn floats(values: &[SystemValue]) -> Vec<f32> {
+    values
+        .iter()
+        .filter_map(|value| match value {
+            SystemValue::Float(value) => Some(*value),
+            _ => None,
+        })
+        .collect()
+}
+
+fn new_empty_object(framework: &mut Framework, name: &str, _state: Vec<SystemValue>) -> Result<Box<dyn Object>, String> {
+    Ok(Box::new(framework.new_empty_object(name)))
+}
+
+fn new_model_object(framework: &mut Framework, name: &str, state: Vec<SystemValue>) -> Result<Box<dyn Object>, String> {
+    let (model_asset_id, texture_asset_id, shader_asset_id, transparent, layer, animation, looping) = match state.as_slice() {
+        [
+            SystemValue::String(model_asset_id),
+            SystemValue::String(texture_asset_id),
+            SystemValue::String(shader_asset_id),
+            SystemValue::Bool(transparent),
+            SystemValue::UInt(layer),
+            SystemValue::String(animation),
+            SystemValue::Bool(looping),
+        ] => (model_asset_id, texture_asset_id, shader_asset_id, *transparent, *layer, animation, *looping),
+        _ => return Err("invalid state".into()),
+    };
+
+    let model_asset = match framework.get_model_asset(model_asset_id) {
+        Some(asset) => asset,
+        None => return Err(format!("model asset '{}' not found", model_asset_id)),
+    };
+    let texture_asset = match texture_asset_id.is_empty() {
+        true => None,
+        false => match framework.get_texture_asset(texture_asset_id) {
+            Some(asset) => Some(asset),
+            None => return Err(format!("texture asset '{}' not found", texture_asset_id)),
+        },
+    };
+    let shader_asset = match framework.get_shader_asset(shader_asset_id) {
+        Some(asset) => asset,
+        None => return Err(format!("shader asset '{}' not found", shader_asset_id)),
+    };
+    let layer = match layer {
+        2 => RenderLayer::Layer2,
+        _ => RenderLayer::Layer1,
+    };
+
+    let mut object: ModelObject = framework.new_model_object(name, model_asset, texture_asset, shader_asset, transparent, layer);
+    if !animation.is_empty() {
+        object.play_animation(animation.clone());
+        object.set_looping(looping);
+    }
+
+    Ok(Box::new(object))
+}
+
+fn new_point_light(_framework: &mut Framework, name: &str, state: Vec<SystemValue>) -> Result<Box<dyn Object>, String> {
+    match floats(&state).as_slice() {
+        [r, g, b, linear, quadratic] => {
+            Ok(Box::new(PointLight::new(name, Vec3::new(*r, *g, *b), Vec2::new(*linear, *quadratic))))
+        }
+        _ => Err("invalid state".into()),
+    }
+}
+
+fn new_nav_obstacle(framework: &mut Framework, name: &str, state: Vec<SystemValue>) -> Result<Box<dyn Object>, String> {
+    match floats(&state).as_slice() {
+        [x, y, z] => Ok(Box::new(framework.new_nav_obstacle(name, Vec3::new(*x, *y, *z)))),
+        _ => Err("invalid state".into()),
+    }
+}
+
+fn new_nav_object(framework: &mut Framework, name: &str, state: Vec<SystemValue>) -> Result<Box<dyn Object>, String> {
+    match state.as_slice() {
+        [SystemValue::String(kind), SystemValue::Float(radius)] if kind == "DynamicCapsule" => {
+            Ok(Box::new(framework.new_dynamic_nav_object(name, *radius)))
+        }
+        [SystemValue::String(kind), SystemValue::String(model_asset_id)] if kind == "StaticMesh" => {
+            match framework.get_model_asset(model_asset_id) {
+                Some(asset) => Ok(Box::new(framework.new_static_nav_object(name, asset))),
+                None => Err(format!("model asset '{}' not found", model_asset_id)),
+            }
+        }
+        _ => Err("invalid state".into()),
+    }
+}
+
+fn new_ray(framework: &mut Framework, name: &str, state: Vec<SystemValue>) -> Result<Box<dyn Object>, String> {
+    match state.as_slice() {
+        [SystemValue::Float(x), SystemValue::Float(y), SystemValue::Float(z), SystemValue::UInt(mask)] => {
+            Ok(Box::new(framework.new_ray(name, Vec3::new(*x, *y, *z), Some(CollisionGroups::from(*mask)))))
+        }
+        _ => Err("invalid state".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, time::Duration};
+
+    use super::*;
+    use crate::{
+        framework::DebugMode,
+        managers::{
+            assets::AssetManager, input::InputManager, navigation::NavigationManager, physics::{BodyColliderType, PhysicsManager},
+            saves::SavesManager,
+        },
+        objects::{empty_object::EmptyObject, trigger::Trigger},
+        systems::main_system::MainSystem,
+    };
+
+    fn new_framework() -> Framework {
+        Framework {
+            debug_mode: DebugMode::None,
+            delta_time: Duration::default(),
+            last_frame_systems_update_time: BTreeMap::new(),
+            system_globals: HashMap::new(),
+            resolution: Vec2::new(0.0, 0.0),
+
+            al: None,
+            input: InputManager::default(),
+            navigation: NavigationManager::new(),
+            physics: PhysicsManager::default(),
+            saves: SavesManager::default(),
+            assets: AssetManager::default(),
+            render: None,
+            ui: None,
+        }
+    }
+
+    #[test]
+    fn persistent_objects_round_trip() {
+        let mut framework = new_framework();
+        systems::add_system(Box::new(MainSystem { objects: vec![] }), &mut framework);
+        let system = systems::get_system_mut_with_id("MainSystem").unwrap();
+
+        let mut root = EmptyObject::new("persistent_root");
+        root.add_child(Box::new(EmptyObject::new("saved_child")));
+        root.set_position(&mut framework, Vec3::new(1.0, 2.0, 3.0), true);
+        system.add_object(Box::new(root));
+        assert!(framework.set_object_persistent("MainSystem", "persistent_root", true).is_ok());
+
+        // added after the tree was marked as persistent, it's not saved but it must survive the load
+        let trigger = Trigger::new(&mut framework.physics, "trigger_child", None, None, BodyColliderType::Ball(1.0));
+        let system = systems::get_system_mut_with_id("MainSystem").unwrap();
+        system.find_object_mut("persistent_root").unwrap().add_child(Box::new(trigger));
+        assert!(framework.set_object_persistent("MainSystem", "persistent_root", true).is_err());
+
+        let object_trees = serialize_persistent_objects();
+        assert!(object_trees.contains_key(&(OBJECT_TREES_VALUE_PREFIX.to_string() + "MainSystem")));
+
+        let system = systems::get_system_mut_with_id("MainSystem").unwrap();
+        system.find_object_mut("persistent_root").unwrap().set_position(&mut framework, Vec3::ZERO, true);
+        restore_persistent_objects(&mut framework, object_trees);
+
+        let system = systems::get_system_with_id("MainSystem").unwrap();
+        let root = system.find_object("persistent_root").unwrap();
+        assert_eq!(root.local_transform().position, Vec3::new(1.0, 2.0, 3.0));
+        assert!(is_object_persistent(*root.object_id()));
+        assert!(root.find_object("saved_child").is_some());
+        assert!(root.find_object("trigger_child").is_some());
+        assert_eq!(system.objects_list().len(), 1);
+    }
+}
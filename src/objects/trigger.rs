@@ -1,9 +1,10 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::{
     framework::Framework,
     managers::{
         debugger,
+        events,
         physics::{
             self, BodyColliderType, CollisionGroups, ObjectBodyParameters, PhysicsManager,
             RenderColliderType,
@@ -31,6 +32,8 @@ pub struct Trigger {
     collider_handle: ColliderHandle,
     render_collider: Option<RenderColliderType>,
     current_events: Vec<CollisionEvent>,
+    // ids of the objects that were inside of the trigger in the last update, used for the enter/exit events
+    intersecting_objects: HashSet<u128>,
     object_properties: HashMap<String, Vec<crate::managers::systems::SystemValue>>,
 }
 
@@ -90,6 +93,7 @@ impl Trigger {
             collider_handle,
             render_collider,
             current_events: Vec::new(),
+            intersecting_objects: HashSet::new(),
             object_properties: HashMap::new()
         }
     }
@@ -107,7 +111,16 @@ impl Object for Trigger {
 
     fn start(&mut self) {}
 
-    fn update(&mut self, _: &mut Framework) {}
+    fn update(&mut self, framework: &mut Framework) {
+        let intersecting_objects = self.intersecting_objects(&framework.physics);
+        for object_id in intersecting_objects.difference(&self.intersecting_objects) {
+            events::emit(None, events::TRIGGER_ENTER, self.trigger_event_payload(*object_id));
+        }
+        for object_id in self.intersecting_objects.difference(&intersecting_objects) {
+            events::emit(None, events::TRIGGER_EXIT, self.trigger_event_payload(*object_id));
+        }
+        self.intersecting_objects = intersecting_objects;
+    }
 
     fn children_list(&self) -> &Vec<Box<dyn Object>> {
         &self.children
@@ -228,6 +241,31 @@ impl Trigger {
         false
     }
 
+    // object ids are the user data of the colliders
+    fn intersecting_objects(&self, physics: &PhysicsManager) -> HashSet<u128> {
+        physics
+            .narrow_phase
+            .intersection_pairs_with(self.collider_handle)
+            .filter(|(_, _, intersecting)| *intersecting)
+            .filter_map(|(collider1, collider2, _)| {
+                let other_collider = match collider1 == self.collider_handle {
+                    true => collider2,
+                    false => collider1,
+                };
+                physics.collider_set.get(other_collider).map(|collider| collider.user_data)
+            })
+            .collect()
+    }
+
+    fn trigger_event_payload(&self, object_id: u128) -> Vec<systems::SystemValue> {
+        vec![
+            systems::SystemValue::String(systems::get_object_system_with_id(self.id).unwrap_or_default()),
+            systems::SystemValue::String(self.name.clone()),
+            systems::SystemValue::String(systems::get_object_system_with_id(object_id).unwrap_or_default()),
+            systems::SystemValue::String(systems::get_object_name_with_id(object_id).unwrap_or_default()),
+        ]
+    }
+
     pub fn mask(&self) -> &CollisionGroups {
         &self.mask
     }
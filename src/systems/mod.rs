@@ -6,11 +6,12 @@ use crate::{
     managers::{
         assets::AssetManager,
         debugger,
-        networking::{self, Message, MessageReliability, NetworkError},
+        events::Event,
+        networking::{self, rpc::RpcContext, Message, MessageReliability, NetworkError},
         render::RenderManager,
         systems::{register_object_id_name, register_object_id_system, CallList, SystemValue},
     },
-    objects::Object,
+    objects::{serialization, Object},
 };
 use egui_glium::egui_winit::egui::Context;
 
@@ -96,6 +97,11 @@ pub trait System {
     }
 
     fn delete_object(&mut self, framework: &mut Framework, name: &str) {
+        networking::interest::forget_object(self.system_id(), name);
+        if let Some(object) = self.find_object(name) {
+            networking::spawn::object_deleted(self.system_id(), object);
+            serialization::object_deleted(object);
+        }
         for (idx, object) in self.objects_list_mut().iter_mut().enumerate() {
             if object.name() == name {
                 if let Some(body_parameters) = object.body_parameters() {
@@ -107,6 +113,7 @@ pub trait System {
                     }
                 }
 
+                networking::interpolation::remove_object_tree(&**object);
                 self.objects_list_mut().remove(idx);
                 return;
             }
@@ -132,4 +139,36 @@ pub trait System {
     }
 
     fn ui_render(&mut self, _ctx: &Context) {}
+
+    /// Called before save_game writes the save, so the system can flush it's state into the global values.
+    fn before_save(&mut self, _framework: &mut Framework) {}
+
+    /// Called for the events the system is subscribed to with events::subscribe.
+    fn handle_event(&mut self, _framework: &mut Framework, _event: &Event) {}
+
+    /// Resumes the scheduled coroutines and timers of the system, called by systems::update after the system's update.
+    fn update_coroutines(&mut self, _framework: &mut Framework) {}
+
+    /// Called when a region requested with Framework::request_region is ready to be read with get_region
+    /// or failed to load (see Framework::get_region_error).
+    fn region_loaded(&mut self, _framework: &mut Framework, _group: &str, _key: &str) {}
+
+    /// Called when another machine calls an RPC registered by this system. The returned values are sent back to the caller.
+    fn handle_rpc(
+        &mut self,
+        _framework: &mut Framework,
+        context: RpcContext,
+        _args: Vec<SystemValue>,
+    ) -> Result<Vec<SystemValue>, String> {
+        Err(format!("system '{}' doesn't handle rpc '{}'", self.system_id(), context.name))
+    }
+
+    /// Called when the response to an RPC made by this system arrives (or when it times out).
+    fn rpc_response(
+        &mut self,
+        _framework: &mut Framework,
+        _call_id: u32,
+        _result: Result<Vec<SystemValue>, String>,
+    ) {
+    }
 }